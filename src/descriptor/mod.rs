@@ -27,12 +27,14 @@ use bitcoin::blockdata::{opcodes, script};
 use bitcoin::{self, Script};
 #[cfg(feature = "serde")]
 use serde::{de, ser};
-use std::fmt;
 use std::str::{self, FromStr};
+use std::{fmt, vec};
 
 use expression;
 use miniscript;
 use miniscript::context::ScriptContextError;
+use miniscript::iter::{HashLock, PkPkh, TimeLock};
+use miniscript::sanity::{Finding, Issue};
 use miniscript::{Legacy, Miniscript, Segwitv0};
 use Error;
 use MiniscriptKey;
@@ -113,6 +115,78 @@ impl<Pk: MiniscriptKey> Descriptor<Pk> {
             )),
         }
     }
+
+    /// Helper for the `iter_*` methods: the items of the descriptor's
+    /// Miniscript, in either context, or of its single key
+    fn leaves<T>(
+        &self,
+        legacy: fn(&Miniscript<Pk, Legacy>) -> Vec<T>,
+        segwit: fn(&Miniscript<Pk, Segwitv0>) -> Vec<T>,
+        key: fn(&Pk) -> Vec<T>,
+    ) -> vec::IntoIter<T> {
+        match *self {
+            Descriptor::Bare(ref ms) | Descriptor::Sh(ref ms) => legacy(ms),
+            Descriptor::Wsh(ref ms) | Descriptor::ShWsh(ref ms) => segwit(ms),
+            Descriptor::Pk(ref pk)
+            | Descriptor::Pkh(ref pk)
+            | Descriptor::Wpkh(ref pk)
+            | Descriptor::ShWpkh(ref pk) => key(pk),
+        }
+        .into_iter()
+    }
+
+    /// Iterate over the public keys of the descriptor which are not
+    /// hashed, including the key of a `pkh` or `wpkh` descriptor
+    pub fn iter_pk(&self) -> vec::IntoIter<Pk> {
+        self.leaves(
+            |ms| ms.iter_pk().collect(),
+            |ms| ms.iter_pk().collect(),
+            |pk| vec![pk.clone()],
+        )
+    }
+
+    /// Iterate over the public key hashes of the `pk_h` fragments
+    pub fn iter_pkh(&self) -> vec::IntoIter<Pk::Hash> {
+        self.leaves(
+            |ms| ms.iter_pkh().collect(),
+            |ms| ms.iter_pkh().collect(),
+            |_| vec![],
+        )
+    }
+
+    /// Iterate over all public keys of the descriptor, whether in full
+    /// or as hashes
+    pub fn iter_pk_pkh(&self) -> vec::IntoIter<PkPkh<Pk>> {
+        self.leaves(
+            |ms| ms.iter_pk_pkh().collect(),
+            |ms| ms.iter_pk_pkh().collect(),
+            |pk| vec![PkPkh::PlainPubkey(pk.clone())],
+        )
+    }
+
+    /// Iterate over the digests of the hashlocks
+    pub fn iter_hashes(&self) -> vec::IntoIter<HashLock> {
+        self.leaves(
+            |ms| ms.iter_hashes().collect(),
+            |ms| ms.iter_hashes().collect(),
+            |_| vec![],
+        )
+    }
+
+    /// Iterate over the timelocks
+    pub fn iter_timelocks(&self) -> vec::IntoIter<TimeLock> {
+        self.leaves(
+            |ms| ms.iter_timelocks().collect(),
+            |ms| ms.iter_timelocks().collect(),
+            |_| vec![],
+        )
+    }
+
+    /// Whether every public key of the descriptor, or the hash of every
+    /// hashed one, is one of `keys`, e.g. those a signer holds
+    pub fn has_only_keys_from(&self, keys: &[Pk]) -> bool {
+        self.iter_pk_pkh().all(|pk| pk.is_any_of(keys))
+    }
 }
 
 impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
@@ -393,6 +467,29 @@ impl<Pk: MiniscriptKey + ToPublicKey> Descriptor<Pk> {
             }
         }
     }
+
+    /// Check the descriptor's Miniscript for risky or non-standard
+    /// constructs, as by `Miniscript::sanity_check`. The size limit of a
+    /// P2SH redeem script does not apply to a bare script, and descriptors
+    /// with a single key have nothing to check.
+    pub fn sanity_check(&self) -> Vec<Finding> {
+        match *self {
+            Descriptor::Bare(ref ms) => ms
+                .sanity_check()
+                .into_iter()
+                .filter_map(|finding| match finding.issue {
+                    Issue::ScriptTooLarge(..) => None,
+                    _ => Some(finding),
+                })
+                .collect(),
+            Descriptor::Sh(ref ms) => ms.sanity_check(),
+            Descriptor::Wsh(ref ms) | Descriptor::ShWsh(ref ms) => ms.sanity_check(),
+            Descriptor::Pk(..)
+            | Descriptor::Pkh(..)
+            | Descriptor::Wpkh(..)
+            | Descriptor::ShWpkh(..) => vec![],
+        }
+    }
 }
 
 impl<Pk> expression::FromTree for Descriptor<Pk>
@@ -422,7 +519,8 @@ where
                 let newtop = &top.args[0];
                 match (newtop.name, newtop.args.len()) {
                     ("wsh", 1) => {
-                        let sub = Miniscript::from_tree(&newtop.args[0])?;
+                        let sub = Miniscript::from_tree(&newtop.args[0])
+                            .map_err(|e| e.located_at(&newtop.args[0]))?;
                         if sub.ty.corr.base != miniscript::types::Base::B {
                             Err(Error::NonTopLevel(format!("{:?}", sub)))
                         } else {
@@ -438,7 +536,8 @@ where
                         }
                     }
                     _ => {
-                        let sub = Miniscript::from_tree(&top.args[0])?;
+                        let sub = Miniscript::from_tree(&top.args[0])
+                            .map_err(|e| e.located_at(&top.args[0]))?;
                         if sub.ty.corr.base != miniscript::types::Base::B {
                             Err(Error::NonTopLevel(format!("{:?}", sub)))
                         } else {
@@ -448,7 +547,8 @@ where
                 }
             }
             ("wsh", 1) => {
-                let sub = Miniscript::from_tree(&top.args[0])?;
+                let sub =
+                    Miniscript::from_tree(&top.args[0]).map_err(|e| e.located_at(&top.args[0]))?;
                 if sub.ty.corr.base != miniscript::types::Base::B {
                     Err(Error::NonTopLevel(format!("{:?}", sub)))
                 } else {
@@ -456,7 +556,7 @@ where
                 }
             }
             _ => {
-                let sub = Miniscript::from_tree(&top)?;
+                let sub = Miniscript::from_tree(top).map_err(|e| e.located_at(top))?;
                 if sub.ty.corr.base != miniscript::types::Base::B {
                     Err(Error::NonTopLevel(format!("{:?}", sub)))
                 } else {
@@ -483,7 +583,7 @@ where
         }
 
         let top = expression::Tree::from_str(s)?;
-        expression::FromTree::from_tree(&top)
+        expression::from_tree_at(&top)
     }
 }
 
@@ -579,10 +679,13 @@ mod tests {
     use bitcoin::hashes::hex::FromHex;
     use bitcoin::hashes::{hash160, sha256};
     use bitcoin::{self, secp256k1, PublicKey};
+    use miniscript::context::ScriptContextError;
+    use miniscript::iter::TimeLock;
+    use miniscript::sanity::Issue;
     use miniscript::satisfy::BitcoinSig;
     use std::collections::HashMap;
     use std::str::FromStr;
-    use {Descriptor, DummyKey, Miniscript, Satisfier};
+    use {AbsLockTime, Descriptor, DummyKey, Miniscript, Satisfier};
 
     type StdDescriptor = Descriptor<PublicKey>;
     const TEST_PK: &'static str =
@@ -975,7 +1078,7 @@ mod tests {
         let descriptor = Descriptor::<bitcoin::PublicKey>::from_str("multi");
         assert_eq!(
             descriptor.unwrap_err().to_string(),
-            "unexpected «no arguments given»"
+            "unexpected «no arguments given» in «multi» at byte 0"
         )
     }
 
@@ -984,7 +1087,7 @@ mod tests {
         let descriptor = Descriptor::<bitcoin::PublicKey>::from_str("thresh");
         assert_eq!(
             descriptor.unwrap_err().to_string(),
-            "unexpected «no arguments given»"
+            "unexpected «no arguments given» in «thresh» at byte 0"
         )
     }
 
@@ -1042,4 +1145,54 @@ mod tests {
         assert_eq!(sig1, sig_a);
         assert_eq!(sig0, sig_b);
     }
+
+    #[test]
+    fn leaves() {
+        let desc =
+            Descriptor::<String>::from_str("sh(wsh(or_d(pk(A),and_v(v:pkh(B),after(500)))))")
+                .unwrap();
+        assert_eq!(desc.iter_pk().collect::<Vec<_>>(), vec!["A"]);
+        assert_eq!(desc.iter_pkh().collect::<Vec<_>>(), vec!["B"]);
+        assert_eq!(
+            desc.iter_timelocks().collect::<Vec<_>>(),
+            vec![TimeLock::After(AbsLockTime::from_consensus(500))]
+        );
+        assert_eq!(desc.iter_hashes().count(), 0);
+        assert!(desc.has_only_keys_from(&["A".to_owned(), "B".to_owned()]));
+        assert!(!desc.has_only_keys_from(&["A".to_owned()]));
+
+        let desc = Descriptor::<String>::from_str("wpkh(A)").unwrap();
+        assert_eq!(desc.iter_pk().collect::<Vec<_>>(), vec!["A"]);
+        assert_eq!(desc.iter_pkh().count(), 0);
+        assert!(desc.has_only_keys_from(&["A".to_owned()]));
+    }
+
+    #[test]
+    fn sanity_check() {
+        let desc = Descriptor::<DummyKey>::from_str("wsh(or_d(pk(),older(144)))").unwrap();
+        let findings = desc.sanity_check();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].issue, Issue::NotSafe);
+        assert_eq!(
+            findings[0].to_string(),
+            "satisfiable without a signature in «older(144)»"
+        );
+
+        // Both keys are the same dummy key
+        let desc = Descriptor::<DummyKey>::from_str("sh(or_i(pk(),c:pk_h()))").unwrap();
+        let issues: Vec<_> = desc.sanity_check().into_iter().map(|f| f.issue).collect();
+        assert_eq!(
+            issues,
+            vec![
+                Issue::ContextMalleable(ScriptContextError::MalleableOrI),
+                Issue::ContextMalleable(ScriptContextError::MalleablePkH),
+                Issue::DuplicateKey,
+            ]
+        );
+
+        assert!(Descriptor::<DummyKey>::from_str("wpkh()")
+            .unwrap()
+            .sanity_check()
+            .is_empty());
+    }
 }
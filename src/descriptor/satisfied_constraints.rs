@@ -20,6 +20,7 @@ use miniscript::ScriptContext;
 use Descriptor;
 use Terminal;
 use {error, Miniscript};
+use {AbsLockTime, RelLockTime};
 use {BitcoinSig, ToPublicKey};
 
 /// Detailed Error type for Interpreter
@@ -66,9 +67,9 @@ pub enum Error {
     /// interpreter was expecting `StackElement::Push`
     UnexpectedStackBoolean,
     /// Could not satisfy, relative locktime not met
-    RelativeLocktimeNotMet(u32),
+    RelativeLocktimeNotMet(RelLockTime),
     /// Could not satisfy, absolute locktime not met
-    AbsoluteLocktimeNotMet(u32),
+    AbsoluteLocktimeNotMet(AbsLockTime),
     /// Forward-secp related errors
     Secp(secp256k1::Error),
 }
@@ -116,13 +117,11 @@ impl fmt::Display for Error {
                 f.write_str("Expected Stack Push operation, found stack bool")
             }
             Error::RelativeLocktimeNotMet(n) => {
-                write!(f, "required relative locktime CSV of {} blocks, not met", n)
+                write!(f, "required relative locktime CSV of {}, not met", n)
+            }
+            Error::AbsoluteLocktimeNotMet(n) => {
+                write!(f, "required absolute locktime CLTV of {}, not met", n)
             }
-            Error::AbsoluteLocktimeNotMet(n) => write!(
-                f,
-                "required absolute locktime CLTV of {} blocks, not met",
-                n
-            ),
             Error::Secp(ref e) => fmt::Display::fmt(e, f),
         }
     }
@@ -191,9 +190,9 @@ pub enum SatisfiedConstraint<'desc, 'stack> {
         preimage: &'stack [u8],
     },
     ///Relative Timelock for CSV.
-    RelativeTimeLock { time: &'desc u32 },
+    RelativeTimeLock { time: &'desc RelLockTime },
     ///Absolute Timelock for CLTV.
-    AbsoluteTimeLock { time: &'desc u32 },
+    AbsoluteTimeLock { time: &'desc AbsLockTime },
 }
 
 ///This is used by the interpreter to know which evaluation state a AstemElem is.
@@ -377,7 +376,7 @@ where
                 Terminal::After(ref n) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self.stack.evaluate_after(n, self.age);
+                    let res = self.stack.evaluate_after(n, self.height);
                     if res.is_some() {
                         return res;
                     }
@@ -385,7 +384,7 @@ where
                 Terminal::Older(ref n) => {
                     debug_assert_eq!(node_state.n_evaluated, 0);
                     debug_assert_eq!(node_state.n_satisfied, 0);
-                    let res = self.stack.evaluate_older(n, self.height);
+                    let res = self.stack.evaluate_older(n, self.age);
                     if res.is_some() {
                         return res;
                     }
@@ -871,38 +870,38 @@ impl<'stack> Stack<'stack> {
     }
 
     /// Helper function to evaluate a After Node. Takes no argument from stack
-    /// `n CHECKSEQUENCEVERIFY 0NOTEQUAL` and `n CHECKSEQUENCEVERIFY`
+    /// `n CHECKLOCKTIMEVERIFY 0NOTEQUAL` and `n CHECKLOCKTIMEVERIFY`
     /// Ideally this should return int value as n: build_scriptint(t as i64)),
     /// The reason we don't need to copy the Script semantics is that
     /// Miniscript never evaluates integers and it is safe to treat them as
     /// booleans
     fn evaluate_after<'desc>(
         &mut self,
-        n: &'desc u32,
-        age: u32,
+        n: &'desc AbsLockTime,
+        height: u32,
     ) -> Option<Result<SatisfiedConstraint<'desc, 'stack>, Error>> {
-        if age >= *n {
+        if n.is_satisfied_by(height) {
             self.push(StackElement::Satisfied);
-            Some(Ok(SatisfiedConstraint::RelativeTimeLock { time: n }))
+            Some(Ok(SatisfiedConstraint::AbsoluteTimeLock { time: n }))
         } else {
             Some(Err(Error::AbsoluteLocktimeNotMet(*n)))
         }
     }
 
     /// Helper function to evaluate a Older Node. Takes no argument from stack
-    /// `n CHECKLOCKTIMEVERIFY 0NOTEQUAL` and `n CHECKLOCKTIMEVERIFY`
+    /// `n CHECKSEQUENCEVERIFY 0NOTEQUAL` and `n CHECKSEQUENCEVERIFY`
     /// Ideally this should return int value as n: build_scriptint(t as i64)),
     /// The reason we don't need to copy the Script semantics is that
     /// Miniscript never evaluates integers and it is safe to treat them as
     /// booleans
     fn evaluate_older<'desc>(
         &mut self,
-        n: &'desc u32,
-        height: u32,
+        n: &'desc RelLockTime,
+        age: u32,
     ) -> Option<Result<SatisfiedConstraint<'desc, 'stack>, Error>> {
-        if height >= *n {
+        if n.is_satisfied_by(age) {
             self.push(StackElement::Satisfied);
-            Some(Ok(SatisfiedConstraint::AbsoluteTimeLock { time: n }))
+            Some(Ok(SatisfiedConstraint::RelativeTimeLock { time: n }))
         } else {
             Some(Err(Error::RelativeLocktimeNotMet(*n)))
         }
@@ -1057,6 +1056,7 @@ mod tests {
     use Miniscript;
     use MiniscriptKey;
     use ToPublicKey;
+    use {AbsLockTime, RelLockTime};
 
     fn setup_keys_sigs(
         n: usize,
@@ -1183,7 +1183,9 @@ mod tests {
         let after_satisfied: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
         assert_eq!(
             after_satisfied.unwrap(),
-            vec![SatisfiedConstraint::RelativeTimeLock { time: &1000 }]
+            vec![SatisfiedConstraint::AbsoluteTimeLock {
+                time: &AbsLockTime::from_consensus(1000)
+            }]
         );
 
         //Check Older
@@ -1192,7 +1194,9 @@ mod tests {
         let older_satisfied: Result<Vec<SatisfiedConstraint>, Error> = constraints.collect();
         assert_eq!(
             older_satisfied.unwrap(),
-            vec![SatisfiedConstraint::AbsoluteTimeLock { time: &1000 }]
+            vec![SatisfiedConstraint::RelativeTimeLock {
+                time: &RelLockTime::from_consensus(1000)
+            }]
         );
 
         //Check Sha256
@@ -18,7 +18,7 @@
 use std::str::FromStr;
 
 use errstr;
-use Error;
+use {Error, ErrorLocation};
 
 use MAX_RECURSION_DEPTH;
 
@@ -29,21 +29,45 @@ pub struct Tree<'a> {
     pub name: &'a str,
     /// The comma-separated contents of the `(...)`, if any
     pub args: Vec<Tree<'a>>,
+    /// Byte offset of `x` in the string the tree was parsed from
+    pub position: usize,
 }
 
 pub trait FromTree: Sized {
     fn from_tree(top: &Tree) -> Result<Self, Error>;
 }
 
+/// Parse a tree, attaching the tree's position to any error which does not
+/// have a more precise one already
+pub fn from_tree_at<T: FromTree>(top: &Tree) -> Result<T, Error> {
+    T::from_tree(top).map_err(|e| e.located_at(top))
+}
+
+/// Location of the first `len` bytes of `sl`, a suffix of a string of
+/// length `total`
+fn location(total: usize, sl: &str, len: usize) -> ErrorLocation {
+    ErrorLocation {
+        position: total - sl.len(),
+        len,
+        opcode_index: None,
+        fragment: None,
+    }
+}
+
 impl<'a> Tree<'a> {
     fn from_slice(sl: &'a str) -> Result<(Tree<'a>, &'a str), Error> {
-        Self::from_slice_helper(sl, 0u32)
+        Self::from_slice_helper(sl, sl.len(), 0u32)
     }
 
-    fn from_slice_helper(mut sl: &'a str, depth: u32) -> Result<(Tree<'a>, &'a str), Error> {
+    fn from_slice_helper(
+        mut sl: &'a str,
+        total: usize,
+        depth: u32,
+    ) -> Result<(Tree<'a>, &'a str), Error> {
         if depth >= MAX_RECURSION_DEPTH {
-            return Err(Error::MaxRecursiveDepthExceeded);
+            return Err(Error::MaxRecursiveDepthExceeded.located(location(total, sl, 1)));
         }
+        let position = total - sl.len();
         enum Found {
             Nothing,
             Lparen(usize),
@@ -76,6 +100,7 @@ impl<'a> Tree<'a> {
                 Tree {
                     name: &sl[..],
                     args: vec![],
+                    position,
                 },
                 "",
             )),
@@ -84,6 +109,7 @@ impl<'a> Tree<'a> {
                 Tree {
                     name: &sl[..n],
                     args: vec![],
+                    position,
                 },
                 &sl[n..],
             )),
@@ -92,22 +118,25 @@ impl<'a> Tree<'a> {
                 let mut ret = Tree {
                     name: &sl[..n],
                     args: vec![],
+                    position,
                 };
 
                 sl = &sl[n + 1..];
                 loop {
-                    let (arg, new_sl) = Tree::from_slice_helper(sl, depth + 1)?;
+                    let (arg, new_sl) = Tree::from_slice_helper(sl, total, depth + 1)?;
                     ret.args.push(arg);
 
                     if new_sl.is_empty() {
-                        return Err(Error::ExpectedChar(')'));
+                        return Err(Error::ExpectedChar(')').located(location(total, new_sl, 1)));
                     }
 
                     sl = &new_sl[1..];
                     match new_sl.as_bytes()[0] {
                         b',' => {}
                         b')' => break,
-                        _ => return Err(Error::ExpectedChar(',')),
+                        _ => {
+                            return Err(Error::ExpectedChar(',').located(location(total, new_sl, 1)))
+                        }
                     }
                 }
                 Ok((ret, sl))
@@ -119,9 +148,9 @@ impl<'a> Tree<'a> {
     pub fn from_str(s: &'a str) -> Result<Tree<'a>, Error> {
         // Filter out non-ASCII because we byte-index strings all over the
         // place and Rust gets very upset when you splinch a string.
-        for ch in s.bytes() {
+        for (n, ch) in s.bytes().enumerate() {
             if ch > 0x7f {
-                return Err(Error::Unprintable(ch));
+                return Err(Error::Unprintable(ch).located(location(s.len(), &s[n..], 1)));
             }
         }
 
@@ -129,7 +158,7 @@ impl<'a> Tree<'a> {
         if rem.is_empty() {
             Ok(top)
         } else {
-            Err(errstr(rem))
+            Err(errstr(rem).located(location(s.len(), rem, rem.len())))
         }
     }
 }
@@ -153,10 +182,16 @@ where
     F: FnOnce(&str) -> Result<T, Err>,
     Err: ToString,
 {
+    let location = || ErrorLocation {
+        position: term.position,
+        len: term.name.len(),
+        opcode_index: None,
+        fragment: None,
+    };
     if term.args.is_empty() {
-        convert(term.name).map_err(|e| Error::Unexpected(e.to_string()))
+        convert(term.name).map_err(|e| Error::Unexpected(e.to_string()).located(location()))
     } else {
-        Err(errstr(term.name))
+        Err(errstr(term.name).located(location()))
     }
 }
 
@@ -167,7 +202,7 @@ where
     F: FnOnce(L) -> T,
 {
     if term.args.len() == 1 {
-        let left = FromTree::from_tree(&term.args[0])?;
+        let left = from_tree_at(&term.args[0])?;
         Ok(convert(left))
     } else {
         Err(errstr(term.name))
@@ -182,8 +217,8 @@ where
     F: FnOnce(L, R) -> T,
 {
     if term.args.len() == 2 {
-        let left = FromTree::from_tree(&term.args[0])?;
-        let right = FromTree::from_tree(&term.args[1])?;
+        let left = from_tree_at(&term.args[0])?;
+        let right = from_tree_at(&term.args[1])?;
         Ok(convert(left, right))
     } else {
         Err(errstr(term.name))
@@ -193,7 +228,9 @@ where
 #[cfg(test)]
 mod tests {
 
-    use super::parse_num;
+    use super::{parse_num, Tree};
+    use std::str::FromStr;
+    use {DummyKey, Miniscript, Segwitv0};
 
     #[test]
     fn test_parse_num() {
@@ -204,4 +241,29 @@ mod tests {
         assert!(parse_num("+6").is_err());
         assert!(parse_num("-6").is_err());
     }
+
+    #[test]
+    fn error_location() {
+        let tree = Tree::from_str("and_v(v:pk(),pk())").unwrap();
+        assert_eq!(tree.position, 0);
+        assert_eq!(tree.args[0].position, 6);
+        assert_eq!(tree.args[1].position, 13);
+
+        let err = Tree::from_str("and_v(v:pk(),pk()").unwrap_err();
+        assert_eq!(err.location().unwrap().position, 17);
+        assert_eq!(err.to_string(), "expected ) at byte 17");
+
+        let input = "and_v(v:pk(),or_b(pk(),pkk()))";
+        let err = Miniscript::<DummyKey, Segwitv0>::from_str(input).unwrap_err();
+        {
+            let location = err.location().unwrap();
+            assert_eq!(location.position, 23);
+            assert_eq!(location.fragment, Some("pkk".to_owned()));
+        }
+        assert_eq!(
+            err.render(input),
+            "error: unexpected «pkk(1 args) while parsing Miniscript»\n in «pkk»\n | and_v(v:pk(),or_b(pk(),pkk()))\n |                        ^^^\n"
+        );
+        assert!(err.unlocated().location().is_none());
+    }
 }
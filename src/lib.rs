@@ -97,9 +97,10 @@ pub mod expression;
 pub mod miniscript;
 pub mod policy;
 pub mod psbt;
+pub mod timelock;
 
 use std::str::FromStr;
-use std::{error, fmt, hash, str};
+use std::{cmp, error, fmt, hash, str};
 
 use bitcoin::blockdata::{opcodes, script};
 use bitcoin::hashes::{hash160, sha256, Hash};
@@ -109,6 +110,7 @@ pub use miniscript::context::{Legacy, ScriptContext, Segwitv0};
 pub use miniscript::decode::Terminal;
 pub use miniscript::satisfy::{BitcoinSig, Satisfier};
 pub use miniscript::Miniscript;
+pub use timelock::{AbsLockTime, RelLockTime};
 
 ///Public key trait which can be converted to Hash type
 pub trait MiniscriptKey:
@@ -309,9 +311,9 @@ pub enum Error {
     /// Could not satisfy a script (fragment) because of a missing signature
     MissingSig(bitcoin::PublicKey),
     /// Could not satisfy, relative locktime not met
-    RelativeLocktimeNotMet(u32),
+    RelativeLocktimeNotMet(RelLockTime),
     /// Could not satisfy, absolute locktime not met
-    AbsoluteLocktimeNotMet(u32),
+    AbsoluteLocktimeNotMet(AbsLockTime),
     /// General failure to satisfy
     CouldNotSatisfy,
     /// Typechecking failed
@@ -350,6 +352,102 @@ pub enum Error {
     MaxRecursiveDepthExceeded,
     /// Script size too large
     ScriptSizeTooLarge,
+    /// An error while parsing a string or script, with where it happened
+    Located(Box<Error>, ErrorLocation),
+}
+
+/// Where in a string or script a parse error happened
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ErrorLocation {
+    /// Byte offset of the problem in the input
+    pub position: usize,
+    /// Length in bytes of the problematic part of the input
+    pub len: usize,
+    /// Index of the offending opcode, when parsing a script
+    pub opcode_index: Option<usize>,
+    /// Name of the innermost fragment being parsed, e.g. `and_v` or
+    /// `v:pk`, when parsing a string
+    pub fragment: Option<String>,
+}
+
+impl Error {
+    /// Attach a location to the error, unless it already has one
+    fn located(self, location: ErrorLocation) -> Error {
+        match self {
+            Error::Located(..) => self,
+            e => Error::Located(Box::new(e), location),
+        }
+    }
+
+    /// Attach the location of a subexpression to the error, unless it
+    /// already has one; in that case only fill in its fragment name
+    fn located_at(self, top: &expression::Tree) -> Error {
+        match self {
+            Error::Located(e, mut location) => {
+                if location.fragment.is_none() {
+                    location.fragment = Some(top.name.to_owned());
+                }
+                Error::Located(e, location)
+            }
+            e => Error::Located(
+                Box::new(e),
+                ErrorLocation {
+                    position: top.position,
+                    len: top.name.len(),
+                    opcode_index: None,
+                    fragment: Some(top.name.to_owned()),
+                },
+            ),
+        }
+    }
+
+    /// Where in its input the error happened, if known
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match *self {
+            Error::Located(_, ref location) => Some(location),
+            _ => None,
+        }
+    }
+
+    /// The error without its location
+    pub fn unlocated(self) -> Error {
+        match self {
+            Error::Located(e, _) => *e,
+            e => e,
+        }
+    }
+
+    /// Describe the error for display under the string it came from,
+    /// with a caret line pointing at the problematic span. Long inputs
+    /// are cut down to the neighbourhood of the span.
+    pub fn render(&self, input: &str) -> String {
+        const CONTEXT: usize = 30;
+        let (message, location) = match *self {
+            Error::Located(ref e, ref location) => (e.to_string(), location),
+            ref e => return format!("error: {}\n", e),
+        };
+        let mut ret = format!("error: {}\n", message);
+        if let Some(ref fragment) = location.fragment {
+            ret.push_str(&format!(" in «{}»\n", fragment));
+        }
+        // Strings this crate parses are ASCII, so byte offsets are
+        // character offsets
+        let position = cmp::min(location.position, input.len());
+        let end = cmp::min(position + cmp::max(location.len, 1), input.len() + 1);
+        let start = position.saturating_sub(CONTEXT);
+        let stop = cmp::min(end + CONTEXT, input.len());
+        let prefix = if start > 0 { "..." } else { "" };
+        let suffix = if stop < input.len() { "..." } else { "" };
+        ret.push_str(&format!(
+            " | {}{}{}\n | {}{}\n",
+            prefix,
+            input.get(start..stop).unwrap_or(""),
+            suffix,
+            " ".repeat(prefix.len() + position - start),
+            "^".repeat(end - position),
+        ));
+        ret
+    }
 }
 
 #[doc(hidden)]
@@ -420,13 +518,11 @@ impl fmt::Display for Error {
             Error::MissingHash(ref h) => write!(f, "missing preimage of hash {}", h),
             Error::MissingSig(ref pk) => write!(f, "missing signature for key {:?}", pk),
             Error::RelativeLocktimeNotMet(n) => {
-                write!(f, "required relative locktime CSV of {} blocks, not met", n)
+                write!(f, "required relative locktime CSV of {}, not met", n)
+            }
+            Error::AbsoluteLocktimeNotMet(n) => {
+                write!(f, "required absolute locktime CLTV of {}, not met", n)
             }
-            Error::AbsoluteLocktimeNotMet(n) => write!(
-                f,
-                "required absolute locktime CLTV of {} blocks, not met",
-                n
-            ),
             Error::CouldNotSatisfy => f.write_str("could not satisfy"),
             Error::BadPubkey(ref e) => fmt::Display::fmt(e, f),
             Error::TypeCheck(ref e) => write!(f, "typecheck: {}", e),
@@ -456,6 +552,16 @@ impl fmt::Display for Error {
                 "Standardness rules imply bitcoin than {} bytes",
                 MAX_SCRIPT_SIZE
             ),
+            Error::Located(ref e, ref location) => {
+                fmt::Display::fmt(e, f)?;
+                if let Some(ref fragment) = location.fragment {
+                    write!(f, " in «{}»", fragment)?;
+                }
+                match location.opcode_index {
+                    Some(n) => write!(f, " at opcode {} (byte {})", n, location.position),
+                    None => write!(f, " at byte {}", location.position),
+                }
+            }
         }
     }
 }
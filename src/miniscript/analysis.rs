@@ -0,0 +1,469 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Best-effort Script Analysis
+//!
+//! `Miniscript::parse` rejects any script outside the Miniscript subset.
+//! The `analyze` function here instead accepts arbitrary scripts, e.g.
+//! those found on chain, and reports which parts of them are Miniscript,
+//! which opcodes are not, and which well-known non-Miniscript template
+//! the script follows, if any.
+//!
+
+use bitcoin::blockdata::{opcodes, script};
+use bitcoin::PublicKey;
+
+use super::decode;
+use super::lex::{lex, opcode_spans, Token, TokenIter};
+use super::{Miniscript, ScriptContext};
+use Error;
+
+/// Largest number of keys Miniscript's `multi` allows
+const MAX_MULTI_KEYS: usize = 20;
+
+/// Largest number of opcodes above `OP_16` a script may contain
+const MAX_OPS_PER_SCRIPT: usize = 201;
+
+/// Longest run of push opcodes in any Miniscript, that of a `multi` with
+/// the most keys: `k`, the keys and `n`
+const MAX_CONSECUTIVE_PUSHES: usize = MAX_MULTI_KEYS + 2;
+
+/// Well-known shape of a script
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Template {
+    /// The whole script is a valid Miniscript
+    Miniscript,
+    /// `OP_RETURN` followed by data: a provably unspendable output
+    OpReturn,
+    /// `k <key>... n CHECKMULTISIG` with more keys than `multi` allows
+    BareMultisig {
+        /// Number of signatures required
+        k: usize,
+        /// Keys which may sign
+        keys: Vec<PublicKey>,
+    },
+    /// A hash-time-locked contract like those of Lightning, which check a
+    /// `HASH160` preimage and a signature in different `IF` branches
+    Htlc,
+    /// None of the above
+    Unknown,
+}
+
+/// A maximal run of opcodes in a script which is a valid Miniscript of
+/// any type, e.g. a `V` expression before some unsupported opcode
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fragment<Ctx: ScriptContext> {
+    /// Byte offset of the fragment in the script
+    pub position: usize,
+    /// Index in the script of the fragment's first opcode
+    pub opcode_index: usize,
+    /// Length of the fragment in bytes
+    pub len: usize,
+    /// The fragment itself
+    pub ms: Miniscript<PublicKey, Ctx>,
+}
+
+/// An opcode which is not part of any Miniscript fragment
+#[derive(Debug)]
+pub struct Unparsed {
+    /// Byte offset of the opcode in the script
+    pub position: usize,
+    /// Index of the opcode in the script
+    pub opcode_index: usize,
+    /// The opcode and any data it pushes
+    pub bytes: Vec<u8>,
+    /// Why the opcode could not be parsed, on its own
+    pub error: Error,
+}
+
+/// Result of `analyze`
+#[derive(Debug)]
+pub struct ScriptAnalysis<Ctx: ScriptContext> {
+    /// Which template the script follows
+    pub template: Template,
+    /// Maximal Miniscript fragments of the script, in order. For a
+    /// `Template::Miniscript` this is the whole script.
+    pub fragments: Vec<Fragment<Ctx>>,
+    /// Opcodes which are not part of any fragment, in order
+    pub unparsed: Vec<Unparsed>,
+}
+
+impl<Ctx: ScriptContext> ScriptAnalysis<Ctx> {
+    /// Whether the whole script is a valid Miniscript
+    pub fn is_miniscript(&self) -> bool {
+        self.template == Template::Miniscript
+    }
+}
+
+/// Analyze an arbitrary script. Parsing never fails; rather, the script is
+/// split into maximal Miniscript fragments, greedily from its start, and
+/// the opcodes between them. The script is lexed once, and candidate
+/// fragments are bounded to a window of `W` opcodes by the consensus limit
+/// on non-push opcodes and by the longest run of pushes a Miniscript
+/// contains. From each of the `n` opcodes every end in its window may be
+/// tried, each by parsing up to `W` opcodes, so the search takes
+/// `O(n * W^2)` time. `W` is a few hundred at most, but for large scripts
+/// this can still take seconds.
+pub fn analyze<Ctx: ScriptContext>(script: &script::Script) -> ScriptAnalysis<Ctx> {
+    let bytes = script.as_bytes();
+    let ops = opcode_spans(bytes);
+
+    if let Ok(ms) = Miniscript::parse(script) {
+        return ScriptAnalysis {
+            template: Template::Miniscript,
+            fragments: vec![Fragment {
+                position: 0,
+                opcode_index: 0,
+                len: bytes.len(),
+                ms,
+            }],
+            unparsed: vec![],
+        };
+    }
+
+    // Tokens of each opcode on its own, or `None` if the lexer rejects it
+    let op_tokens: Vec<Option<Vec<Token>>> = (0..ops.len())
+        .map(|n| lex(&sub_script(bytes, &ops[n..n + 1])).ok())
+        .collect();
+
+    let mut fragments = vec![];
+    let mut unparsed = vec![];
+    let mut i = 0;
+    while i < ops.len() {
+        let window_end = fragment_window(bytes, &ops, &op_tokens, i);
+        // `window[n]` holds the tokens of opcodes `i..n`
+        let mut window = vec![];
+        let mut n_tokens = vec![0];
+        for tokens in op_tokens[i..window_end].iter().filter_map(Option::as_ref) {
+            window.extend_from_slice(tokens);
+            n_tokens.push(window.len());
+        }
+        let found = (i + 1..window_end + 1)
+            .rev()
+            .filter_map(|end| {
+                let mut iter = TokenIter::new(window[..n_tokens[end - i]].to_vec());
+                parse_tokens::<Ctx>(&mut iter).ok().map(|ms| (end, ms))
+            })
+            .next();
+        match found {
+            Some((end, ms)) => {
+                fragments.push(Fragment {
+                    position: ops[i].0,
+                    opcode_index: i,
+                    len: ops[end - 1].1 - ops[i].0,
+                    ms,
+                });
+                i = end;
+            }
+            None => {
+                let single = sub_script(bytes, &ops[i..i + 1]);
+                unparsed.push(Unparsed {
+                    position: ops[i].0,
+                    opcode_index: i,
+                    bytes: single.to_bytes(),
+                    // The error's own location is relative to `single`
+                    error: parse_fragment::<Ctx>(&single)
+                        .err()
+                        .map(Error::unlocated)
+                        .unwrap_or(Error::UnexpectedStart),
+                });
+                i += 1;
+            }
+        }
+    }
+
+    ScriptAnalysis {
+        template: classify(bytes, &ops),
+        fragments,
+        unparsed,
+    }
+}
+
+/// End of the longest run of opcodes from `start` which could be a
+/// Miniscript fragment: it must begin with an opcode some fragment begins
+/// with, every opcode must lex on its own, `OP_VERIFY` may not follow an
+/// opcode with a `VERIFY` form, and neither limit on non-push opcodes nor
+/// on consecutive pushes may be exceeded
+fn fragment_window(
+    bytes: &[u8],
+    ops: &[(usize, usize)],
+    op_tokens: &[Option<Vec<Token>>],
+    start: usize,
+) -> usize {
+    match op_tokens[start].as_ref().and_then(|tokens| tokens.first()) {
+        Some(&Token::Num(_))
+        | Some(&Token::Pubkey(_))
+        | Some(&Token::Dup)
+        | Some(&Token::Size)
+        | Some(&Token::If)
+        | Some(&Token::ToAltStack)
+        | Some(&Token::Swap) => {}
+        _ => return start,
+    }
+    let mut n_ops = 0;
+    let mut n_pushes = 0;
+    let mut end = start;
+    while end < ops.len() {
+        let tokens = match op_tokens[end] {
+            Some(ref tokens) => tokens,
+            None => break,
+        };
+        if bytes[ops[end].0] <= opcodes::all::OP_PUSHNUM_16.into_u8() {
+            n_pushes += 1;
+            if n_pushes > MAX_CONSECUTIVE_PUSHES {
+                break;
+            }
+        } else {
+            n_pushes = 0;
+            n_ops += 1;
+            if n_ops > MAX_OPS_PER_SCRIPT {
+                break;
+            }
+        }
+        if end > start && tokens[..] == [Token::Verify] {
+            match op_tokens[end - 1].as_ref().and_then(|prev| prev.last()) {
+                Some(&Token::Equal) | Some(&Token::CheckSig) | Some(&Token::CheckMultiSig) => break,
+                _ => {}
+            }
+        }
+        end += 1;
+    }
+    end
+}
+
+/// Parse a script as a Miniscript of any type, not only `B`
+fn parse_fragment<Ctx: ScriptContext>(
+    script: &script::Script,
+) -> Result<Miniscript<PublicKey, Ctx>, Error> {
+    parse_tokens(&mut TokenIter::new(lex(script)?))
+}
+
+/// Parse tokens as a Miniscript of any type, requiring all to be used
+fn parse_tokens<Ctx: ScriptContext>(
+    iter: &mut TokenIter,
+) -> Result<Miniscript<PublicKey, Ctx>, Error> {
+    let ms = decode::parse::<Ctx>(iter)?;
+    if let Some(leading) = iter.next() {
+        Err(Error::Trailing(leading.to_string()))
+    } else {
+        Ok(ms)
+    }
+}
+
+/// The opcodes in `ops` as a script of their own
+fn sub_script(bytes: &[u8], ops: &[(usize, usize)]) -> script::Script {
+    let start = ops.first().map(|op| op.0).unwrap_or(0);
+    let end = ops.last().map(|op| op.1).unwrap_or(0);
+    script::Script::from(bytes[start..end].to_vec())
+}
+
+/// Recognize the template of a script which is not a Miniscript
+fn classify(bytes: &[u8], ops: &[(usize, usize)]) -> Template {
+    let opcode = |i: usize| bytes[ops[i].0];
+    let data = |i: usize| &bytes[ops[i].0..ops[i].1];
+    if ops.is_empty() {
+        return Template::Unknown;
+    }
+    if opcode(0) == opcodes::all::OP_RETURN.into_u8() {
+        return Template::OpReturn;
+    }
+
+    let last = ops.len() - 1;
+    if ops.len() >= 4 && opcode(last) == opcodes::all::OP_CHECKMULTISIG.into_u8() {
+        let keys: Result<Vec<_>, _> = (1..last - 1)
+            .map(|i| PublicKey::from_slice(&data(i)[1..]))
+            .collect();
+        if let (Some(k), Some(n), Ok(keys)) = (push_num(data(0)), push_num(data(last - 1)), keys) {
+            if n == keys.len() && n > MAX_MULTI_KEYS && k <= n {
+                return Template::BareMultisig { k, keys };
+            }
+        }
+    }
+
+    let has_op = |op: opcodes::All| (0..ops.len()).any(|i| opcode(i) == op.into_u8());
+    let has_hashlock = (0..ops.len().saturating_sub(2)).any(|i| {
+        opcode(i) == opcodes::all::OP_HASH160.into_u8()
+            && data(i + 1).len() == 21
+            && (opcode(i + 2) == opcodes::all::OP_EQUAL.into_u8()
+                || opcode(i + 2) == opcodes::all::OP_EQUALVERIFY.into_u8())
+    });
+    let has_sig = has_op(opcodes::all::OP_CHECKSIG)
+        || has_op(opcodes::all::OP_CHECKSIGVERIFY)
+        || has_op(opcodes::all::OP_CHECKMULTISIG);
+    let has_branch = has_op(opcodes::all::OP_IF) || has_op(opcodes::all::OP_NOTIF);
+    if has_hashlock && has_sig && has_branch {
+        Template::Htlc
+    } else {
+        Template::Unknown
+    }
+}
+
+/// Value of an opcode pushing a small non-negative number
+fn push_num(op: &[u8]) -> Option<usize> {
+    match op[0] {
+        0 => Some(0),
+        n if n > 0x50 && n - 0x50 <= 16 => Some((n - 0x50) as usize),
+        n if n <= 4 => match script::read_scriptint(&op[1..]) {
+            Ok(n) if n >= 0 => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::blockdata::opcodes::all;
+    use hex_script;
+    use miniscript::tests::pubkeys;
+    use miniscript::{Legacy, Segwitv0};
+    use std::str::FromStr;
+
+    #[test]
+    fn whole_miniscript() {
+        let keys = pubkeys(2);
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_str(&format!(
+            "or_d(pk({}),and_v(v:pk({}),older(144)))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        let analysis = analyze::<Segwitv0>(&ms.encode());
+        assert!(analysis.is_miniscript());
+        assert_eq!(analysis.fragments.len(), 1);
+        assert_eq!(analysis.fragments[0].ms, ms);
+        assert!(analysis.unparsed.is_empty());
+    }
+
+    #[test]
+    fn partial_miniscript() {
+        let keys = pubkeys(1);
+        // `v:pk(A)` followed by an opcode outside Miniscript
+        let script = script::Builder::new()
+            .push_key(&keys[0])
+            .push_opcode(all::OP_CHECKSIGVERIFY)
+            .push_opcode(all::OP_DEPTH)
+            .push_int(144)
+            .push_opcode(all::OP_CSV)
+            .into_script();
+        let analysis = analyze::<Segwitv0>(&script);
+        assert_eq!(analysis.template, Template::Unknown);
+        assert_eq!(analysis.fragments.len(), 2);
+        assert_eq!(
+            analysis.fragments[0].ms.to_string(),
+            format!("v:pk({})", keys[0])
+        );
+        assert_eq!(analysis.fragments[0].position, 0);
+        assert_eq!(analysis.fragments[0].len, 35);
+        assert_eq!(analysis.fragments[1].ms.to_string(), "older(144)");
+        assert_eq!(analysis.fragments[1].opcode_index, 3);
+        assert_eq!(analysis.unparsed.len(), 1);
+        assert_eq!(analysis.unparsed[0].position, 35);
+        assert_eq!(analysis.unparsed[0].opcode_index, 2);
+        assert_eq!(analysis.unparsed[0].bytes, vec![all::OP_DEPTH.into_u8()]);
+    }
+
+    #[test]
+    fn templates() {
+        let analysis = analyze::<Legacy>(&hex_script("6a0b68656c6c6f20776f726c64"));
+        assert_eq!(analysis.template, Template::OpReturn);
+        assert!(analysis.fragments.is_empty());
+        assert_eq!(analysis.unparsed.len(), 2);
+
+        let keys = pubkeys(21);
+        let mut builder = script::Builder::new().push_int(2);
+        for key in &keys {
+            builder = builder.push_key(key);
+        }
+        let script = builder
+            .push_int(21)
+            .push_opcode(all::OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(
+            analyze::<Legacy>(&script).template,
+            Template::BareMultisig {
+                k: 2,
+                keys: keys.clone(),
+            }
+        );
+
+        // BOLT-3 offered HTLC output
+        let script = script::Builder::new()
+            .push_opcode(all::OP_DUP)
+            .push_opcode(all::OP_HASH160)
+            .push_slice(&[1; 20])
+            .push_opcode(all::OP_EQUAL)
+            .push_opcode(all::OP_IF)
+            .push_opcode(all::OP_CHECKSIG)
+            .push_opcode(all::OP_ELSE)
+            .push_key(&keys[0])
+            .push_opcode(all::OP_SWAP)
+            .push_opcode(all::OP_SIZE)
+            .push_int(32)
+            .push_opcode(all::OP_EQUAL)
+            .push_opcode(all::OP_NOTIF)
+            .push_opcode(all::OP_DROP)
+            .push_int(2)
+            .push_opcode(all::OP_SWAP)
+            .push_key(&keys[1])
+            .push_int(2)
+            .push_opcode(all::OP_CHECKMULTISIG)
+            .push_opcode(all::OP_ELSE)
+            .push_opcode(all::OP_HASH160)
+            .push_slice(&[2; 20])
+            .push_opcode(all::OP_EQUALVERIFY)
+            .push_opcode(all::OP_CHECKSIG)
+            .push_opcode(all::OP_ENDIF)
+            .push_opcode(all::OP_ENDIF)
+            .into_script();
+        let analysis = analyze::<Segwitv0>(&script);
+        assert_eq!(analysis.template, Template::Htlc);
+        assert!(!analysis.unparsed.is_empty());
+
+        assert_eq!(
+            analyze::<Segwitv0>(&hex_script("")).template,
+            Template::Unknown
+        );
+    }
+
+    #[test]
+    fn large_scripts() {
+        use std::time::{Duration, Instant};
+
+        for &op in &[
+            all::OP_PUSHNUM_1,
+            all::OP_VERIFY,
+            all::OP_SIZE,
+            all::OP_DEPTH,
+        ] {
+            let mut builder = script::Builder::new();
+            for _ in 0..2000 {
+                builder = builder.push_opcode(op);
+            }
+            let start = Instant::now();
+            let analysis = analyze::<Segwitv0>(&builder.into_script());
+            assert!(start.elapsed() < Duration::from_secs(10));
+            assert_eq!(analysis.template, Template::Unknown);
+        }
+
+        // A run of pushes too long for any fragment is still split up
+        let mut builder = script::Builder::new();
+        for _ in 0..30 {
+            builder = builder.push_int(1);
+        }
+        let analysis = analyze::<Segwitv0>(&builder.into_script());
+        assert_eq!(analysis.fragments.len(), 30);
+        assert!(analysis.unparsed.is_empty());
+    }
+}
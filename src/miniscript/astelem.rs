@@ -37,12 +37,13 @@ use Miniscript;
 use MiniscriptKey;
 use Terminal;
 use ToPublicKey;
+use {AbsLockTime, RelLockTime};
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
     /// Internal helper function for displaying wrapper types; returns
     /// a character to display before the `:` as well as a reference
     /// to the wrapped type to allow easy recursion
-    fn wrap_char(&self) -> Option<(char, &Arc<Miniscript<Pk, Ctx>>)> {
+    pub(crate) fn wrap_char(&self) -> Option<(char, &Arc<Miniscript<Pk, Ctx>>)> {
         match *self {
             Terminal::Alt(ref sub) => Some(('a', sub)),
             Terminal::Swap(ref sub) => Some(('s', sub)),
@@ -206,8 +207,8 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Debug for Terminal<Pk, Ctx> {
             match *self {
                 Terminal::PkK(ref pk) => write!(f, "pk_k({:?})", pk),
                 Terminal::PkH(ref pkh) => write!(f, "pk_h({:?})", pkh),
-                Terminal::After(t) => write!(f, "after({})", t),
-                Terminal::Older(t) => write!(f, "older({})", t),
+                Terminal::After(t) => write!(f, "after({})", t.to_consensus_u32()),
+                Terminal::Older(t) => write!(f, "older({})", t.to_consensus_u32()),
                 Terminal::Sha256(h) => write!(f, "sha256({})", h),
                 Terminal::Hash256(h) => {
                     let mut x = h.into_inner();
@@ -256,8 +257,8 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for Terminal<Pk, Ctx> {
         match *self {
             Terminal::PkK(ref pk) => write!(f, "pk_k({})", pk),
             Terminal::PkH(ref pkh) => write!(f, "pk_h({})", pkh),
-            Terminal::After(t) => write!(f, "after({})", t),
-            Terminal::Older(t) => write!(f, "older({})", t),
+            Terminal::After(t) => write!(f, "after({})", t.to_consensus_u32()),
+            Terminal::Older(t) => write!(f, "older({})", t.to_consensus_u32()),
             Terminal::Sha256(h) => write!(f, "sha256({})", h),
             Terminal::Hash256(h) => {
                 let mut x = h.into_inner();
@@ -347,7 +348,7 @@ where
     <<Pk as MiniscriptKey>::Hash as str::FromStr>::Err: ToString,
 {
     fn from_tree(top: &expression::Tree) -> Result<Arc<Terminal<Pk, Ctx>>, Error> {
-        Ok(Arc::new(expression::FromTree::from_tree(top)?))
+        Ok(Arc::new(expression::from_tree_at(top)?))
     }
 }
 
@@ -411,10 +412,10 @@ where
                 expression::terminal(&top.args[0], |x| Pk::Hash::from_str(x).map(Terminal::PkH))
             }
             ("after", 1) => expression::terminal(&top.args[0], |x| {
-                expression::parse_num(x).map(Terminal::After)
+                expression::parse_num(x).map(|x| Terminal::After(AbsLockTime::from_consensus(x)))
             }),
             ("older", 1) => expression::terminal(&top.args[0], |x| {
-                expression::parse_num(x).map(Terminal::Older)
+                expression::parse_num(x).map(|x| Terminal::Older(RelLockTime::from_consensus(x)))
             }),
             ("sha256", 1) => expression::terminal(&top.args[0], |x| {
                 sha256::Hash::from_hex(x).map(Terminal::Sha256)
@@ -447,14 +448,14 @@ where
             }
             ("and_b", 2) => expression::binary(top, Terminal::AndB),
             ("and_n", 2) => Ok(Terminal::AndOr(
-                expression::FromTree::from_tree(&top.args[0])?,
-                expression::FromTree::from_tree(&top.args[1])?,
+                expression::from_tree_at(&top.args[0])?,
+                expression::from_tree_at(&top.args[1])?,
                 Arc::new(Miniscript::from_ast(Terminal::False)?),
             )),
             ("andor", 3) => Ok(Terminal::AndOr(
-                expression::FromTree::from_tree(&top.args[0])?,
-                expression::FromTree::from_tree(&top.args[1])?,
-                expression::FromTree::from_tree(&top.args[2])?,
+                expression::from_tree_at(&top.args[0])?,
+                expression::from_tree_at(&top.args[1])?,
+                expression::from_tree_at(&top.args[2])?,
             )),
             ("or_b", 2) => expression::binary(top, Terminal::OrB),
             ("or_d", 2) => expression::binary(top, Terminal::OrD),
@@ -482,7 +483,7 @@ where
 
                 let subs: Result<Vec<Arc<Miniscript<Pk, Ctx>>>, _> = top.args[1..]
                     .iter()
-                    .map(|sub| expression::FromTree::from_tree(sub))
+                    .map(|sub| expression::from_tree_at(sub))
                     .collect();
 
                 Ok(Terminal::Thresh(k, subs?))
@@ -576,9 +577,11 @@ impl<Pk: MiniscriptKey + ToPublicKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
                 .push_slice(&Pk::hash_to_hash160(&hash)[..])
                 .push_opcode(opcodes::all::OP_EQUALVERIFY),
             Terminal::After(t) => builder
-                .push_int(t as i64)
+                .push_int(t.to_consensus_u32() as i64)
                 .push_opcode(opcodes::all::OP_CLTV),
-            Terminal::Older(t) => builder.push_int(t as i64).push_opcode(opcodes::all::OP_CSV),
+            Terminal::Older(t) => builder
+                .push_int(t.to_consensus_u32() as i64)
+                .push_opcode(opcodes::all::OP_CSV),
             Terminal::Sha256(h) => builder
                 .push_opcode(opcodes::all::OP_SIZE)
                 .push_int(32)
@@ -697,8 +700,8 @@ impl<Pk: MiniscriptKey + ToPublicKey, Ctx: ScriptContext> Terminal<Pk, Ctx> {
         match *self {
             Terminal::PkK(ref pk) => pk.serialized_len(),
             Terminal::PkH(..) => 24,
-            Terminal::After(n) => script_num_size(n as usize) + 1,
-            Terminal::Older(n) => script_num_size(n as usize) + 1,
+            Terminal::After(n) => script_num_size(n.to_consensus_u32() as usize) + 1,
+            Terminal::Older(n) => script_num_size(n.to_consensus_u32() as usize) + 1,
             Terminal::Sha256(..) => 33 + 6,
             Terminal::Hash256(..) => 33 + 6,
             Terminal::Ripemd160(..) => 21 + 6,
@@ -0,0 +1,637 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Lightning Scripts
+//!
+//! The witness scripts of Lightning commitment transactions, as specified
+//! in BOLT 3, are not Miniscripts: they select spending paths with `SWAP`
+//! and `SIZE` tricks rather than with Miniscript's disjunctions. This
+//! module recognizes the `to_local`, offered HTLC and received HTLC
+//! scripts exactly, including the `option_anchors` variants of the HTLC
+//! scripts, extracts their keys, hashes and delays, and lifts them to
+//! semantic policies.
+//!
+
+use bitcoin::blockdata::{opcodes, script};
+use bitcoin::hashes::{hash160, Hash};
+use bitcoin::PublicKey;
+
+use super::lex::{lex, Token};
+use policy::{Liftable, Semantic};
+use {AbsLockTime, RelLockTime};
+
+/// A `to_local` output, spendable by the revocation key at once or by
+/// the local node after a delay
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ToLocal {
+    /// Key of the remote node, once the commitment is revoked
+    pub revocation_key: PublicKey,
+    /// Delay before the local node may spend
+    pub to_self_delay: RelLockTime,
+    /// Key of the local node
+    pub local_delayed_key: PublicKey,
+}
+
+/// An offered HTLC output, spendable by the revocation key, by the remote
+/// node with the payment preimage, or by both nodes together through the
+/// HTLC-timeout transaction
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct OfferedHtlc {
+    /// `HASH160` of the revocation key
+    pub revocation_key_hash: hash160::Hash,
+    /// HTLC key of the remote node
+    pub remote_htlc_key: PublicKey,
+    /// HTLC key of the local node
+    pub local_htlc_key: PublicKey,
+    /// `RIPEMD160` of the payment hash, i.e. `HASH160` of the preimage
+    pub payment_hash160: hash160::Hash,
+    /// Whether this is the `option_anchors` variant, where the remote node
+    /// may only spend once the commitment transaction has one confirmation
+    pub option_anchors: bool,
+}
+
+/// A received HTLC output, spendable by the revocation key, by both nodes
+/// together with the payment preimage through the HTLC-success
+/// transaction, or by the remote node after the HTLC expires
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ReceivedHtlc {
+    /// `HASH160` of the revocation key
+    pub revocation_key_hash: hash160::Hash,
+    /// HTLC key of the remote node
+    pub remote_htlc_key: PublicKey,
+    /// HTLC key of the local node
+    pub local_htlc_key: PublicKey,
+    /// `RIPEMD160` of the payment hash, i.e. `HASH160` of the preimage
+    pub payment_hash160: hash160::Hash,
+    /// Block height or time from which the remote node may spend
+    pub cltv_expiry: AbsLockTime,
+    /// Whether this is the `option_anchors` variant, where the remote node
+    /// may only spend once the commitment transaction has one confirmation
+    pub option_anchors: bool,
+}
+
+/// Any of the BOLT 3 scripts this module recognizes
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Bolt3Script {
+    /// A `to_local` output
+    ToLocal(ToLocal),
+    /// An offered HTLC output
+    OfferedHtlc(OfferedHtlc),
+    /// A received HTLC output
+    ReceivedHtlc(ReceivedHtlc),
+}
+
+/// Cursor over the tokens of a script, each method consuming a single
+/// token of the expected kind
+struct Tokens {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Tokens {
+    fn new(script: &script::Script) -> Option<Tokens> {
+        lex(script).ok().map(|tokens| Tokens { tokens, pos: 0 })
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let ret = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        ret
+    }
+
+    fn expect(&mut self, expected: &[Token]) -> Option<()> {
+        for tok in expected {
+            if self.next()? != *tok {
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    fn key(&mut self) -> Option<PublicKey> {
+        match self.next()? {
+            Token::Pubkey(pk) => Some(pk),
+            _ => None,
+        }
+    }
+
+    fn hash160(&mut self) -> Option<hash160::Hash> {
+        match self.next()? {
+            Token::Hash20(hash) => Some(hash160::Hash::from_inner(hash)),
+            _ => None,
+        }
+    }
+
+    fn num(&mut self) -> Option<u32> {
+        match self.next()? {
+            Token::Num(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    fn end(&self) -> Option<()> {
+        if self.pos == self.tokens.len() {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// The revocation branch and the start of the remote branch common
+    /// to both HTLC scripts, returning the revocation key hash and the
+    /// remote key
+    fn htlc_prefix(&mut self) -> Option<(hash160::Hash, PublicKey)> {
+        self.expect(&[Token::Dup, Token::Hash160])?;
+        let revocation_key_hash = self.hash160()?;
+        self.expect(&[Token::Equal, Token::If, Token::CheckSig, Token::Else])?;
+        let remote_htlc_key = self.key()?;
+        self.expect(&[Token::Swap, Token::Size, Token::Num(32), Token::Equal])?;
+        Some((revocation_key_hash, remote_htlc_key))
+    }
+
+    /// The end of both HTLC scripts, returning whether it is the
+    /// `option_anchors` variant, with `1 CHECKSEQUENCEVERIFY DROP` before
+    /// the final `ENDIF`
+    fn htlc_suffix(&mut self) -> Option<bool> {
+        self.expect(&[Token::EndIf])?;
+        let option_anchors = self.tokens.get(self.pos) == Some(&Token::Num(1));
+        if option_anchors {
+            self.expect(&[Token::Num(1), Token::CheckSequenceVerify, Token::Drop])?;
+        }
+        self.expect(&[Token::EndIf])?;
+        self.end()?;
+        Some(option_anchors)
+    }
+}
+
+/// The revocation branch and the start of the remote branch common to
+/// both HTLC scripts
+fn htlc_prefix(
+    revocation_key_hash: &hash160::Hash,
+    remote_htlc_key: &PublicKey,
+) -> script::Builder {
+    script::Builder::new()
+        .push_opcode(opcodes::all::OP_DUP)
+        .push_opcode(opcodes::all::OP_HASH160)
+        .push_slice(&revocation_key_hash[..])
+        .push_opcode(opcodes::all::OP_EQUAL)
+        .push_opcode(opcodes::all::OP_IF)
+        .push_opcode(opcodes::all::OP_CHECKSIG)
+        .push_opcode(opcodes::all::OP_ELSE)
+        .push_key(remote_htlc_key)
+        .push_opcode(opcodes::all::OP_SWAP)
+        .push_opcode(opcodes::all::OP_SIZE)
+        .push_int(32)
+        .push_opcode(opcodes::all::OP_EQUAL)
+}
+
+/// The end of both HTLC scripts, as recognized by `Tokens::htlc_suffix`
+fn push_htlc_suffix(builder: script::Builder, option_anchors: bool) -> script::Builder {
+    let builder = builder.push_opcode(opcodes::all::OP_ENDIF);
+    let builder = if option_anchors {
+        builder
+            .push_int(1)
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+    } else {
+        builder
+    };
+    builder.push_opcode(opcodes::all::OP_ENDIF)
+}
+
+/// `2 SWAP <local key> 2 CHECKMULTISIG`, checking signatures of the
+/// remote key left on the stack by `htlc_prefix` and of the local key
+fn push_htlc_multisig(builder: script::Builder, local_htlc_key: &PublicKey) -> script::Builder {
+    builder
+        .push_int(2)
+        .push_opcode(opcodes::all::OP_SWAP)
+        .push_key(local_htlc_key)
+        .push_int(2)
+        .push_opcode(opcodes::all::OP_CHECKMULTISIG)
+}
+
+impl ToLocal {
+    /// Recognize a `to_local` witness script
+    pub fn from_script(script: &script::Script) -> Option<ToLocal> {
+        let mut tokens = Tokens::new(script)?;
+        tokens.expect(&[Token::If])?;
+        let revocation_key = tokens.key()?;
+        tokens.expect(&[Token::Else])?;
+        let to_self_delay = RelLockTime::from_consensus(tokens.num()?);
+        tokens.expect(&[Token::CheckSequenceVerify, Token::Drop])?;
+        let local_delayed_key = tokens.key()?;
+        tokens.expect(&[Token::EndIf, Token::CheckSig])?;
+        tokens.end()?;
+        Some(ToLocal {
+            revocation_key,
+            to_self_delay,
+            local_delayed_key,
+        })
+    }
+
+    /// Encode as a witness script
+    pub fn encode(&self) -> script::Script {
+        script::Builder::new()
+            .push_opcode(opcodes::all::OP_IF)
+            .push_key(&self.revocation_key)
+            .push_opcode(opcodes::all::OP_ELSE)
+            .push_int(self.to_self_delay.to_consensus_u32() as i64)
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_key(&self.local_delayed_key)
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script()
+    }
+}
+
+impl OfferedHtlc {
+    /// Recognize an offered HTLC witness script
+    pub fn from_script(script: &script::Script) -> Option<OfferedHtlc> {
+        let mut tokens = Tokens::new(script)?;
+        let (revocation_key_hash, remote_htlc_key) = tokens.htlc_prefix()?;
+        tokens.expect(&[Token::NotIf, Token::Drop, Token::Num(2), Token::Swap])?;
+        let local_htlc_key = tokens.key()?;
+        tokens.expect(&[
+            Token::Num(2),
+            Token::CheckMultiSig,
+            Token::Else,
+            Token::Hash160,
+        ])?;
+        let payment_hash160 = tokens.hash160()?;
+        tokens.expect(&[Token::Equal, Token::Verify, Token::CheckSig])?;
+        let option_anchors = tokens.htlc_suffix()?;
+        Some(OfferedHtlc {
+            revocation_key_hash,
+            remote_htlc_key,
+            local_htlc_key,
+            payment_hash160,
+            option_anchors,
+        })
+    }
+
+    /// Encode as a witness script
+    pub fn encode(&self) -> script::Script {
+        let builder = htlc_prefix(&self.revocation_key_hash, &self.remote_htlc_key)
+            .push_opcode(opcodes::all::OP_NOTIF)
+            .push_opcode(opcodes::all::OP_DROP);
+        let builder = push_htlc_multisig(builder, &self.local_htlc_key)
+            .push_opcode(opcodes::all::OP_ELSE)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(&self.payment_hash160[..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY)
+            .push_opcode(opcodes::all::OP_CHECKSIG);
+        push_htlc_suffix(builder, self.option_anchors).into_script()
+    }
+}
+
+impl ReceivedHtlc {
+    /// Recognize a received HTLC witness script
+    pub fn from_script(script: &script::Script) -> Option<ReceivedHtlc> {
+        let mut tokens = Tokens::new(script)?;
+        let (revocation_key_hash, remote_htlc_key) = tokens.htlc_prefix()?;
+        tokens.expect(&[Token::If, Token::Hash160])?;
+        let payment_hash160 = tokens.hash160()?;
+        tokens.expect(&[Token::Equal, Token::Verify, Token::Num(2), Token::Swap])?;
+        let local_htlc_key = tokens.key()?;
+        tokens.expect(&[
+            Token::Num(2),
+            Token::CheckMultiSig,
+            Token::Else,
+            Token::Drop,
+        ])?;
+        let cltv_expiry = AbsLockTime::from_consensus(tokens.num()?);
+        tokens.expect(&[Token::CheckLockTimeVerify, Token::Drop, Token::CheckSig])?;
+        let option_anchors = tokens.htlc_suffix()?;
+        Some(ReceivedHtlc {
+            revocation_key_hash,
+            remote_htlc_key,
+            local_htlc_key,
+            payment_hash160,
+            cltv_expiry,
+            option_anchors,
+        })
+    }
+
+    /// Encode as a witness script
+    pub fn encode(&self) -> script::Script {
+        let builder = htlc_prefix(&self.revocation_key_hash, &self.remote_htlc_key)
+            .push_opcode(opcodes::all::OP_IF)
+            .push_opcode(opcodes::all::OP_HASH160)
+            .push_slice(&self.payment_hash160[..])
+            .push_opcode(opcodes::all::OP_EQUALVERIFY);
+        let builder = push_htlc_multisig(builder, &self.local_htlc_key)
+            .push_opcode(opcodes::all::OP_ELSE)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_int(self.cltv_expiry.to_consensus_u32() as i64)
+            .push_opcode(opcodes::all::OP_CLTV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_opcode(opcodes::all::OP_CHECKSIG);
+        push_htlc_suffix(builder, self.option_anchors).into_script()
+    }
+}
+
+impl Bolt3Script {
+    /// Recognize any of the BOLT 3 witness scripts
+    pub fn from_script(script: &script::Script) -> Option<Bolt3Script> {
+        ToLocal::from_script(script)
+            .map(Bolt3Script::ToLocal)
+            .or_else(|| OfferedHtlc::from_script(script).map(Bolt3Script::OfferedHtlc))
+            .or_else(|| ReceivedHtlc::from_script(script).map(Bolt3Script::ReceivedHtlc))
+    }
+
+    /// Encode as a witness script
+    pub fn encode(&self) -> script::Script {
+        match *self {
+            Bolt3Script::ToLocal(ref s) => s.encode(),
+            Bolt3Script::OfferedHtlc(ref s) => s.encode(),
+            Bolt3Script::ReceivedHtlc(ref s) => s.encode(),
+        }
+    }
+}
+
+/// The branches of an HTLC script other than the revocation one, which
+/// under `option_anchors` also need a confirmed commitment transaction
+fn lift_htlc_remote(branches: Semantic<PublicKey>, option_anchors: bool) -> Semantic<PublicKey> {
+    if option_anchors {
+        Semantic::And(vec![Semantic::Older(RelLockTime::from_height(1)), branches])
+    } else {
+        branches
+    }
+}
+
+/// Signatures of both HTLC keys, as checked by `push_htlc_multisig`
+fn lift_htlc_multisig(remote: &PublicKey, local: &PublicKey) -> Semantic<PublicKey> {
+    Semantic::Threshold(2, vec![Semantic::Key(*remote), Semantic::Key(*local)])
+}
+
+impl Liftable<PublicKey> for ToLocal {
+    fn lift(&self) -> Semantic<PublicKey> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<PublicKey> {
+        Semantic::Or(vec![
+            Semantic::Key(self.revocation_key),
+            Semantic::And(vec![
+                Semantic::Older(self.to_self_delay),
+                Semantic::Key(self.local_delayed_key),
+            ]),
+        ])
+    }
+}
+
+impl Liftable<PublicKey> for OfferedHtlc {
+    fn lift(&self) -> Semantic<PublicKey> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<PublicKey> {
+        Semantic::Or(vec![
+            Semantic::KeyHash(self.revocation_key_hash),
+            lift_htlc_remote(
+                Semantic::Or(vec![
+                    lift_htlc_multisig(&self.remote_htlc_key, &self.local_htlc_key),
+                    Semantic::And(vec![
+                        Semantic::Hash160(self.payment_hash160),
+                        Semantic::Key(self.remote_htlc_key),
+                    ]),
+                ]),
+                self.option_anchors,
+            ),
+        ])
+    }
+}
+
+impl Liftable<PublicKey> for ReceivedHtlc {
+    fn lift(&self) -> Semantic<PublicKey> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<PublicKey> {
+        Semantic::Or(vec![
+            Semantic::KeyHash(self.revocation_key_hash),
+            lift_htlc_remote(
+                Semantic::Or(vec![
+                    Semantic::And(vec![
+                        Semantic::Hash160(self.payment_hash160),
+                        lift_htlc_multisig(&self.remote_htlc_key, &self.local_htlc_key),
+                    ]),
+                    Semantic::And(vec![
+                        Semantic::After(self.cltv_expiry),
+                        Semantic::Key(self.remote_htlc_key),
+                    ]),
+                ]),
+                self.option_anchors,
+            ),
+        ])
+    }
+}
+
+impl Liftable<PublicKey> for Bolt3Script {
+    fn lift(&self) -> Semantic<PublicKey> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<PublicKey> {
+        match *self {
+            Bolt3Script::ToLocal(ref s) => s.lift_keys(),
+            Bolt3Script::OfferedHtlc(ref s) => s.lift_keys(),
+            Bolt3Script::ReceivedHtlc(ref s) => s.lift_keys(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::{ripemd160, sha256};
+    use miniscript::analysis::{self, Template};
+    use miniscript::tests::pubkeys;
+    use miniscript::Segwitv0;
+    use MiniscriptKey;
+
+    #[test]
+    fn to_local() {
+        let keys = pubkeys(2);
+        let script = script::Builder::new()
+            .push_opcode(opcodes::all::OP_IF)
+            .push_key(&keys[0])
+            .push_opcode(opcodes::all::OP_ELSE)
+            .push_int(144)
+            .push_opcode(opcodes::all::OP_CSV)
+            .push_opcode(opcodes::all::OP_DROP)
+            .push_key(&keys[1])
+            .push_opcode(opcodes::all::OP_ENDIF)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .into_script();
+        let to_local = ToLocal::from_script(&script).unwrap();
+        assert_eq!(to_local.revocation_key, keys[0]);
+        assert_eq!(to_local.to_self_delay, RelLockTime::from_height(144));
+        assert_eq!(to_local.local_delayed_key, keys[1]);
+        assert_eq!(to_local.encode(), script);
+        assert_eq!(
+            Bolt3Script::from_script(&script),
+            Some(Bolt3Script::ToLocal(to_local))
+        );
+        assert_eq!(
+            to_local.lift_keys().to_string(),
+            format!("or(pk({}),and(older(144),pk({})))", keys[0], keys[1])
+        );
+
+        // Anything else is not a `to_local`, even if it is close
+        let mut bytes = script.to_bytes();
+        bytes.pop();
+        bytes.push(opcodes::all::OP_CHECKSIGVERIFY.into_u8());
+        assert_eq!(Bolt3Script::from_script(&bytes.into()), None);
+    }
+
+    #[test]
+    fn htlcs() {
+        let keys = pubkeys(3);
+        let payment_hash = sha256::Hash::hash(&[1; 32]);
+        let payment_hash160 =
+            hash160::Hash::from_inner(ripemd160::Hash::hash(&payment_hash[..]).into_inner());
+        assert_eq!(payment_hash160, hash160::Hash::hash(&[1; 32]));
+
+        let offered = OfferedHtlc {
+            revocation_key_hash: keys[0].to_pubkeyhash(),
+            remote_htlc_key: keys[1],
+            local_htlc_key: keys[2],
+            payment_hash160,
+            option_anchors: false,
+        };
+        let script = offered.encode();
+        assert_eq!(
+            Bolt3Script::from_script(&script),
+            Some(Bolt3Script::OfferedHtlc(offered))
+        );
+        // Offered HTLCs were already recognized, without their contents
+        assert_eq!(
+            analysis::analyze::<Segwitv0>(&script).template,
+            Template::Htlc
+        );
+        assert_eq!(
+            offered.lift_keys().to_string(),
+            format!(
+                "or(pkh({}),or(thresh(2,pk({}),pk({})),and(hash160({}),pk({}))))",
+                keys[0].to_pubkeyhash(),
+                keys[1],
+                keys[2],
+                payment_hash160,
+                keys[1]
+            )
+        );
+        assert!(offered
+            .lift()
+            .is_equivalent(&Bolt3Script::OfferedHtlc(offered).lift())
+            .unwrap());
+
+        let received = ReceivedHtlc {
+            revocation_key_hash: keys[0].to_pubkeyhash(),
+            remote_htlc_key: keys[1],
+            local_htlc_key: keys[2],
+            payment_hash160,
+            cltv_expiry: AbsLockTime::from_consensus(600_000),
+            option_anchors: false,
+        };
+        let script = received.encode();
+        assert_eq!(
+            Bolt3Script::from_script(&script),
+            Some(Bolt3Script::ReceivedHtlc(received))
+        );
+        assert_eq!(OfferedHtlc::from_script(&script), None);
+        assert_eq!(
+            received.lift().to_string(),
+            format!(
+                "or(pkh({}),or(and(hash160({}),thresh(2,pkh({}),pkh({}))),and(after(600000),pkh({}))))",
+                keys[0].to_pubkeyhash(),
+                payment_hash160,
+                keys[1].to_pubkeyhash(),
+                keys[2].to_pubkeyhash(),
+                keys[1].to_pubkeyhash()
+            )
+        );
+    }
+    #[test]
+    fn anchor_htlcs() {
+        let keys = pubkeys(3);
+        let payment_hash160 = hash160::Hash::hash(&[1; 32]);
+        let offered = OfferedHtlc {
+            revocation_key_hash: keys[0].to_pubkeyhash(),
+            remote_htlc_key: keys[1],
+            local_htlc_key: keys[2],
+            payment_hash160,
+            option_anchors: true,
+        };
+        let script = offered.encode();
+        let legacy = OfferedHtlc {
+            option_anchors: false,
+            ..offered
+        }
+        .encode();
+        // `1 CHECKSEQUENCEVERIFY DROP` before the final `ENDIF`
+        assert_eq!(script.len(), legacy.len() + 3);
+        assert_eq!(
+            script[script.len() - 4..],
+            [
+                opcodes::all::OP_PUSHNUM_1.into_u8(),
+                opcodes::all::OP_CSV.into_u8(),
+                opcodes::all::OP_DROP.into_u8(),
+                opcodes::all::OP_ENDIF.into_u8()
+            ]
+        );
+        assert_eq!(
+            Bolt3Script::from_script(&script),
+            Some(Bolt3Script::OfferedHtlc(offered))
+        );
+        assert_eq!(
+            offered.lift().to_string(),
+            format!(
+                "or(pkh({}),and(older(1),or(thresh(2,pkh({}),pkh({})),and(hash160({}),pkh({})))))",
+                keys[0].to_pubkeyhash(),
+                keys[1].to_pubkeyhash(),
+                keys[2].to_pubkeyhash(),
+                payment_hash160,
+                keys[1].to_pubkeyhash()
+            )
+        );
+
+        let received = ReceivedHtlc {
+            revocation_key_hash: keys[0].to_pubkeyhash(),
+            remote_htlc_key: keys[1],
+            local_htlc_key: keys[2],
+            payment_hash160,
+            cltv_expiry: AbsLockTime::from_consensus(600_000),
+            option_anchors: true,
+        };
+        let script = received.encode();
+        assert_eq!(
+            Bolt3Script::from_script(&script),
+            Some(Bolt3Script::ReceivedHtlc(received))
+        );
+        // Until the commitment transaction confirms, only the revocation
+        // key may spend
+        assert_eq!(
+            received.lift().at_age(0),
+            Semantic::KeyHash(keys[0].to_pubkeyhash())
+        );
+
+        // Any other delay is not part of BOLT 3
+        let mut bytes = script.to_bytes();
+        let n = bytes.len();
+        bytes[n - 4] = opcodes::all::OP_PUSHNUM_2.into_u8();
+        assert_eq!(Bolt3Script::from_script(&bytes.into()), None);
+    }
+}
@@ -71,6 +71,15 @@ pub trait ScriptContext:
     fn check_frag_validity<Pk: MiniscriptKey, Ctx: ScriptContext>(
         _frag: &Terminal<Pk, Ctx>,
     ) -> Result<(), ScriptContextError>;
+
+    /// The largest script which standardness rules allow spending under
+    /// this context: a P2SH redeem script must fit in a single push,
+    /// and a P2WSH witness script is limited by policy. Defaults to the
+    /// latter.
+    fn max_standard_script_size() -> usize {
+        // MAX_STANDARD_P2WSH_SCRIPT_SIZE
+        3600
+    }
 }
 
 /// Legacy ScriptContext
@@ -94,6 +103,11 @@ impl ScriptContext for Legacy {
     ) -> Result<(), ScriptContextError> {
         Ok(())
     }
+
+    fn max_standard_script_size() -> usize {
+        // MAX_SCRIPT_ELEMENT_SIZE
+        520
+    }
 }
 
 /// Segwitv0 ScriptContext
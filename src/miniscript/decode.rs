@@ -29,6 +29,7 @@ use miniscript::ScriptContext;
 use std::sync::Arc;
 use Error;
 use MiniscriptKey;
+use {AbsLockTime, RelLockTime};
 
 fn return_none<T>(_: usize) -> Option<T> {
     None
@@ -73,10 +74,10 @@ pub enum Terminal<Pk: MiniscriptKey, Ctx: ScriptContext> {
     /// `DUP HASH160 <keyhash> EQUALVERIFY`
     PkH(Pk::Hash),
     // timelocks
-    /// `n CHECKSEQUENCEVERIFY`
-    After(u32),
     /// `n CHECKLOCKTIMEVERIFY`
-    Older(u32),
+    After(AbsLockTime),
+    /// `n CHECKSEQUENCEVERIFY`
+    Older(RelLockTime),
     // hashlocks
     /// `SIZE 32 EQUALVERIFY SHA256 <hash> EQUAL`
     Sha256(sha256::Hash),
@@ -211,10 +212,46 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> TerminalStack<Pk, Ctx> {
 }
 
 /// Parse a script fragment into an `Terminal`
-#[allow(unreachable_patterns)]
 pub fn parse<Ctx: ScriptContext>(
     tokens: &mut TokenIter,
 ) -> Result<Miniscript<bitcoin::PublicKey, Ctx>, Error> {
+    parse_with_keys(tokens, &mut |pk| Ok(*pk), &mut |pkh| Ok(*pkh))
+}
+
+/// Parse a script fragment into an `Terminal` with keys of any type,
+/// decoding each public key pushed by the script with `decodefpk`, and
+/// each public key hash (of `pk_h`) with `decodefpkh`
+///
+/// Errors are located at the last token read before they were found.
+pub fn parse_with_keys<Pk, Ctx, FPk, FPkh>(
+    tokens: &mut TokenIter,
+    decodefpk: &mut FPk,
+    decodefpkh: &mut FPkh,
+) -> Result<Miniscript<Pk, Ctx>, Error>
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+    FPk: FnMut(&bitcoin::PublicKey) -> Result<Pk, Error>,
+    FPkh: FnMut(&hash160::Hash) -> Result<Pk::Hash, Error>,
+{
+    parse_tokens(tokens, decodefpk, decodefpkh).map_err(|e| match tokens.location() {
+        Some(loc) => e.located(loc.clone()),
+        None => e,
+    })
+}
+
+#[allow(unreachable_patterns)]
+fn parse_tokens<Pk, Ctx, FPk, FPkh>(
+    tokens: &mut TokenIter,
+    decodefpk: &mut FPk,
+    decodefpkh: &mut FPkh,
+) -> Result<Miniscript<Pk, Ctx>, Error>
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+    FPk: FnMut(&bitcoin::PublicKey) -> Result<Pk, Error>,
+    FPkh: FnMut(&hash160::Hash) -> Result<Pk::Hash, Error>,
+{
     let mut non_term = Vec::with_capacity(tokens.len());
     let mut term = TerminalStack(Vec::with_capacity(tokens.len()));
 
@@ -227,7 +264,7 @@ pub fn parse<Ctx: ScriptContext>(
                 match_token!(
                     tokens,
                     // pubkey
-                    Tk::Pubkey(pk) => term.reduce0(Terminal::PkK(pk))?,
+                    Tk::Pubkey(pk) => term.reduce0(Terminal::PkK(decodefpk(&pk)?))?,
                     // checksig
                     Tk::CheckSig => {
                         non_term.push(NonTerm::Check);
@@ -240,7 +277,7 @@ pub fn parse<Ctx: ScriptContext>(
                             tokens,
                             Tk::Hash20(hash), Tk::Hash160, Tk::Dup => {
                                 term.reduce0(Terminal::PkH(
-                                    hash160::Hash::from_inner(hash)
+                                    decodefpkh(&hash160::Hash::from_inner(hash))?
                                 ))?
                             },
                             Tk::Hash32(hash), Tk::Sha256, Tk::Verify, Tk::Equal, Tk::Num(32), Tk::Size => {
@@ -262,9 +299,9 @@ pub fn parse<Ctx: ScriptContext>(
                     },
                     // timelocks
                     Tk::CheckSequenceVerify, Tk::Num(n)
-                        => term.reduce0(Terminal::Older(n))?,
+                        => term.reduce0(Terminal::Older(RelLockTime::from_consensus(n)))?,
                     Tk::CheckLockTimeVerify, Tk::Num(n)
-                        => term.reduce0(Terminal::After(n))?,
+                        => term.reduce0(Terminal::After(AbsLockTime::from_consensus(n)))?,
                     // hashlocks
                     Tk::Equal => match_token!(
                         tokens,
@@ -351,7 +388,7 @@ pub fn parse<Ctx: ScriptContext>(
                         for _ in 0..n {
                             match_token!(
                                 tokens,
-                                Tk::Pubkey(pk) => keys.push(pk),
+                                Tk::Pubkey(pk) => keys.push(decodefpk(&pk)?),
                             );
                         }
                         let k = match_token!(
@@ -0,0 +1,455 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Tree Traversal
+//!
+//! Iterators over the nodes of a Miniscript and over the keys, hashes and
+//! timelocks in its leaves, and bottom-up folds and rewrites of it, so
+//! that code walking a Miniscript need not match on every variant of
+//! `Terminal` just to recurse into its children.
+//!
+
+use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d};
+use std::sync::Arc;
+
+use super::decode::Terminal;
+use super::{Miniscript, ScriptContext};
+use {AbsLockTime, RelLockTime};
+use {Error, MiniscriptKey};
+
+/// A node of a Miniscript, as yielded by its iterators
+pub struct Node<'a, Pk: MiniscriptKey + 'a, Ctx: ScriptContext + 'a> {
+    /// The node itself
+    pub ms: &'a Miniscript<Pk, Ctx>,
+    /// Indices, in `branches`, of the children leading from the root of
+    /// the iterated Miniscript to the node; empty for the root itself
+    pub path: Vec<usize>,
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Node<'a, Pk, Ctx> {
+    /// Depth of the node in the tree, the root having depth 0
+    pub fn depth(&self) -> usize {
+        self.path.len()
+    }
+}
+
+/// Iterator over the nodes of a Miniscript, created by `Miniscript::iter`
+/// or `Miniscript::iter_post_order`
+pub struct Iter<'a, Pk: MiniscriptKey + 'a, Ctx: ScriptContext + 'a> {
+    /// Nodes still to visit, the next one last, each with whether its
+    /// children have already been pushed (only used in post-order)
+    stack: Vec<(Node<'a, Pk, Ctx>, bool)>,
+    post_order: bool,
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iter<'a, Pk, Ctx> {
+    fn new(ms: &'a Miniscript<Pk, Ctx>, post_order: bool) -> Iter<'a, Pk, Ctx> {
+        Iter {
+            stack: vec![(Node { ms, path: vec![] }, false)],
+            post_order,
+        }
+    }
+
+    /// Push the children of `node` so that they are visited in order
+    fn push_branches(&mut self, node: &Node<'a, Pk, Ctx>) {
+        for (i, sub) in node.ms.branches().into_iter().enumerate().rev() {
+            let mut path = node.path.clone();
+            path.push(i);
+            self.stack.push((Node { ms: sub, path }, false));
+        }
+    }
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext> Iterator for Iter<'a, Pk, Ctx> {
+    type Item = Node<'a, Pk, Ctx>;
+
+    fn next(&mut self) -> Option<Node<'a, Pk, Ctx>> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+            if !self.post_order {
+                self.push_branches(&node);
+                return Some(node);
+            }
+            if expanded || node.ms.branches().is_empty() {
+                return Some(node);
+            }
+            let path = node.path.clone();
+            let ms = node.ms;
+            self.stack.push((node, true));
+            self.push_branches(&Node { ms, path });
+        }
+    }
+}
+
+/// A public key of a Miniscript, in full or only as a hash (for `pk_h`)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PkPkh<Pk: MiniscriptKey> {
+    /// A key which is in the Miniscript in full
+    PlainPubkey(Pk),
+    /// A key of which the Miniscript only has the hash
+    HashedPubkey(Pk::Hash),
+}
+
+impl<Pk: MiniscriptKey> PkPkh<Pk> {
+    /// Whether the key is one of `keys`, or the hash of one of them
+    pub fn is_any_of(&self, keys: &[Pk]) -> bool {
+        match *self {
+            PkPkh::PlainPubkey(ref pk) => keys.contains(pk),
+            PkPkh::HashedPubkey(ref pkh) => keys.iter().any(|pk| pk.to_pubkeyhash() == *pkh),
+        }
+    }
+}
+
+/// The digest of a hashlock, by hash function
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum HashLock {
+    /// The digest of a `sha256` fragment
+    Sha256(sha256::Hash),
+    /// The digest of a `hash256` fragment
+    Hash256(sha256d::Hash),
+    /// The digest of a `ripemd160` fragment
+    Ripemd160(ripemd160::Hash),
+    /// The digest of a `hash160` fragment
+    Hash160(hash160::Hash),
+}
+
+/// A timelock of an `after` or `older` fragment
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TimeLock {
+    /// The lock time of an `after` fragment
+    After(AbsLockTime),
+    /// The lock time of an `older` fragment
+    Older(RelLockTime),
+}
+
+/// Iterator over the keys, hashes or timelocks in the leaves of a
+/// Miniscript, in script order, created by `Miniscript::iter_pk` and
+/// similar methods
+pub struct LeafIter<'a, Pk: MiniscriptKey + 'a, Ctx: ScriptContext + 'a, T> {
+    nodes: Iter<'a, Pk, Ctx>,
+    /// Items of the current node not yet returned, the next one last
+    pending: Vec<T>,
+    /// Items of each node
+    extract: fn(&Terminal<Pk, Ctx>) -> Vec<T>,
+}
+
+impl<'a, Pk: MiniscriptKey, Ctx: ScriptContext, T> Iterator for LeafIter<'a, Pk, Ctx, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.pending.pop() {
+                return Some(item);
+            }
+            let node = self.nodes.next()?;
+            self.pending = (self.extract)(&node.ms.node);
+            self.pending.reverse();
+        }
+    }
+}
+
+fn leaf_pk<Pk: MiniscriptKey, Ctx: ScriptContext>(node: &Terminal<Pk, Ctx>) -> Vec<Pk> {
+    match *node {
+        Terminal::PkK(ref pk) => vec![pk.clone()],
+        Terminal::Multi(_, ref keys) => keys.clone(),
+        _ => vec![],
+    }
+}
+
+fn leaf_pkh<Pk: MiniscriptKey, Ctx: ScriptContext>(node: &Terminal<Pk, Ctx>) -> Vec<Pk::Hash> {
+    match *node {
+        Terminal::PkH(ref pkh) => vec![pkh.clone()],
+        _ => vec![],
+    }
+}
+
+fn leaf_pk_pkh<Pk: MiniscriptKey, Ctx: ScriptContext>(node: &Terminal<Pk, Ctx>) -> Vec<PkPkh<Pk>> {
+    match *node {
+        Terminal::PkH(ref pkh) => vec![PkPkh::HashedPubkey(pkh.clone())],
+        _ => leaf_pk(node).into_iter().map(PkPkh::PlainPubkey).collect(),
+    }
+}
+
+fn leaf_hash<Pk: MiniscriptKey, Ctx: ScriptContext>(node: &Terminal<Pk, Ctx>) -> Vec<HashLock> {
+    match *node {
+        Terminal::Sha256(h) => vec![HashLock::Sha256(h)],
+        Terminal::Hash256(h) => vec![HashLock::Hash256(h)],
+        Terminal::Ripemd160(h) => vec![HashLock::Ripemd160(h)],
+        Terminal::Hash160(h) => vec![HashLock::Hash160(h)],
+        _ => vec![],
+    }
+}
+
+fn leaf_timelock<Pk: MiniscriptKey, Ctx: ScriptContext>(node: &Terminal<Pk, Ctx>) -> Vec<TimeLock> {
+    match *node {
+        Terminal::After(t) => vec![TimeLock::After(t)],
+        Terminal::Older(t) => vec![TimeLock::Older(t)],
+        _ => vec![],
+    }
+}
+
+/// A transformation of Miniscript nodes, applied bottom-up by
+/// `Miniscript::rewrite`
+pub trait Rewriter<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    /// Transform a node whose children have already been rewritten.
+    /// Returning `node` itself keeps it unchanged; returning an error
+    /// aborts the rewrite.
+    fn rewrite(&mut self, node: Terminal<Pk, Ctx>) -> Result<Terminal<Pk, Ctx>, Error>;
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Iterate over all nodes of the Miniscript, parents before their
+    /// children, and children in script order
+    pub fn iter<'a>(&'a self) -> Iter<'a, Pk, Ctx> {
+        Iter::new(self, false)
+    }
+
+    /// Iterate over all nodes of the Miniscript, children (in script
+    /// order) before their parents
+    pub fn iter_post_order<'a>(&'a self) -> Iter<'a, Pk, Ctx> {
+        Iter::new(self, true)
+    }
+
+    fn iter_leaves<'a, T>(
+        &'a self,
+        extract: fn(&Terminal<Pk, Ctx>) -> Vec<T>,
+    ) -> LeafIter<'a, Pk, Ctx, T> {
+        LeafIter {
+            nodes: self.iter(),
+            pending: vec![],
+            extract,
+        }
+    }
+
+    /// Iterate over the public keys of the Miniscript which are not
+    /// hashed, including those of `multi`
+    pub fn iter_pk<'a>(&'a self) -> LeafIter<'a, Pk, Ctx, Pk> {
+        self.iter_leaves(leaf_pk)
+    }
+
+    /// Iterate over the public key hashes of the `pk_h` fragments
+    pub fn iter_pkh<'a>(&'a self) -> LeafIter<'a, Pk, Ctx, Pk::Hash> {
+        self.iter_leaves(leaf_pkh)
+    }
+
+    /// Iterate over all public keys of the Miniscript, whether in full or
+    /// as hashes
+    pub fn iter_pk_pkh<'a>(&'a self) -> LeafIter<'a, Pk, Ctx, PkPkh<Pk>> {
+        self.iter_leaves(leaf_pk_pkh)
+    }
+
+    /// Iterate over the digests of the hashlocks
+    pub fn iter_hashes<'a>(&'a self) -> LeafIter<'a, Pk, Ctx, HashLock> {
+        self.iter_leaves(leaf_hash)
+    }
+
+    /// Iterate over the timelocks
+    pub fn iter_timelocks<'a>(&'a self) -> LeafIter<'a, Pk, Ctx, TimeLock> {
+        self.iter_leaves(leaf_timelock)
+    }
+
+    /// Whether every public key of the Miniscript, or the hash of every
+    /// hashed one, is one of `keys`, e.g. those a signer holds
+    pub fn has_only_keys_from(&self, keys: &[Pk]) -> bool {
+        self.iter_pk_pkh().all(|pk| pk.is_any_of(keys))
+    }
+
+    /// Compute a value bottom-up, calling `f` on each node with the
+    /// values computed for its children, in the order of `branches`
+    pub fn fold<T, F>(&self, f: &mut F) -> T
+    where
+        F: FnMut(&Miniscript<Pk, Ctx>, Vec<T>) -> T,
+    {
+        let mut subs = vec![];
+        for sub in self.branches() {
+            subs.push(sub.fold(f));
+        }
+        f(self, subs)
+    }
+
+    /// Rebuild the Miniscript bottom-up, passing each node to `rewriter`
+    /// once its children have been rewritten. Every rewritten node is
+    /// type checked again, as by `from_ast`.
+    pub fn rewrite<R: Rewriter<Pk, Ctx>>(
+        &self,
+        rewriter: &mut R,
+    ) -> Result<Miniscript<Pk, Ctx>, Error> {
+        let mut subs = vec![];
+        for sub in self.branches() {
+            subs.push(Arc::new(sub.rewrite(rewriter)?));
+        }
+        let node = with_branches(&self.node, subs);
+        Miniscript::from_ast(rewriter.rewrite(node)?)
+    }
+}
+
+/// Copy of `node` with its children replaced by `subs`, which must have
+/// as many elements as `node` has branches
+fn with_branches<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    node: &Terminal<Pk, Ctx>,
+    subs: Vec<Arc<Miniscript<Pk, Ctx>>>,
+) -> Terminal<Pk, Ctx> {
+    let mut subs = subs.into_iter();
+    let mut next = || subs.next().expect("as many children as branches");
+    match *node {
+        Terminal::Alt(..) => Terminal::Alt(next()),
+        Terminal::Swap(..) => Terminal::Swap(next()),
+        Terminal::Check(..) => Terminal::Check(next()),
+        Terminal::DupIf(..) => Terminal::DupIf(next()),
+        Terminal::Verify(..) => Terminal::Verify(next()),
+        Terminal::NonZero(..) => Terminal::NonZero(next()),
+        Terminal::ZeroNotEqual(..) => Terminal::ZeroNotEqual(next()),
+        Terminal::AndV(..) => Terminal::AndV(next(), next()),
+        Terminal::AndB(..) => Terminal::AndB(next(), next()),
+        Terminal::AndOr(..) => Terminal::AndOr(next(), next(), next()),
+        Terminal::OrB(..) => Terminal::OrB(next(), next()),
+        Terminal::OrD(..) => Terminal::OrD(next(), next()),
+        Terminal::OrC(..) => Terminal::OrC(next(), next()),
+        Terminal::OrI(..) => Terminal::OrI(next(), next()),
+        Terminal::Thresh(k, ref old) => Terminal::Thresh(k, old.iter().map(|_| next()).collect()),
+        ref leaf => leaf.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniscript::Segwitv0;
+    use std::str::FromStr;
+    use RelLockTime;
+
+    type StringMs = Miniscript<String, Segwitv0>;
+
+    #[test]
+    fn iter() {
+        let ms = StringMs::from_str("or_d(pk(A),and_v(v:pk(B),older(144)))").unwrap();
+        let pre: Vec<_> = ms
+            .iter()
+            .map(|node| (node.ms.to_string(), node.path))
+            .collect();
+        assert_eq!(
+            pre,
+            vec![
+                (ms.to_string(), vec![]),
+                ("pk(A)".to_owned(), vec![0]),
+                ("pk_k(A)".to_owned(), vec![0, 0]),
+                ("and_v(v:pk(B),older(144))".to_owned(), vec![1]),
+                ("v:pk(B)".to_owned(), vec![1, 0]),
+                ("pk(B)".to_owned(), vec![1, 0, 0]),
+                ("pk_k(B)".to_owned(), vec![1, 0, 0, 0]),
+                ("older(144)".to_owned(), vec![1, 1]),
+            ]
+        );
+
+        let post: Vec<_> = ms
+            .iter_post_order()
+            .map(|node| (node.ms.to_string(), node.depth()))
+            .collect();
+        assert_eq!(
+            post,
+            vec![
+                ("pk_k(A)".to_owned(), 2),
+                ("pk(A)".to_owned(), 1),
+                ("pk_k(B)".to_owned(), 4),
+                ("pk(B)".to_owned(), 3),
+                ("v:pk(B)".to_owned(), 2),
+                ("older(144)".to_owned(), 2),
+                ("and_v(v:pk(B),older(144))".to_owned(), 1),
+                (ms.to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn fold_and_rewrite() {
+        let ms = StringMs::from_str("or_d(pk(A),and_v(v:pk(B),older(144)))").unwrap();
+        let n_keys = ms.fold(&mut |node, subs: Vec<usize>| match node.node {
+            Terminal::PkK(..) => 1,
+            _ => subs.into_iter().sum(),
+        });
+        assert_eq!(n_keys, 2);
+
+        struct Relock(RelLockTime);
+        impl Rewriter<String, Segwitv0> for Relock {
+            fn rewrite(
+                &mut self,
+                node: Terminal<String, Segwitv0>,
+            ) -> Result<Terminal<String, Segwitv0>, Error> {
+                match node {
+                    Terminal::Older(..) => Ok(Terminal::Older(self.0)),
+                    node => Ok(node),
+                }
+            }
+        }
+        let relocked = ms
+            .rewrite(&mut Relock(RelLockTime::from_height(288)))
+            .unwrap();
+        assert_eq!(
+            relocked.to_string(),
+            "or_d(pk(A),and_v(v:pk(B),older(288)))"
+        );
+
+        // Rewritten nodes are type checked
+        struct Unkey;
+        impl Rewriter<String, Segwitv0> for Unkey {
+            fn rewrite(
+                &mut self,
+                node: Terminal<String, Segwitv0>,
+            ) -> Result<Terminal<String, Segwitv0>, Error> {
+                match node {
+                    Terminal::PkK(..) => Ok(Terminal::True),
+                    node => Ok(node),
+                }
+            }
+        }
+        assert!(ms.rewrite(&mut Unkey).is_err());
+    }
+
+    #[test]
+    fn leaves() {
+        let ms = StringMs::from_str(
+            "or_d(multi(1,A,B),and_v(v:pkh(C),and_v(v:sha256(\
+             1111111111111111111111111111111111111111111111111111111111111111),older(144))))",
+        )
+        .unwrap();
+        assert_eq!(ms.iter_pk().collect::<Vec<_>>(), vec!["A", "B"]);
+        assert_eq!(ms.iter_pkh().collect::<Vec<_>>(), vec!["C"]);
+        assert_eq!(
+            ms.iter_pk_pkh().collect::<Vec<_>>(),
+            vec![
+                PkPkh::PlainPubkey("A".to_owned()),
+                PkPkh::PlainPubkey("B".to_owned()),
+                PkPkh::HashedPubkey("C".to_owned()),
+            ]
+        );
+        assert_eq!(
+            ms.iter_hashes().collect::<Vec<_>>(),
+            vec![HashLock::Sha256(
+                sha256::Hash::from_str(
+                    "1111111111111111111111111111111111111111111111111111111111111111"
+                )
+                .unwrap()
+            )]
+        );
+        assert_eq!(
+            ms.iter_timelocks().collect::<Vec<_>>(),
+            vec![TimeLock::Older(RelLockTime::from_height(144))]
+        );
+
+        let keys = ["A".to_owned(), "B".to_owned(), "C".to_owned()];
+        assert!(ms.has_only_keys_from(&keys));
+        assert!(!ms.has_only_keys_from(&keys[..2]));
+        assert!(!ms.has_only_keys_from(&keys[1..]));
+    }
+}
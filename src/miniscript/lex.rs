@@ -20,9 +20,10 @@
 use bitcoin::blockdata::{opcodes, script};
 use bitcoin::PublicKey;
 
-use std::fmt;
+use std::{cmp, fmt};
 
 use super::Error;
+use ErrorLocation;
 
 /// Atom of a tokenized version of a script
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -84,23 +85,48 @@ impl fmt::Display for Token {
 #[derive(Debug, Clone)]
 /// Iterator that goes through a vector of tokens backward (our parser wants to read
 /// backward and this is more efficient anyway since we can use `Vec::pop()`).
-pub struct TokenIter(Vec<Token>);
+pub struct TokenIter {
+    tokens: Vec<Token>,
+    /// Where in the script each token came from, if known
+    locations: Vec<ErrorLocation>,
+    /// Location of the token most recently returned by `next`
+    last: Option<ErrorLocation>,
+}
 
 impl TokenIter {
     pub fn new(v: Vec<Token>) -> TokenIter {
-        TokenIter(v)
+        TokenIter {
+            tokens: v,
+            locations: vec![],
+            last: None,
+        }
     }
 
     pub fn peek(&self) -> Option<&Token> {
-        self.0.last()
+        self.tokens.last()
     }
 
     pub fn un_next(&mut self, tok: Token) {
-        self.0.push(tok)
+        self.tokens.push(tok);
+        if self.locations.len() + 1 == self.tokens.len() {
+            if let Some(ref loc) = self.last {
+                self.locations.push(loc.clone());
+            }
+        }
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Location in the script of the token most recently returned, if the
+    /// tokens were produced by `lex_located`
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        self.last.as_ref()
     }
 }
 
@@ -108,195 +134,259 @@ impl Iterator for TokenIter {
     type Item = Token;
 
     fn next(&mut self) -> Option<Token> {
-        self.0.pop()
+        let tok = self.tokens.pop();
+        if tok.is_some() && self.locations.len() > self.tokens.len() {
+            self.last = self.locations.pop();
+        }
+        tok
     }
 }
 
 /// Tokenize a script
 pub fn lex(script: &script::Script) -> Result<Vec<Token>, Error> {
-    let mut ret = Vec::with_capacity(script.len());
+    lex_located(script).map(|iter| iter.tokens)
+}
 
-    for ins in script.iter(true) {
-        match ins {
-            script::Instruction::Error(e) => return Err(Error::Script(e)),
-            script::Instruction::Op(opcodes::all::OP_BOOLAND) => {
-                ret.push(Token::BoolAnd);
-            }
-            script::Instruction::Op(opcodes::all::OP_BOOLOR) => {
-                ret.push(Token::BoolOr);
-            }
-            script::Instruction::Op(opcodes::all::OP_EQUAL) => {
-                ret.push(Token::Equal);
-            }
-            script::Instruction::Op(opcodes::all::OP_EQUALVERIFY) => {
-                ret.push(Token::Equal);
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKSIG) => {
-                ret.push(Token::CheckSig);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKSIGVERIFY) => {
-                ret.push(Token::CheckSig);
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKMULTISIG) => {
-                ret.push(Token::CheckMultiSig);
-            }
-            script::Instruction::Op(opcodes::all::OP_CHECKMULTISIGVERIFY) => {
-                ret.push(Token::CheckMultiSig);
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(op) if op == opcodes::all::OP_CSV => {
-                ret.push(Token::CheckSequenceVerify);
-            }
-            script::Instruction::Op(op) if op == opcodes::all::OP_CLTV => {
-                ret.push(Token::CheckLockTimeVerify);
-            }
-            script::Instruction::Op(opcodes::all::OP_FROMALTSTACK) => {
-                ret.push(Token::FromAltStack);
-            }
-            script::Instruction::Op(opcodes::all::OP_TOALTSTACK) => {
-                ret.push(Token::ToAltStack);
-            }
-            script::Instruction::Op(opcodes::all::OP_DROP) => {
-                ret.push(Token::Drop);
-            }
-            script::Instruction::Op(opcodes::all::OP_DUP) => {
-                ret.push(Token::Dup);
-            }
-            script::Instruction::Op(opcodes::all::OP_ADD) => {
-                ret.push(Token::Add);
-            }
-            script::Instruction::Op(opcodes::all::OP_IF) => {
-                ret.push(Token::If);
-            }
-            script::Instruction::Op(opcodes::all::OP_IFDUP) => {
-                ret.push(Token::IfDup);
-            }
-            script::Instruction::Op(opcodes::all::OP_NOTIF) => {
-                ret.push(Token::NotIf);
-            }
-            script::Instruction::Op(opcodes::all::OP_ELSE) => {
-                ret.push(Token::Else);
-            }
-            script::Instruction::Op(opcodes::all::OP_ENDIF) => {
-                ret.push(Token::EndIf);
-            }
-            script::Instruction::Op(opcodes::all::OP_0NOTEQUAL) => {
-                ret.push(Token::ZeroNotEqual);
-            }
-            script::Instruction::Op(opcodes::all::OP_SIZE) => {
-                ret.push(Token::Size);
-            }
-            script::Instruction::Op(opcodes::all::OP_SWAP) => {
-                ret.push(Token::Swap);
-            }
-            script::Instruction::Op(opcodes::all::OP_VERIFY) => {
-                match ret.last() {
-                    Some(op @ &Token::Equal)
-                    | Some(op @ &Token::CheckSig)
-                    | Some(op @ &Token::CheckMultiSig) => return Err(Error::NonMinimalVerify(*op)),
-                    _ => {}
+/// Tokenize a script, remembering the opcode each token was read from so
+/// that errors found while parsing the tokens can point back into the script
+pub fn lex_located(script: &script::Script) -> Result<TokenIter, Error> {
+    let spans = opcode_spans(script.as_bytes());
+    let mut ret = TokenIter::new(Vec::with_capacity(script.len()));
+
+    for (n, ins) in script.iter(true).enumerate() {
+        let (start, end) = spans
+            .get(n)
+            .cloned()
+            .unwrap_or((script.len(), script.len()));
+        let location = ErrorLocation {
+            position: start,
+            len: end - start,
+            opcode_index: Some(n),
+            fragment: None,
+        };
+        let n_tokens = ret.tokens.len();
+        lex_instruction(ins, &mut ret.tokens).map_err(|e| e.located(location.clone()))?;
+        for _ in n_tokens..ret.tokens.len() {
+            ret.locations.push(location.clone());
+        }
+    }
+    Ok(ret)
+}
+
+/// Tokenize a single instruction, appending its tokens to `ret`
+fn lex_instruction(ins: script::Instruction, ret: &mut Vec<Token>) -> Result<(), Error> {
+    match ins {
+        script::Instruction::Error(e) => return Err(Error::Script(e)),
+        script::Instruction::Op(opcodes::all::OP_BOOLAND) => {
+            ret.push(Token::BoolAnd);
+        }
+        script::Instruction::Op(opcodes::all::OP_BOOLOR) => {
+            ret.push(Token::BoolOr);
+        }
+        script::Instruction::Op(opcodes::all::OP_EQUAL) => {
+            ret.push(Token::Equal);
+        }
+        script::Instruction::Op(opcodes::all::OP_EQUALVERIFY) => {
+            ret.push(Token::Equal);
+            ret.push(Token::Verify);
+        }
+        script::Instruction::Op(opcodes::all::OP_CHECKSIG) => {
+            ret.push(Token::CheckSig);
+        }
+        script::Instruction::Op(opcodes::all::OP_CHECKSIGVERIFY) => {
+            ret.push(Token::CheckSig);
+            ret.push(Token::Verify);
+        }
+        script::Instruction::Op(opcodes::all::OP_CHECKMULTISIG) => {
+            ret.push(Token::CheckMultiSig);
+        }
+        script::Instruction::Op(opcodes::all::OP_CHECKMULTISIGVERIFY) => {
+            ret.push(Token::CheckMultiSig);
+            ret.push(Token::Verify);
+        }
+        script::Instruction::Op(op) if op == opcodes::all::OP_CSV => {
+            ret.push(Token::CheckSequenceVerify);
+        }
+        script::Instruction::Op(op) if op == opcodes::all::OP_CLTV => {
+            ret.push(Token::CheckLockTimeVerify);
+        }
+        script::Instruction::Op(opcodes::all::OP_FROMALTSTACK) => {
+            ret.push(Token::FromAltStack);
+        }
+        script::Instruction::Op(opcodes::all::OP_TOALTSTACK) => {
+            ret.push(Token::ToAltStack);
+        }
+        script::Instruction::Op(opcodes::all::OP_DROP) => {
+            ret.push(Token::Drop);
+        }
+        script::Instruction::Op(opcodes::all::OP_DUP) => {
+            ret.push(Token::Dup);
+        }
+        script::Instruction::Op(opcodes::all::OP_ADD) => {
+            ret.push(Token::Add);
+        }
+        script::Instruction::Op(opcodes::all::OP_IF) => {
+            ret.push(Token::If);
+        }
+        script::Instruction::Op(opcodes::all::OP_IFDUP) => {
+            ret.push(Token::IfDup);
+        }
+        script::Instruction::Op(opcodes::all::OP_NOTIF) => {
+            ret.push(Token::NotIf);
+        }
+        script::Instruction::Op(opcodes::all::OP_ELSE) => {
+            ret.push(Token::Else);
+        }
+        script::Instruction::Op(opcodes::all::OP_ENDIF) => {
+            ret.push(Token::EndIf);
+        }
+        script::Instruction::Op(opcodes::all::OP_0NOTEQUAL) => {
+            ret.push(Token::ZeroNotEqual);
+        }
+        script::Instruction::Op(opcodes::all::OP_SIZE) => {
+            ret.push(Token::Size);
+        }
+        script::Instruction::Op(opcodes::all::OP_SWAP) => {
+            ret.push(Token::Swap);
+        }
+        script::Instruction::Op(opcodes::all::OP_VERIFY) => {
+            match ret.last() {
+                Some(op @ &Token::Equal)
+                | Some(op @ &Token::CheckSig)
+                | Some(op @ &Token::CheckMultiSig) => return Err(Error::NonMinimalVerify(*op)),
+                _ => {}
+            }
+            ret.push(Token::Verify);
+        }
+        script::Instruction::Op(opcodes::all::OP_RIPEMD160) => {
+            ret.push(Token::Ripemd160);
+        }
+        script::Instruction::Op(opcodes::all::OP_HASH160) => {
+            ret.push(Token::Hash160);
+        }
+        script::Instruction::Op(opcodes::all::OP_SHA256) => {
+            ret.push(Token::Sha256);
+        }
+        script::Instruction::Op(opcodes::all::OP_HASH256) => {
+            ret.push(Token::Hash256);
+        }
+        script::Instruction::PushBytes(bytes) => {
+            match bytes.len() {
+                20 => {
+                    let mut x = [0; 20];
+                    x.copy_from_slice(bytes);
+                    ret.push(Token::Hash20(x))
                 }
-                ret.push(Token::Verify);
-            }
-            script::Instruction::Op(opcodes::all::OP_RIPEMD160) => {
-                ret.push(Token::Ripemd160);
-            }
-            script::Instruction::Op(opcodes::all::OP_HASH160) => {
-                ret.push(Token::Hash160);
-            }
-            script::Instruction::Op(opcodes::all::OP_SHA256) => {
-                ret.push(Token::Sha256);
-            }
-            script::Instruction::Op(opcodes::all::OP_HASH256) => {
-                ret.push(Token::Hash256);
-            }
-            script::Instruction::PushBytes(bytes) => {
-                match bytes.len() {
-                    20 => {
-                        let mut x = [0; 20];
-                        x.copy_from_slice(bytes);
-                        ret.push(Token::Hash20(x))
-                    }
-                    32 => {
-                        let mut x = [0; 32];
-                        x.copy_from_slice(bytes);
-                        ret.push(Token::Hash32(x))
-                    }
-                    33 | 65 => {
-                        ret.push(Token::Pubkey(
-                            PublicKey::from_slice(bytes).map_err(Error::BadPubkey)?,
-                        ));
-                    }
-                    _ => {
-                        match script::read_scriptint(bytes) {
-                            Ok(v) if v >= 0 => {
-                                // check minimality of the number
-                                if &script::Builder::new().push_int(v).into_script()[1..] != bytes {
-                                    return Err(Error::InvalidPush(bytes.to_owned()));
-                                }
-                                ret.push(Token::Num(v as u32));
+                32 => {
+                    let mut x = [0; 32];
+                    x.copy_from_slice(bytes);
+                    ret.push(Token::Hash32(x))
+                }
+                33 | 65 => {
+                    ret.push(Token::Pubkey(
+                        PublicKey::from_slice(bytes).map_err(Error::BadPubkey)?,
+                    ));
+                }
+                _ => {
+                    match script::read_scriptint(bytes) {
+                        Ok(v) if v >= 0 => {
+                            // check minimality of the number
+                            if &script::Builder::new().push_int(v).into_script()[1..] != bytes {
+                                return Err(Error::InvalidPush(bytes.to_owned()));
                             }
-                            Ok(_) => return Err(Error::InvalidPush(bytes.to_owned())),
-                            Err(e) => return Err(Error::Script(e)),
+                            ret.push(Token::Num(v as u32));
                         }
+                        Ok(_) => return Err(Error::InvalidPush(bytes.to_owned())),
+                        Err(e) => return Err(Error::Script(e)),
                     }
                 }
             }
-            script::Instruction::Op(opcodes::all::OP_PUSHBYTES_0) => {
-                ret.push(Token::Num(0));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_1) => {
-                ret.push(Token::Num(1));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_2) => {
-                ret.push(Token::Num(2));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_3) => {
-                ret.push(Token::Num(3));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_4) => {
-                ret.push(Token::Num(4));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_5) => {
-                ret.push(Token::Num(5));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_6) => {
-                ret.push(Token::Num(6));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_7) => {
-                ret.push(Token::Num(7));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_8) => {
-                ret.push(Token::Num(8));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_9) => {
-                ret.push(Token::Num(9));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_10) => {
-                ret.push(Token::Num(10));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_11) => {
-                ret.push(Token::Num(11));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_12) => {
-                ret.push(Token::Num(12));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_13) => {
-                ret.push(Token::Num(13));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_14) => {
-                ret.push(Token::Num(14));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_15) => {
-                ret.push(Token::Num(15));
-            }
-            script::Instruction::Op(opcodes::all::OP_PUSHNUM_16) => {
-                ret.push(Token::Num(16));
-            }
-            script::Instruction::Op(op) => return Err(Error::InvalidOpcode(op)),
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHBYTES_0) => {
+            ret.push(Token::Num(0));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_1) => {
+            ret.push(Token::Num(1));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_2) => {
+            ret.push(Token::Num(2));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_3) => {
+            ret.push(Token::Num(3));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_4) => {
+            ret.push(Token::Num(4));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_5) => {
+            ret.push(Token::Num(5));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_6) => {
+            ret.push(Token::Num(6));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_7) => {
+            ret.push(Token::Num(7));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_8) => {
+            ret.push(Token::Num(8));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_9) => {
+            ret.push(Token::Num(9));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_10) => {
+            ret.push(Token::Num(10));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_11) => {
+            ret.push(Token::Num(11));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_12) => {
+            ret.push(Token::Num(12));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_13) => {
+            ret.push(Token::Num(13));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_14) => {
+            ret.push(Token::Num(14));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_15) => {
+            ret.push(Token::Num(15));
+        }
+        script::Instruction::Op(opcodes::all::OP_PUSHNUM_16) => {
+            ret.push(Token::Num(16));
+        }
+        script::Instruction::Op(op) => return Err(Error::InvalidOpcode(op)),
+    }
+    Ok(())
+}
+
+/// Start and end byte offsets of each opcode of a script, including the
+/// data it pushes. A push running past the end of the script ends there.
+pub(crate) fn opcode_spans(bytes: &[u8]) -> Vec<(usize, usize)> {
+    let mut ret = vec![];
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let op = bytes[pos] as usize;
+        let (header, len) = match op {
+            76 => (2, read_le(&bytes[pos + 1..], 1)),
+            77 => (3, read_le(&bytes[pos + 1..], 2)),
+            78 => (5, read_le(&bytes[pos + 1..], 4)),
+            op if op < 76 => (1, op),
+            _ => (1, 0),
         };
+        let end = cmp::min(pos.saturating_add(header).saturating_add(len), bytes.len());
+        ret.push((pos, end));
+        pos = end;
     }
-    Ok(ret)
+    ret
+}
+
+/// Little-endian length of a `PUSHDATA`, or one reaching the end of the
+/// script if it is too short to hold it
+fn read_le(bytes: &[u8], n: usize) -> usize {
+    if bytes.len() < n {
+        return bytes.len();
+    }
+    bytes[..n]
+        .iter()
+        .rev()
+        .fold(0, |acc, &b| (acc << 8) | b as usize)
 }
@@ -31,18 +31,25 @@ use std::{fmt, str};
 
 use bitcoin;
 use bitcoin::blockdata::script;
+use bitcoin::hashes::hash160;
 
 pub use self::context::Legacy;
 pub use self::context::Segwitv0;
 
+pub mod analysis;
 pub mod astelem;
+pub mod bolt3;
 pub(crate) mod context;
 pub mod decode;
+pub mod iter;
 pub mod lex;
+pub mod optimize;
+pub mod sanity;
 pub mod satisfy;
 pub mod types;
+pub mod wrap;
 
-use self::lex::{lex, TokenIter};
+use self::lex::lex_located;
 use self::types::Property;
 pub use miniscript::context::ScriptContext;
 use miniscript::decode::Terminal;
@@ -134,26 +141,143 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
     pub fn as_inner(&self) -> &Terminal<Pk, Ctx> {
         &self.node
     }
+
+    /// Whether some spending path of the Miniscript requires both a block
+    /// height and a time based `after`, or both kinds of `older`, and so
+    /// can never be used
+    pub fn has_mixed_timelocks(&self) -> bool {
+        self.ext.timelock_info.contains_unspendable_path()
+    }
+
+    /// The direct children of this node, in script order. Together with
+    /// the public `ty` and `ext` fields, this allows reading the type and
+    /// the extra properties of every fragment of the Miniscript.
+    pub fn branches(&self) -> Vec<&Miniscript<Pk, Ctx>> {
+        match self.node {
+            Terminal::True
+            | Terminal::False
+            | Terminal::PkK(..)
+            | Terminal::PkH(..)
+            | Terminal::After(..)
+            | Terminal::Older(..)
+            | Terminal::Sha256(..)
+            | Terminal::Hash256(..)
+            | Terminal::Ripemd160(..)
+            | Terminal::Hash160(..)
+            | Terminal::Multi(..) => vec![],
+            Terminal::Alt(ref sub)
+            | Terminal::Swap(ref sub)
+            | Terminal::Check(ref sub)
+            | Terminal::DupIf(ref sub)
+            | Terminal::Verify(ref sub)
+            | Terminal::NonZero(ref sub)
+            | Terminal::ZeroNotEqual(ref sub) => vec![&**sub],
+            Terminal::AndV(ref l, ref r)
+            | Terminal::AndB(ref l, ref r)
+            | Terminal::OrB(ref l, ref r)
+            | Terminal::OrD(ref l, ref r)
+            | Terminal::OrC(ref l, ref r)
+            | Terminal::OrI(ref l, ref r) => vec![&**l, &**r],
+            Terminal::AndOr(ref a, ref b, ref c) => vec![&**a, &**b, &**c],
+            Terminal::Thresh(_, ref subs) => subs.iter().map(|sub| &**sub).collect(),
+        }
+    }
+
+    /// Pretty-print the Miniscript as a tree with one fragment per line,
+    /// each annotated with its type (see the `Display` of `types::Type`),
+    /// its worst-case op count when unexecuted, satisfied and dissatisfied
+    /// (`-` if there is no such witness) and its script size in bytes.
+    /// Wrappers get lines of their own, so that e.g. `pk(A)` is shown as
+    /// `c:` above `pk_k(A)`.
+    pub fn annotated(&self) -> String {
+        let mut lines = vec![];
+        self.annotate(0, &mut lines);
+        let width = lines.iter().map(|line| line.0.len()).max().unwrap_or(0);
+        let ops = |n: Option<usize>| n.map(|n| n.to_string()).unwrap_or_else(|| "-".to_owned());
+
+        let mut ret = String::new();
+        for (label, ms) in lines {
+            ret.push_str(&format!(
+                "{:width$}  {:10} ops {}/{}/{}  size {}\n",
+                label,
+                ms.ty.to_string(),
+                ms.ext.ops_count_static,
+                ops(ms.ext.ops_count_sat),
+                ops(ms.ext.ops_count_nsat),
+                ms.ext.pk_cost,
+                width = width,
+            ));
+        }
+        ret
+    }
+
+    /// Helper for `annotated`, collecting the indented label of each line
+    /// and the fragment it describes
+    fn annotate<'a>(&'a self, depth: usize, lines: &mut Vec<(String, &'a Miniscript<Pk, Ctx>)>) {
+        let (name, subs) = match self.node.wrap_char() {
+            Some((ch, sub)) => (format!("{}:", ch), vec![&**sub]),
+            None => match self.node {
+                Terminal::AndV(..) => ("and_v".to_owned(), self.branches()),
+                Terminal::AndB(..) => ("and_b".to_owned(), self.branches()),
+                Terminal::AndOr(ref a, ref b, ref c) if c.node == Terminal::False => {
+                    ("and_n".to_owned(), vec![&**a, &**b])
+                }
+                Terminal::AndOr(..) => ("andor".to_owned(), self.branches()),
+                Terminal::OrB(..) => ("or_b".to_owned(), self.branches()),
+                Terminal::OrD(..) => ("or_d".to_owned(), self.branches()),
+                Terminal::OrC(..) => ("or_c".to_owned(), self.branches()),
+                Terminal::OrI(..) => ("or_i".to_owned(), self.branches()),
+                Terminal::Thresh(k, _) => (format!("thresh({})", k), self.branches()),
+                _ => (self.node.to_string(), vec![]),
+            },
+        };
+        lines.push((format!("{}{}", "  ".repeat(depth), name), self));
+        for sub in subs {
+            sub.annotate(depth + 1, lines);
+        }
+    }
 }
 
 impl<Ctx: ScriptContext> Miniscript<bitcoin::PublicKey, Ctx> {
     /// Attempt to parse a script into a Miniscript representation
     pub fn parse(script: &script::Script) -> Result<Miniscript<bitcoin::PublicKey, Ctx>, Error> {
+        Miniscript::parse_with_keys(script, &mut |pk| Ok(*pk), &mut |pkh| Ok(*pkh))
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Attempt to parse a script into a Miniscript representation with
+    /// keys of any type, e.g. to look up each key in a wallet's key
+    /// registry. Every public key pushed by the script is decoded with
+    /// `decodefpk`, and every public key hash (of a `pk_h`) with
+    /// `decodefpkh`; the first error either returns stops the parse.
+    pub fn parse_with_keys<FPk, FPkh>(
+        script: &script::Script,
+        decodefpk: &mut FPk,
+        decodefpkh: &mut FPkh,
+    ) -> Result<Miniscript<Pk, Ctx>, Error>
+    where
+        FPk: FnMut(&bitcoin::PublicKey) -> Result<Pk, Error>,
+        FPkh: FnMut(&hash160::Hash) -> Result<Pk::Hash, Error>,
+    {
         // Transactions more than 100Kb are non-standard
         if script.len() > 100_000 {
             return Err(Error::ScriptSizeTooLarge);
         }
-        let tokens = lex(script)?;
-        let mut iter = TokenIter::new(tokens);
+        let mut iter = lex_located(script)?;
 
-        let top = decode::parse(&mut iter)?;
+        let top = decode::parse_with_keys(&mut iter, decodefpk, decodefpkh)?;
         Ctx::check_frag_validity(&top.node)?;
         let type_check = types::Type::type_check(&top.node, |_| None)?;
         if type_check.corr.base != types::Base::B {
             return Err(Error::NonTopLevel(format!("{:?}", top)));
         };
         if let Some(leading) = iter.next() {
-            Err(Error::Trailing(leading.to_string()))
+            let err = Error::Trailing(leading.to_string());
+            Err(match iter.location() {
+                Some(loc) => err.located(loc.clone()),
+                None => err,
+            })
         } else {
             Ok(top)
         }
@@ -255,7 +379,7 @@ where
     <<Pk as MiniscriptKey>::Hash as str::FromStr>::Err: ToString,
 {
     fn from_tree(top: &expression::Tree) -> Result<Arc<Miniscript<Pk, Ctx>>, Error> {
-        Ok(Arc::new(expression::FromTree::from_tree(top)?))
+        Ok(Arc::new(expression::from_tree_at(top)?))
     }
 }
 
@@ -269,7 +393,7 @@ where
     /// Parse an expression tree into a Miniscript. As a general rule, this
     /// should not be called directly; rather go through the descriptor API.
     fn from_tree(top: &expression::Tree) -> Result<Miniscript<Pk, Ctx>, Error> {
-        let inner: Terminal<Pk, Ctx> = expression::FromTree::from_tree(top)?;
+        let inner: Terminal<Pk, Ctx> = expression::from_tree_at(top)?;
         Ok(Miniscript {
             ty: Type::type_check(&inner, |_| None)?,
             ext: ExtData::type_check(&inner, |_| None)?,
@@ -296,7 +420,7 @@ where
         }
 
         let top = expression::Tree::from_str(s)?;
-        let ms: Miniscript<Pk, Ctx> = expression::FromTree::from_tree(&top)?;
+        let ms: Miniscript<Pk, Ctx> = expression::from_tree_at(&top)?;
 
         if ms.ty.corr.base != types::Base::B {
             Err(Error::NonTopLevel(format!("{:?}", ms)))
@@ -383,7 +507,7 @@ mod tests {
 
     type Segwitv0Script = Miniscript<bitcoin::PublicKey, Segwitv0>;
 
-    fn pubkeys(n: usize) -> Vec<bitcoin::PublicKey> {
+    pub(crate) fn pubkeys(n: usize) -> Vec<bitcoin::PublicKey> {
         let mut ret = Vec::with_capacity(n);
         let secp = secp256k1::Secp256k1::new();
         let mut sk = [0; 32];
@@ -608,6 +732,10 @@ mod tests {
 
         assert!(Segwitv0Script::from_str("1()").is_err());
         assert!(Segwitv0Script::from_str("tv:1()").is_err());
+        // Relative timelock with the BIP 68 disable flag set
+        assert!(Segwitv0Script::from_str("older(2147483649)").is_err());
+        // Relative timelock of zero 512-second intervals
+        assert!(Segwitv0Script::from_str("older(4194304)").is_err());
     }
 
     #[test]
@@ -797,6 +925,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn mixed_timelocks() {
+        let mixed = |s: &str| Segwitv0Script::from_str(s).unwrap().has_mixed_timelocks();
+        assert!(mixed("and_v(v:after(700000),after(1600000000))"));
+        assert!(!mixed("or_i(after(700000),after(1600000000))"));
+        assert!(mixed("and_b(older(144),a:older(4194305))"));
+        // Relative and absolute timelocks are independent
+        assert!(!mixed("and_v(v:older(4194305),after(700000))"));
+
+        assert!(!mixed("thresh(1,dv:older(144),sdv:older(4194305))"));
+        assert!(mixed("thresh(2,dv:older(144),sdv:older(4194305))"));
+        assert!(!mixed(
+            "andor(dv:after(700000),after(800000),after(1600000000))"
+        ));
+        assert!(mixed(
+            "andor(dv:after(700000),after(1600000000),after(800000))"
+        ));
+    }
+
     #[test]
     fn deserialize() {
         // Most of these came from fuzzing, hence the increasing lengths
@@ -819,4 +966,90 @@ mod tests {
         ))
         .is_err());
     }
+
+    #[test]
+    fn parse_with_keys() {
+        let keys = pubkeys(3);
+        let names = ["alice", "bob", "carol"];
+        let ms = Segwitv0Script::from_str(&format!(
+            "or_d(multi(1,{},{}),and_v(v:pkh({}),older(10)))",
+            keys[0],
+            keys[1],
+            keys[2].to_pubkeyhash()
+        ))
+        .unwrap();
+        let script = ms.encode();
+
+        let mut decode_pk = |pk: &bitcoin::PublicKey| match keys.iter().position(|k| k == pk) {
+            Some(i) => Ok(names[i].to_owned()),
+            None => Err(::Error::Unexpected(format!("unknown key {}", pk))),
+        };
+        let mut decode_pkh =
+            |pkh: &hash160::Hash| match keys.iter().position(|k| k.to_pubkeyhash() == *pkh) {
+                Some(i) => Ok(names[i].to_owned()),
+                None => Err(::Error::Unexpected(format!("unknown key hash {}", pkh))),
+            };
+        let named = Miniscript::<String, Segwitv0>::parse_with_keys(
+            &script,
+            &mut decode_pk,
+            &mut decode_pkh,
+        )
+        .unwrap();
+        assert_eq!(
+            named.to_string(),
+            "or_d(multi(1,alice,bob),and_v(v:pkh(carol),older(10)))"
+        );
+        assert_eq!(named.ty, ms.ty);
+
+        // Keys which the decoder does not know are an error
+        let others = pubkeys(4);
+        let script = Segwitv0Script::from_str(&format!("pk({})", others[3]))
+            .unwrap()
+            .encode();
+        assert!(Miniscript::<String, Segwitv0>::parse_with_keys(
+            &script,
+            &mut decode_pk,
+            &mut decode_pkh
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let location = |hex: &str| {
+            let err = Segwitv0Script::parse(&hex_script(hex)).unwrap_err();
+            let location = err.location().cloned().unwrap();
+            (location.opcode_index, location.position, location.len)
+        };
+        // OP_NOP is not a Miniscript opcode
+        assert_eq!(location("5161"), (Some(1), 1, 1));
+        // Two `pk` fragments back to back are read as an ill-typed
+        // `and_v`, which starts at the first key
+        let pk = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        assert_eq!(location(&format!("21{}ac21{}ac", pk, pk)), (Some(0), 0, 34));
+        // A hashlock's `EQUAL` needs a hash, not a number, to compare to
+        assert_eq!(location("5187"), (Some(0), 0, 1));
+    }
+
+    #[test]
+    fn annotated() {
+        let ms = Miniscript::<String, Segwitv0>::from_str("or_d(pk(A),and_v(v:pkh(B),older(144)))")
+            .unwrap();
+        assert_eq!(ms.ty.to_string(), "Bsfm");
+        assert_eq!(ms.branches().len(), 2);
+        assert_eq!(ms.branches()[1].ty.corr.base, types::Base::B);
+        assert_eq!(
+            ms.annotated().lines().collect::<Vec<_>>(),
+            vec![
+                "or_d             Bsfm       ops 7/9/-  size 67",
+                "  c:             Bondusem   ops 1/1/1  size 35",
+                "    pk_k(A)      Kondusem   ops 0/0/0  size 34",
+                "  and_v          Bnsfm      ops 5/5/-  size 29",
+                "    v:           Vnsfm      ops 4/4/-  size 25",
+                "      c:         Bndusem    ops 4/4/4  size 25",
+                "        pk_h(B)  Kndusem    ops 3/3/3  size 24",
+                "    older(144)   Bzfm       ops 1/1/-  size 4",
+            ]
+        );
+    }
 }
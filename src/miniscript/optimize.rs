@@ -0,0 +1,438 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Optimizer
+//!
+//! Rewrites Miniscripts into cheaper equivalent ones, fragment by fragment.
+//! A rewrite of a fragment is only kept if it type checks as a subtype of
+//! the original, so that it fits wherever the original did, if it lifts
+//! to the same normalized semantic policy, and if neither its script nor
+//! its largest satisfaction or dissatisfaction is any larger. At the top
+//! level, where the script is never dissatisfied, the rewrite need only
+//! keep the base type and the safety and non-malleability of the original,
+//! and its dissatisfactions are not compared.
+//!
+
+use std::cmp;
+use std::sync::Arc;
+
+use super::decode::Terminal;
+use super::iter::Rewriter;
+use super::types::Type;
+use super::{Miniscript, ScriptContext};
+use policy::{Liftable, Semantic as Policy};
+use {Error, MiniscriptKey, ToPublicKey};
+
+/// Size of the witness element `1` which selects the first branch of an
+/// `or_i` or satisfies a `d:` wrapper, as the `one_cost` of segwit passed
+/// to `max_satisfaction_size`. All sizes here are those of segwit witness
+/// elements with their length prefix, even under `Legacy`, where an
+/// `OP_1` in the scriptSig takes a single byte. Being added to and compared
+/// with the sizes of signatures, keys and other elements, it decides e.g.
+/// whether `or_i` is cheaper than `or_d`.
+const ONE_COST: usize = 2;
+
+/// Applies the rewrite rules of `candidates` at each node
+struct Optimizer;
+
+impl<Pk, Ctx> Rewriter<Pk, Ctx> for Optimizer
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    Ctx: ScriptContext,
+{
+    fn rewrite(&mut self, node: Terminal<Pk, Ctx>) -> Result<Terminal<Pk, Ctx>, Error> {
+        let original = Miniscript::from_ast(node)?;
+        Ok(improve(original, false).node)
+    }
+}
+
+/// Whether a top-level fragment of type `ty` may replace one of type
+/// `other`: only their dissatisfactions may differ
+fn fits_top_level(ty: &Type, other: Type) -> bool {
+    ty.corr.base == other.corr.base
+        && (ty.mall.safe || !other.mall.safe)
+        && (ty.mall.non_malleable || !other.mall.non_malleable)
+}
+
+/// Whether `ms` lifts to `policy`, which must be normalized; comparing
+/// spending paths when the normalized policies differ only in form,
+/// e.g. for a `thresh(n,...)` of `n` children and an `and`
+fn same_policy<Pk, Ctx>(ms: &Miniscript<Pk, Ctx>, policy: &Policy<Pk>) -> bool
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    Ctx: ScriptContext,
+{
+    let lifted = ms.lift().normalized();
+    lifted == *policy || lifted.is_equivalent(policy).unwrap_or(false)
+}
+
+/// Repeatedly apply the first rule of `candidates` giving a cheaper
+/// fragment which fits in place of `original`, with the same policy
+fn improve<Pk, Ctx>(original: Miniscript<Pk, Ctx>, top_level: bool) -> Miniscript<Pk, Ctx>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    Ctx: ScriptContext,
+{
+    let fits: fn(&Type, Type) -> bool = if top_level {
+        fits_top_level
+    } else {
+        Type::is_subtype
+    };
+    let policy = original.lift().normalized();
+    let mut best = original.clone();
+    // A rewrite may enable another one at the same node, e.g. `thresh`
+    // becoming `and_b`, then `and_v`
+    loop {
+        let better = candidates(&best.node).into_iter().find(|ms| {
+            fits(&ms.ty, original.ty)
+                && is_cheaper(ms, &best, top_level)
+                && same_policy(ms, &policy)
+        });
+        match better {
+            Some(ms) => best = ms,
+            None => return best,
+        }
+    }
+}
+
+/// Whether `ms` is strictly cheaper than `than` in script size, in
+/// satisfaction size or, unless at the `top_level`, in dissatisfaction
+/// size, and no more expensive in the others. Fragments which cannot be
+/// satisfied are never cheaper.
+fn is_cheaper<Pk, Ctx>(
+    ms: &Miniscript<Pk, Ctx>,
+    than: &Miniscript<Pk, Ctx>,
+    top_level: bool,
+) -> bool
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    Ctx: ScriptContext,
+{
+    let (new_sat, new_dissat) = witness_sizes(&ms.node);
+    let (old_sat, old_dissat) = witness_sizes(&than.node);
+    let (new_sat, old_sat) = match (new_sat, old_sat) {
+        (Some(new), Some(old)) => (new, old),
+        _ => return false,
+    };
+    // A fragment which cannot be dissatisfied has no dissatisfaction to
+    // make larger, and type checking already keeps those which can
+    let (new_dissat, old_dissat) = match (new_dissat, old_dissat) {
+        (Some(new), Some(old)) if !top_level => (new, old),
+        _ => (0, 0),
+    };
+    let new = (ms.script_size(), new_sat, new_dissat);
+    let old = (than.script_size(), old_sat, old_dissat);
+    new.0 <= old.0 && new.1 <= old.1 && new.2 <= old.2 && new != old
+}
+
+/// Maximum sizes, in bytes, of the witnesses satisfying and dissatisfying
+/// a fragment, or `None` if it has no satisfaction or no dissatisfaction.
+/// Unlike `max_satisfaction_size`, this is defined for every fragment which
+/// type checks, whatever the types of its children.
+fn witness_sizes<Pk, Ctx>(node: &Terminal<Pk, Ctx>) -> (Option<usize>, Option<usize>)
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+{
+    let add = |a: Option<usize>, b: Option<usize>| match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        _ => None,
+    };
+    let max = |a: Option<usize>, b: Option<usize>| match (a, b) {
+        (Some(a), Some(b)) => Some(cmp::max(a, b)),
+        (a, None) => a,
+        (None, b) => b,
+    };
+    match *node {
+        Terminal::PkK(..) => (Some(73), Some(1)),
+        Terminal::PkH(..) => (Some(34 + 73), Some(1 + 34)),
+        Terminal::After(..) | Terminal::Older(..) => (Some(0), None),
+        // Any 32-byte value other than the preimage dissatisfies a hash
+        Terminal::Sha256(..)
+        | Terminal::Hash256(..)
+        | Terminal::Ripemd160(..)
+        | Terminal::Hash160(..) => (Some(33), Some(33)),
+        Terminal::True => (Some(0), None),
+        Terminal::False => (None, Some(0)),
+        Terminal::Alt(ref sub)
+        | Terminal::Swap(ref sub)
+        | Terminal::Check(ref sub)
+        | Terminal::ZeroNotEqual(ref sub) => witness_sizes(&sub.node),
+        Terminal::DupIf(ref sub) => (add(Some(ONE_COST), witness_sizes(&sub.node).0), Some(1)),
+        Terminal::Verify(ref sub) => (witness_sizes(&sub.node).0, None),
+        Terminal::NonZero(ref sub) => (witness_sizes(&sub.node).0, Some(1)),
+        Terminal::AndV(ref l, ref r) => {
+            let ((sl, _), (sr, _)) = (witness_sizes(&l.node), witness_sizes(&r.node));
+            (add(sl, sr), None)
+        }
+        Terminal::AndB(ref l, ref r) => {
+            let ((sl, dl), (sr, dr)) = (witness_sizes(&l.node), witness_sizes(&r.node));
+            (add(sl, sr), add(dl, dr))
+        }
+        Terminal::AndOr(ref a, ref b, ref c) => {
+            let (sa, da) = witness_sizes(&a.node);
+            let (sb, _) = witness_sizes(&b.node);
+            let (sc, dc) = witness_sizes(&c.node);
+            (max(add(sa, sb), add(da, sc)), add(da, dc))
+        }
+        Terminal::OrB(ref l, ref r) => {
+            let ((sl, dl), (sr, dr)) = (witness_sizes(&l.node), witness_sizes(&r.node));
+            (max(add(sl, dr), add(dl, sr)), add(dl, dr))
+        }
+        Terminal::OrD(ref l, ref r) => {
+            let ((sl, dl), (sr, dr)) = (witness_sizes(&l.node), witness_sizes(&r.node));
+            (max(sl, add(dl, sr)), add(dl, dr))
+        }
+        Terminal::OrC(ref l, ref r) => {
+            let ((sl, dl), (sr, _)) = (witness_sizes(&l.node), witness_sizes(&r.node));
+            (max(sl, add(dl, sr)), None)
+        }
+        Terminal::OrI(ref l, ref r) => {
+            let ((sl, dl), (sr, dr)) = (witness_sizes(&l.node), witness_sizes(&r.node));
+            (
+                max(add(Some(ONE_COST), sl), add(Some(1), sr)),
+                max(add(Some(ONE_COST), dl), add(Some(1), dr)),
+            )
+        }
+        Terminal::Thresh(k, ref subs) => {
+            // `best[j]`: the largest witness for the children so far, with
+            // `j` of them satisfied
+            let mut best = vec![None; k + 1];
+            best[0] = Some(0);
+            let mut dissat = Some(0);
+            for sub in subs {
+                let (sat, sub_dissat) = witness_sizes(&sub.node);
+                for j in (0..k + 1).rev() {
+                    let skip = add(best[j], sub_dissat);
+                    let take = if j > 0 { add(best[j - 1], sat) } else { None };
+                    best[j] = max(skip, take);
+                }
+                dissat = add(dissat, sub_dissat);
+            }
+            (best[k], dissat)
+        }
+        Terminal::Multi(k, _) => (Some(1 + 73 * k), Some(1 + k)),
+    }
+}
+
+/// Type check a fragment for use as a child
+fn child<Pk, Ctx>(node: Terminal<Pk, Ctx>) -> Option<Arc<Miniscript<Pk, Ctx>>>
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+{
+    Miniscript::from_ast(node).ok().map(Arc::new)
+}
+
+/// The child of an `a:` or `s:` wrapper
+fn unwrap_alt_swap<Pk, Ctx>(ms: &Miniscript<Pk, Ctx>) -> Option<Arc<Miniscript<Pk, Ctx>>>
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+{
+    match ms.node {
+        Terminal::Alt(ref sub) | Terminal::Swap(ref sub) => Some(Arc::clone(sub)),
+        _ => None,
+    }
+}
+
+/// Equivalent rewrites of a fragment, which may or may not type check,
+/// or be cheaper
+fn candidates<Pk, Ctx>(node: &Terminal<Pk, Ctx>) -> Vec<Miniscript<Pk, Ctx>>
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+{
+    let mut ret = vec![];
+    match *node {
+        // `X SWAP Y BOOLAND` or `X TOALTSTACK Y FROMALTSTACK BOOLAND`
+        // becomes `X VERIFY Y`
+        Terminal::AndB(ref l, ref r) => {
+            if let (Some(vl), Some(r)) =
+                (child(Terminal::Verify(Arc::clone(l))), unwrap_alt_swap(r))
+            {
+                ret.push(Terminal::AndV(vl, r));
+            }
+        }
+        // `and_n(X,Y)` becomes `and_v(v:X,Y)`
+        Terminal::AndOr(ref a, ref b, ref c) if c.node == Terminal::False => {
+            if let Some(va) = child(Terminal::Verify(Arc::clone(a))) {
+                ret.push(Terminal::AndV(va, Arc::clone(b)));
+            }
+        }
+        // `or_b(X,a:Y)` becomes `or_d(X,Y)`
+        Terminal::OrB(ref l, ref r) => {
+            if let Some(r) = unwrap_alt_swap(r) {
+                ret.push(Terminal::OrD(Arc::clone(l), r));
+            }
+        }
+        // An `or_d` needs the dissatisfaction of its left child to use its
+        // right child, which may cost more than the branch selector of an
+        // `or_i`
+        Terminal::OrD(ref l, ref r) => {
+            ret.push(Terminal::OrI(Arc::clone(l), Arc::clone(r)));
+        }
+        // `u:X` and `l:X` become `X`
+        Terminal::OrI(ref l, ref r) if r.node == Terminal::False => {
+            ret.push(l.node.clone());
+        }
+        Terminal::OrI(ref l, ref r) if l.node == Terminal::False => {
+            ret.push(r.node.clone());
+        }
+        // Conversely an `or_i` may be cheaper as an `or_d` or `or_c`,
+        // in either order
+        Terminal::OrI(ref l, ref r) => {
+            ret.push(Terminal::OrD(Arc::clone(l), Arc::clone(r)));
+            ret.push(Terminal::OrD(Arc::clone(r), Arc::clone(l)));
+            ret.push(Terminal::OrC(Arc::clone(l), Arc::clone(r)));
+            ret.push(Terminal::OrC(Arc::clone(r), Arc::clone(l)));
+        }
+        // `t:v:X` becomes `X`
+        Terminal::AndV(ref l, ref r) if r.node == Terminal::True => {
+            if let Terminal::Verify(ref sub) = l.node {
+                ret.push(sub.node.clone());
+            }
+        }
+        // `thresh(n,...)` of `n` children becomes a chain of `and_b`s, and
+        // `thresh(1,...)` a chain of `or_b`s, saving the `k EQUAL`
+        Terminal::Thresh(k, ref subs) if k == subs.len() || k == 1 => {
+            let mut acc = Some(Arc::clone(&subs[0]));
+            for sub in &subs[1..] {
+                acc = acc.and_then(|acc| {
+                    child(if k == subs.len() {
+                        Terminal::AndB(acc, Arc::clone(sub))
+                    } else {
+                        Terminal::OrB(acc, Arc::clone(sub))
+                    })
+                });
+            }
+            if let Some(acc) = acc {
+                ret.push(acc.node.clone());
+            }
+        }
+        _ => {}
+    }
+    ret.into_iter()
+        .filter_map(|node| Miniscript::from_ast(node).ok())
+        .collect()
+}
+
+impl<Pk, Ctx> Miniscript<Pk, Ctx>
+where
+    Pk: MiniscriptKey + ToPublicKey,
+    Ctx: ScriptContext,
+{
+    /// Rewrite the Miniscript into an equivalent one which is cheaper to
+    /// use, e.g. `and_v(v:X,Y)` for `and_b(X,s:Y)` or `X` for `u:X`.
+    /// The result has the same base type, safety and non-malleability as
+    /// the Miniscript, and its fragments the same types (or subtypes) as
+    /// those they replace; it lifts to an equivalent semantic policy, and
+    /// has neither a larger script nor a larger maximum satisfaction, with
+    /// witness sizes counted as for segwit; if no rewrite achieves this,
+    /// or if the Miniscript is malleable, it is returned unchanged.
+    pub fn optimized(&self) -> Miniscript<Pk, Ctx> {
+        // A third party may change the witness of a malleable Miniscript,
+        // so its costs are not meaningful
+        if !self.ty.mall.non_malleable {
+            return self.clone();
+        }
+        match self.rewrite(&mut Optimizer) {
+            Ok(ms) => {
+                let ms = improve(ms, true);
+                if is_cheaper(&ms, self, true) {
+                    ms
+                } else {
+                    self.clone()
+                }
+            }
+            Err(..) => self.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniscript::Segwitv0;
+    use std::str::FromStr;
+    use DummyKey;
+
+    type DummyMs = Miniscript<DummyKey, Segwitv0>;
+
+    fn optimized(s: &str) -> String {
+        let ms = DummyMs::from_str(s).unwrap();
+        let opt = ms.optimized();
+        assert!(fits_top_level(&opt.ty, ms.ty));
+        assert!(opt.script_size() <= ms.script_size());
+        assert!(witness_sizes(&opt.node).0 <= witness_sizes(&ms.node).0);
+        assert!(opt.lift().is_equivalent(&ms.lift()).unwrap());
+        opt.to_string()
+    }
+
+    #[test]
+    fn optimize() {
+        // Already optimal
+        assert_eq!(optimized("and_v(v:pk(),pk())"), "and_v(v:pk(),pk())");
+        assert_eq!(optimized("or_d(pk(),pk())"), "or_d(pk(),pk())");
+
+        assert_eq!(optimized("and_b(pk(),s:pk())"), "and_v(v:pk(),pk())");
+        assert_eq!(optimized("and_b(pk(),a:pk())"), "and_v(v:pk(),pk())");
+        assert_eq!(optimized("thresh(2,pk(),s:pk())"), "and_v(v:pk(),pk())");
+        // The right child need not be dissatisfied when the left one is
+        // satisfied
+        assert_eq!(
+            optimized("or_b(and_b(pk(),s:pk()),a:or_b(pk(),a:pk()))"),
+            "or_d(and_b(pk(),s:pk()),or_b(pk(),a:pk()))"
+        );
+        assert_eq!(
+            optimized("thresh(1,pk(),s:pk(),s:pk())"),
+            "or_b(or_b(pk(),s:pk()),s:pk())"
+        );
+        // The dissatisfaction of a key costs less than a branch selector
+        assert_eq!(optimized("or_i(pk(),pk())"), "or_d(pk(),pk())");
+        // `t:` is only needed for its unit property, which is useless at the
+        // top level, but not where it makes its parent unit
+        assert_eq!(optimized("tv:older(144)"), "older(144)");
+        assert_eq!(
+            optimized("or_d(pk(),tv:older(144))"),
+            "or_d(pk(),tv:older(144))"
+        );
+        assert_eq!(optimized("and_b(pk(),au:pk())"), "and_v(v:pk(),pk())");
+    }
+
+    #[test]
+    fn optimize_undefined_sizes() {
+        // Children whose dissatisfaction size `max_satisfaction_size` cannot
+        // compute
+        let hash = "1111111111111111111111111111111111111111111111111111111111111111";
+        optimized(&format!("thresh(1,u:sha256({}))", hash));
+        optimized(&format!("l:thresh(1,sha256({}))", hash));
+        optimized("thresh(1,n:pk())");
+        optimized("thresh(1,u:0)");
+    }
+
+    #[test]
+    fn sizes() {
+        let sizes = |s: &str| witness_sizes(&DummyMs::from_str(s).unwrap().node);
+        assert_eq!(sizes("or_d(pk(),pk())"), (Some(74), Some(2)));
+        assert_eq!(sizes("u:pk()"), (Some(75), Some(3)));
+        assert_eq!(sizes("dv:older(1)"), (Some(2), Some(1)));
+        assert_eq!(sizes("andor(pk(),older(1),pk())"), (Some(74), Some(2)));
+        assert_eq!(
+            sizes("thresh(2,pk(),s:pk(),sln:older(1))"),
+            (Some(148), Some(4))
+        );
+        assert_eq!(sizes("and_v(v:pk(),older(1))"), (Some(73), None));
+    }
+}
@@ -0,0 +1,314 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Sanity Checks
+//!
+//! Parsing only requires a Miniscript to type check. A Miniscript may
+//! nonetheless be risky to use, e.g. if a third party can malleate its
+//! witness or satisfy it without any signature, or non-standard, e.g. if
+//! it is too large to be relayed. `Miniscript::sanity_check` reports such
+//! problems, each with the fragment causing it.
+//!
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::context::ScriptContextError;
+use super::decode::Terminal;
+use super::iter::PkPkh;
+use super::types::extra_props::MAX_OPS_PER_SCRIPT;
+use super::{Miniscript, ScriptContext};
+use {MiniscriptKey, ToPublicKey};
+
+/// A problem found by `Miniscript::sanity_check`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Issue {
+    /// A third party can change some satisfaction of the fragment without
+    /// invalidating it
+    Malleable,
+    /// The fragment is malleable under the script context, e.g. `pk_h`
+    /// under Legacy rules
+    ContextMalleable(ScriptContextError),
+    /// The fragment, and hence the whole script, can be satisfied without
+    /// any signature
+    NotSafe,
+    /// The fragment has a key, or the hash of a key, which already
+    /// appears earlier in the script
+    DuplicateKey,
+    /// Satisfying the script may execute this many non-push opcodes,
+    /// more than consensus rules allow
+    TooManyOps(usize),
+    /// The script has this many bytes, more than standardness rules allow
+    /// under its context
+    ScriptTooLarge(usize),
+    /// Some spending path of the fragment requires both a height and a
+    /// time based timelock, and so can never be used
+    MixedTimelocks,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Issue::Malleable => f.write_str("malleable"),
+            Issue::ContextMalleable(ref e) => fmt::Display::fmt(e, f),
+            Issue::NotSafe => f.write_str("satisfiable without a signature"),
+            Issue::DuplicateKey => f.write_str("duplicate key"),
+            Issue::TooManyOps(n) => write!(
+                f,
+                "{} opcodes executed, more than {}",
+                n, MAX_OPS_PER_SCRIPT
+            ),
+            Issue::ScriptTooLarge(n) => write!(f, "{} bytes is non-standard", n),
+            Issue::MixedTimelocks => f.write_str("mixes height and time timelocks"),
+        }
+    }
+}
+
+/// A problem found by `Miniscript::sanity_check` in one of its fragments
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Finding {
+    /// What is wrong
+    pub issue: Issue,
+    /// Indices, in `branches`, of the children leading from the root to
+    /// the offending fragment, as for the nodes of `Miniscript::iter`;
+    /// empty for issues of the whole script
+    pub path: Vec<usize>,
+    /// The offending fragment
+    pub fragment: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} in «{}»", self.issue, self.fragment)
+    }
+}
+
+impl<Pk: MiniscriptKey + ToPublicKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Check the Miniscript for constructs which type check but are risky
+    /// or non-standard: malleability, spending paths without signatures,
+    /// keys used more than once, too many opcodes, scripts too large for
+    /// their context and unusable mixes of timelocks. An empty result
+    /// means that none was found.
+    pub fn sanity_check(&self) -> Vec<Finding> {
+        let mut ret = vec![];
+
+        // Report malleability and mixed timelocks where they arise,
+        // rather than at every ancestor of the fragment causing them
+        for node in self.iter() {
+            let finding = |issue| Finding {
+                issue,
+                path: node.path.clone(),
+                fragment: node.ms.to_string(),
+            };
+            let subs = node.ms.branches();
+            if !node.ms.ty.mall.non_malleable && subs.iter().all(|sub| sub.ty.mall.non_malleable) {
+                ret.push(finding(Issue::Malleable));
+            }
+            if let Err(e) = Ctx::check_frag_non_malleable(&node.ms.node) {
+                ret.push(finding(Issue::ContextMalleable(e)));
+            }
+            if node.ms.has_mixed_timelocks() && !subs.iter().any(|sub| sub.has_mixed_timelocks()) {
+                ret.push(finding(Issue::MixedTimelocks));
+            }
+        }
+
+        if !self.ty.mall.safe {
+            not_safe(self, vec![], &mut ret);
+        }
+
+        let mut seen = BTreeSet::new();
+        for node in self.iter().filter(|node| node.ms.branches().is_empty()) {
+            let mut duplicate = false;
+            for pk in node.ms.iter_pk_pkh() {
+                let pkh = match pk {
+                    PkPkh::PlainPubkey(pk) => pk.to_pubkeyhash(),
+                    PkPkh::HashedPubkey(pkh) => pkh,
+                };
+                duplicate |= !seen.insert(pkh);
+            }
+            if duplicate {
+                ret.push(Finding {
+                    issue: Issue::DuplicateKey,
+                    path: node.path,
+                    fragment: node.ms.to_string(),
+                });
+            }
+        }
+
+        let whole = |issue| Finding {
+            issue,
+            path: vec![],
+            fragment: self.to_string(),
+        };
+        if let Some(ops) = self.ext.ops_count_sat {
+            if ops > MAX_OPS_PER_SCRIPT {
+                ret.push(whole(Issue::TooManyOps(ops)));
+            }
+        }
+        let size = self.script_size();
+        if size > Ctx::max_standard_script_size() {
+            ret.push(whole(Issue::ScriptTooLarge(size)));
+        }
+        ret
+    }
+}
+
+/// Report the fragments of `ms`, an unsafe fragment at `path`, which can
+/// be satisfied without a signature: descend through wrappers and into
+/// the unsafe alternatives of disjunctions, stopping at any other
+/// fragment, whose satisfaction needs its unsafe children together
+fn not_safe<Pk, Ctx>(ms: &Miniscript<Pk, Ctx>, path: Vec<usize>, ret: &mut Vec<Finding>)
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+{
+    let descend = match ms.node {
+        Terminal::Alt(..)
+        | Terminal::Swap(..)
+        | Terminal::Check(..)
+        | Terminal::DupIf(..)
+        | Terminal::Verify(..)
+        | Terminal::NonZero(..)
+        | Terminal::ZeroNotEqual(..)
+        | Terminal::OrB(..)
+        | Terminal::OrD(..)
+        | Terminal::OrC(..)
+        | Terminal::OrI(..) => true,
+        // Unsafe through its `else` branch alone
+        Terminal::AndOr(ref a, ref b, _) => a.ty.mall.safe || b.ty.mall.safe,
+        _ => false,
+    };
+    let subs = ms.branches();
+    let unsafe_subs: Vec<_> = subs
+        .iter()
+        .enumerate()
+        .filter(|&(_, sub)| !sub.ty.mall.safe)
+        .collect();
+    if !descend || unsafe_subs.is_empty() {
+        ret.push(Finding {
+            issue: Issue::NotSafe,
+            path,
+            fragment: ms.to_string(),
+        });
+        return;
+    }
+    for (i, sub) in unsafe_subs {
+        // Of the children of an `andor`, only its `else` branch
+        if let Terminal::AndOr(..) = ms.node {
+            if i < 2 {
+                continue;
+            }
+        }
+        let mut path = path.clone();
+        path.push(i);
+        not_safe(sub, path, ret);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::PublicKey;
+    use miniscript::tests::pubkeys;
+    use miniscript::{Legacy, Segwitv0};
+    use std::str::FromStr;
+    use DummyKey;
+
+    fn check(s: &str) -> Vec<(Issue, Vec<usize>, String)> {
+        Miniscript::<DummyKey, Segwitv0>::from_str(s)
+            .unwrap()
+            .sanity_check()
+            .into_iter()
+            .map(|f| (f.issue, f.path, f.fragment))
+            .collect()
+    }
+
+    #[test]
+    fn sanity_check() {
+        let keys = pubkeys(3);
+        let ms = Miniscript::<PublicKey, Segwitv0>::from_str(&format!(
+            "or_d(pk({}),and_v(v:pk({}),older(144)))",
+            keys[0], keys[1]
+        ))
+        .unwrap();
+        assert_eq!(ms.sanity_check(), vec![]);
+
+        // A timelock alone suffices in the second branch
+        assert_eq!(
+            check("or_d(pk(),older(144))"),
+            vec![(Issue::NotSafe, vec![1], "older(144)".to_owned())]
+        );
+        assert_eq!(
+            check("and_v(v:older(144),after(100))"),
+            vec![(
+                Issue::NotSafe,
+                vec![],
+                "and_v(v:older(144),after(100))".to_owned()
+            )]
+        );
+
+        // Anyone can satisfy `older` with a different dissatisfaction of
+        // the hash, since neither needs a signature
+        let findings = check(
+            "or_i(and_v(v:pk(),older(144)),\
+             or_d(sha256(1111111111111111111111111111111111111111111111111111111111111111),\
+             older(144)))",
+        );
+        assert_eq!(findings[0].0, Issue::Malleable);
+        assert_eq!(findings[0].1, vec![1]);
+
+        let findings = check("and_v(v:pk(),and_v(v:older(144),older(4194305)))");
+        assert_eq!(
+            findings,
+            vec![(
+                Issue::MixedTimelocks,
+                vec![1],
+                "and_v(v:older(144),older(4194305))".to_owned()
+            )]
+        );
+
+        let ms = Miniscript::<PublicKey, Legacy>::from_str(&format!(
+            "or_i(pk({}),multi(1,{},{}))",
+            keys[0], keys[1], keys[0]
+        ))
+        .unwrap();
+        let findings = ms.sanity_check();
+        assert_eq!(
+            findings[0].issue,
+            Issue::ContextMalleable(ScriptContextError::MalleableOrI)
+        );
+        assert_eq!(findings[1].issue, Issue::DuplicateKey);
+        assert_eq!(findings[1].path, vec![1]);
+        assert_eq!(
+            findings[1].to_string(),
+            format!("duplicate key in «multi(1,{},{})»", keys[1], keys[0])
+        );
+        assert_eq!(findings.len(), 2);
+
+        // 21 `multi`s of 20 keys each are too large for P2SH
+        let multi = "multi(1,,,,,,,,,,,,,,,,,,,,)";
+        let mut big = multi.to_owned();
+        for _ in 0..20 {
+            big = format!("and_v(v:{},{})", multi, big);
+        }
+        let ms = Miniscript::<DummyKey, Legacy>::from_str(&big).unwrap();
+        let findings = ms.sanity_check();
+        assert!(findings
+            .iter()
+            .any(|f| f.issue == Issue::TooManyOps(21 * 21) && f.path.is_empty()));
+        assert!(findings
+            .iter()
+            .any(|f| f.issue == Issue::ScriptTooLarge(ms.script_size())));
+    }
+}
@@ -27,6 +27,7 @@ use {MiniscriptKey, ToPublicKey};
 
 use ScriptContext;
 use Terminal;
+use {AbsLockTime, RelLockTime};
 
 /// Type alias for a signature/hashtype pair
 pub type BitcoinSig = (secp256k1::Signature, bitcoin::SigHashType);
@@ -75,12 +76,12 @@ pub trait Satisfier<Pk: MiniscriptKey> {
     }
 
     /// Assert whether an relative locktime is satisfied
-    fn check_older(&self, _: u32) -> bool {
+    fn check_older(&self, _: RelLockTime) -> bool {
         false
     }
 
     /// Assert whether a absolute locktime is satisfied
-    fn check_after(&self, _: u32) -> bool {
+    fn check_after(&self, _: AbsLockTime) -> bool {
         false
     }
 }
@@ -88,23 +89,23 @@ pub trait Satisfier<Pk: MiniscriptKey> {
 // Allow use of `()` as a "no conditions available" satisfier
 impl<Pk: MiniscriptKey> Satisfier<Pk> for () {}
 
-/// Newtype around `u32` which implements `Satisfier` using `n` as an
-/// relative locktime
+/// Newtype around `u32` which implements `Satisfier` using `n` as the
+/// `nSequence` of the spending input, i.e. as a relative locktime
 pub struct Older(pub u32);
 
 impl<Pk: MiniscriptKey> Satisfier<Pk> for Older {
-    fn check_older(&self, n: u32) -> bool {
-        n <= self.0
+    fn check_older(&self, n: RelLockTime) -> bool {
+        n.is_satisfied_by(self.0)
     }
 }
 
-/// Newtype around `u32` which implements `Satisfier` using `n` as an
-/// absolute locktime
+/// Newtype around `u32` which implements `Satisfier` using `n` as the
+/// `nLockTime` of the spending transaction, i.e. as an absolute locktime
 pub struct After(pub u32);
 
 impl<Pk: MiniscriptKey> Satisfier<Pk> for After {
-    fn check_after(&self, n: u32) -> bool {
-        n <= self.0
+    fn check_after(&self, n: AbsLockTime) -> bool {
+        n.is_satisfied_by(self.0)
     }
 }
 
@@ -161,11 +162,11 @@ impl<'a, Pk: MiniscriptKey, S: Satisfier<Pk>> Satisfier<Pk> for &'a S {
         (**self).lookup_hash160(h)
     }
 
-    fn check_older(&self, t: u32) -> bool {
+    fn check_older(&self, t: RelLockTime) -> bool {
         (**self).check_older(t)
     }
 
-    fn check_after(&self, t: u32) -> bool {
+    fn check_after(&self, t: AbsLockTime) -> bool {
         (**self).check_after(t)
     }
 }
@@ -199,11 +200,11 @@ impl<'a, Pk: MiniscriptKey, S: Satisfier<Pk>> Satisfier<Pk> for &'a mut S {
         (**self).lookup_hash160(h)
     }
 
-    fn check_older(&self, t: u32) -> bool {
+    fn check_older(&self, t: RelLockTime) -> bool {
         (**self).check_older(t)
     }
 
-    fn check_after(&self, t: u32) -> bool {
+    fn check_after(&self, t: AbsLockTime) -> bool {
         (**self).check_after(t)
     }
 }
@@ -292,7 +293,7 @@ macro_rules! impl_tuple_satisfier {
                 None
             }
 
-            fn check_older(&self, n: u32) -> bool {
+            fn check_older(&self, n: RelLockTime) -> bool {
                 let &($(ref $ty,)*) = self;
                 $(
                     if $ty.check_older(n) {
@@ -302,7 +303,7 @@ macro_rules! impl_tuple_satisfier {
                 false
             }
 
-            fn check_after(&self, n: u32) -> bool {
+            fn check_after(&self, n: AbsLockTime) -> bool {
                 let &($(ref $ty,)*) = self;
                 $(
                     if $ty.check_after(n) {
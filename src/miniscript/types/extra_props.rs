@@ -6,9 +6,88 @@ use script_num_size;
 use std::cmp;
 use MiniscriptKey;
 use Terminal;
+use {AbsLockTime, RelLockTime};
 
 pub const MAX_OPS_PER_SCRIPT: usize = 201;
 
+/// Which kinds of timelock a fragment may require, and whether any of its
+/// spending paths requires timelocks of different units at once. Such a
+/// path can never be used, since a transaction has a single `nLockTime`
+/// and each input a single `nSequence`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub struct TimeLockInfo {
+    /// Whether some spending path has an `older` counting blocks
+    pub csv_with_height: bool,
+    /// Whether some spending path has an `older` counting 512-second
+    /// intervals
+    pub csv_with_time: bool,
+    /// Whether some spending path has an `after` block height
+    pub cltv_with_height: bool,
+    /// Whether some spending path has an `after` UNIX timestamp
+    pub cltv_with_time: bool,
+    /// Whether some single spending path mixes heights and times
+    pub contains_combination: bool,
+}
+
+impl TimeLockInfo {
+    /// Timelock information of an `after` fragment
+    pub fn from_after(t: AbsLockTime) -> TimeLockInfo {
+        TimeLockInfo {
+            cltv_with_height: t.is_block_height(),
+            cltv_with_time: t.is_block_time(),
+            ..TimeLockInfo::default()
+        }
+    }
+
+    /// Timelock information of an `older` fragment
+    pub fn from_older(t: RelLockTime) -> TimeLockInfo {
+        TimeLockInfo {
+            csv_with_height: t.is_height_locked(),
+            csv_with_time: t.is_time_locked(),
+            ..TimeLockInfo::default()
+        }
+    }
+
+    /// Whether some spending path can never be used because it mixes
+    /// heights and times
+    pub fn contains_unspendable_path(self) -> bool {
+        self.contains_combination
+    }
+
+    /// Timelock information of a conjunction of two fragments
+    pub fn combine_and(a: TimeLockInfo, b: TimeLockInfo) -> TimeLockInfo {
+        TimeLockInfo::combine_threshold(2, vec![a, b])
+    }
+
+    /// Timelock information of a disjunction of two fragments
+    pub fn combine_or(a: TimeLockInfo, b: TimeLockInfo) -> TimeLockInfo {
+        TimeLockInfo::combine_threshold(1, vec![a, b])
+    }
+
+    /// Timelock information of a `k`-of-n threshold of fragments. Unless
+    /// `k` is 1, any two of them may be used in the same spending path.
+    pub fn combine_threshold<I>(k: usize, subs: I) -> TimeLockInfo
+    where
+        I: IntoIterator<Item = TimeLockInfo>,
+    {
+        let mut ret = TimeLockInfo::default();
+        for sub in subs {
+            if k > 1 {
+                ret.contains_combination |= (ret.csv_with_height && sub.csv_with_time)
+                    || (ret.csv_with_time && sub.csv_with_height)
+                    || (ret.cltv_with_height && sub.cltv_with_time)
+                    || (ret.cltv_with_time && sub.cltv_with_height);
+            }
+            ret.csv_with_height |= sub.csv_with_height;
+            ret.csv_with_time |= sub.csv_with_time;
+            ret.cltv_with_height |= sub.cltv_with_height;
+            ret.cltv_with_time |= sub.cltv_with_time;
+            ret.contains_combination |= sub.contains_combination;
+        }
+        ret
+    }
+}
+
 /// Structure representing the extra type properties of a fragment which are
 /// relevant to legacy(pre-segwit) safety and fee estimation. If a fragment is
 /// used in pre-segwit transactions it will only be malleable but still is
@@ -25,6 +104,8 @@ pub struct ExtData {
     pub ops_count_sat: Option<usize>,
     /// The worst case ops-count for dissatisfying this Miniscript fragment.
     pub ops_count_nsat: Option<usize>,
+    /// The kinds of timelock used by this Miniscript fragment.
+    pub timelock_info: TimeLockInfo,
 }
 
 impl Property for ExtData {
@@ -39,6 +120,7 @@ impl Property for ExtData {
             ops_count_static: 0,
             ops_count_sat: Some(0),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -49,6 +131,7 @@ impl Property for ExtData {
             ops_count_static: 0,
             ops_count_sat: None,
             ops_count_nsat: Some(0),
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -59,6 +142,7 @@ impl Property for ExtData {
             ops_count_static: 0,
             ops_count_sat: Some(0),
             ops_count_nsat: Some(0),
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -69,6 +153,7 @@ impl Property for ExtData {
             ops_count_static: 3,
             ops_count_sat: Some(3),
             ops_count_nsat: Some(3),
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -85,6 +170,7 @@ impl Property for ExtData {
             ops_count_static: 1,
             ops_count_sat: Some(n + 1),
             ops_count_nsat: Some(n + 1),
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -100,6 +186,7 @@ impl Property for ExtData {
             ops_count_static: 4,
             ops_count_sat: Some(4),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -110,6 +197,7 @@ impl Property for ExtData {
             ops_count_static: 4,
             ops_count_sat: Some(4),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -120,6 +208,7 @@ impl Property for ExtData {
             ops_count_static: 4,
             ops_count_sat: Some(4),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -130,6 +219,7 @@ impl Property for ExtData {
             ops_count_static: 4,
             ops_count_sat: Some(4),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::default(),
         }
     }
 
@@ -140,8 +230,24 @@ impl Property for ExtData {
             ops_count_static: 1,
             ops_count_sat: Some(1),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::default(),
+        }
+    }
+
+    fn from_after(t: AbsLockTime) -> Self {
+        ExtData {
+            timelock_info: TimeLockInfo::from_after(t),
+            ..Self::from_time(t.to_consensus_u32())
+        }
+    }
+
+    fn from_older(t: RelLockTime) -> Self {
+        ExtData {
+            timelock_info: TimeLockInfo::from_older(t),
+            ..Self::from_time(t.to_consensus_u32())
         }
     }
+
     fn cast_alt(self) -> Result<Self, ErrorKind> {
         Ok(ExtData {
             pk_cost: self.pk_cost + 2,
@@ -149,6 +255,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 2,
             ops_count_sat: self.ops_count_sat.map(|x| x + 2),
             ops_count_nsat: self.ops_count_nsat.map(|x| x + 2),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -159,6 +266,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 1,
             ops_count_sat: self.ops_count_sat.map(|x| x + 1),
             ops_count_nsat: self.ops_count_nsat.map(|x| x + 1),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -169,6 +277,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 1,
             ops_count_sat: self.ops_count_sat.map(|x| x + 1),
             ops_count_nsat: self.ops_count_nsat.map(|x| x + 1),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -179,6 +288,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 3,
             ops_count_sat: self.ops_count_sat.map(|x| x + 3),
             ops_count_nsat: Some(self.ops_count_static + 3),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -190,6 +300,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + verify_cost,
             ops_count_sat: self.ops_count_sat.map(|x| x + verify_cost),
             ops_count_nsat: None,
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -200,6 +311,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 4,
             ops_count_sat: self.ops_count_sat.map(|x| x + 4),
             ops_count_nsat: Some(self.ops_count_static + 4),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -210,6 +322,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 1,
             ops_count_sat: self.ops_count_sat.map(|x| x + 1),
             ops_count_nsat: self.ops_count_nsat.map(|x| x + 1),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -220,6 +333,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static,
             ops_count_sat: self.ops_count_sat,
             ops_count_nsat: None,
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -235,6 +349,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 3,
             ops_count_sat: self.ops_count_sat.map(|x| x + 3),
             ops_count_nsat: Some(self.ops_count_static + 3),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -245,6 +360,7 @@ impl Property for ExtData {
             ops_count_static: self.ops_count_static + 3,
             ops_count_sat: self.ops_count_sat.map(|x| x + 3),
             ops_count_nsat: Some(self.ops_count_static + 3),
+            timelock_info: self.timelock_info,
         })
     }
 
@@ -259,6 +375,7 @@ impl Property for ExtData {
             ops_count_nsat: l
                 .ops_count_nsat
                 .and_then(|x| r.ops_count_nsat.map(|y| x + y + 1)),
+            timelock_info: TimeLockInfo::combine_and(l.timelock_info, r.timelock_info),
         })
     }
 
@@ -269,6 +386,7 @@ impl Property for ExtData {
             ops_count_static: l.ops_count_static + r.ops_count_static,
             ops_count_sat: l.ops_count_sat.and_then(|x| r.ops_count_sat.map(|y| x + y)),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::combine_and(l.timelock_info, r.timelock_info),
         })
     }
 
@@ -286,6 +404,7 @@ impl Property for ExtData {
             ops_count_nsat: l
                 .ops_count_nsat
                 .and_then(|x| r.ops_count_nsat.map(|y| x + y + 1)),
+            timelock_info: TimeLockInfo::combine_or(l.timelock_info, r.timelock_info),
         })
     }
 
@@ -302,6 +421,7 @@ impl Property for ExtData {
             ops_count_nsat: l
                 .ops_count_nsat
                 .and_then(|x| r.ops_count_nsat.map(|y| x + y + 3)),
+            timelock_info: TimeLockInfo::combine_or(l.timelock_info, r.timelock_info),
         })
     }
 
@@ -316,6 +436,7 @@ impl Property for ExtData {
                     .and_then(|x| l.ops_count_nsat.map(|y| y + x + 2)),
             ),
             ops_count_nsat: None,
+            timelock_info: TimeLockInfo::combine_or(l.timelock_info, r.timelock_info),
         })
     }
 
@@ -333,6 +454,7 @@ impl Property for ExtData {
                 (_, Some(x)) | (Some(x), _) => Some(x + 3),
                 (None, None) => None,
             },
+            timelock_info: TimeLockInfo::combine_or(l.timelock_info, r.timelock_info),
         })
     }
 
@@ -350,6 +472,10 @@ impl Property for ExtData {
             ops_count_nsat: c
                 .ops_count_nsat
                 .and_then(|z| a.ops_count_nsat.map(|x| x + b.ops_count_static + z + 3)),
+            timelock_info: TimeLockInfo::combine_or(
+                TimeLockInfo::combine_and(a.timelock_info, b.timelock_info),
+                c.timelock_info,
+            ),
         })
     }
 
@@ -364,9 +490,11 @@ impl Property for ExtData {
         let mut ops_count_nsat = Some(0);
         let mut ops_count_sat = Some(0);
         let mut sat_count = 0;
+        let mut timelocks = Vec::with_capacity(n);
         for i in 0..n {
             let sub = sub_ck(i)?;
             pk_cost += sub.pk_cost;
+            timelocks.push(sub.timelock_info);
             ops_count_static += sub.ops_count_static;
             match (sub.ops_count_sat, sub.ops_count_nsat) {
                 (Some(x), Some(y)) => {
@@ -402,6 +530,7 @@ impl Property for ExtData {
             ops_count_sat: ops_count_sat
                 .map(|x: usize| (x + (n - 1) + 1 + (sum + ops_count_nsat_sum as i32) as usize)), //adds and equal
             ops_count_nsat: ops_count_nsat.map(|x| x + (n - 1) + 1), //adds and equal
+            timelock_info: TimeLockInfo::combine_threshold(k, timelocks),
         })
     }
 
@@ -445,7 +574,7 @@ impl Property for ExtData {
             }
             Terminal::After(t) => {
                 // FIXME check if t > 2^31 - 1
-                if t == 0 {
+                if t.to_consensus_u32() == 0 {
                     return Err(Error {
                         fragment: fragment.clone(),
                         error: ErrorKind::ZeroTime,
@@ -454,12 +583,18 @@ impl Property for ExtData {
                 Ok(Self::from_after(t))
             }
             Terminal::Older(t) => {
-                if t == 0 {
+                if t.value() == 0 {
                     return Err(Error {
                         fragment: fragment.clone(),
                         error: ErrorKind::ZeroTime,
                     });
                 }
+                if t.is_disabled() {
+                    return Err(Error {
+                        fragment: fragment.clone(),
+                        error: ErrorKind::DisabledTime,
+                    });
+                }
                 Ok(Self::from_older(t))
             }
             Terminal::Sha256(..) => Ok(Self::from_sha256()),
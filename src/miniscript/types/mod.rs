@@ -24,6 +24,7 @@ pub use self::malleability::{Dissat, Malleability};
 use super::ScriptContext;
 use MiniscriptKey;
 use Terminal;
+use {AbsLockTime, RelLockTime};
 
 /// None-returning function to help type inference when we need a
 /// closure that simply returns `None`
@@ -36,6 +37,9 @@ fn return_none<T>(_: usize) -> Option<T> {
 pub enum ErrorKind {
     /// Relative or absolute timelock had a time value of 0
     ZeroTime,
+    /// Relative timelock had the BIP 68 disable flag set, so that it
+    /// would not be enforced
+    DisabledTime,
     /// Passed a `z` arguement to a `d` wrapeer when `z` was expected
     NonZeroDupIf,
     /// Multisignature or threshold policy had a `k` value of 0
@@ -110,6 +114,11 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> fmt::Display for Error<Pk, Ctx> {
                 "fragment «{}» represents a 0-valued timelock (use `1` instead)",
                 self.fragment,
             ),
+            ErrorKind::DisabledTime => write!(
+                f,
+                "fragment «{}» represents a relative timelock with the disable flag (1 << 31) set",
+                self.fragment,
+            ),
             ErrorKind::NonZeroDupIf => write!(
                 f,
                 "fragment «{}» represents needs to be `z`, needs to consume zero elements from the stack",
@@ -233,6 +242,52 @@ impl Type {
         return false;
     }
 }
+
+impl fmt::Display for Base {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Base::B => "B",
+            Base::K => "K",
+            Base::V => "V",
+            Base::W => "W",
+        })
+    }
+}
+
+/// Writes the type in the notation of the Miniscript specification: the
+/// base type followed by a letter for each property the fragment has,
+/// among `z`ero-arg, `o`ne-arg, `n`onzero, `d`issatisfiable, `u`nit,
+/// `s`afe, `f`orced, `e`xpressive and `m` (has a non-malleable
+/// satisfaction), e.g. `Bondusem` for `pk(A)`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.corr.base)?;
+        let properties = [
+            ('z', self.corr.input == Input::Zero),
+            (
+                'o',
+                self.corr.input == Input::One || self.corr.input == Input::OneNonZero,
+            ),
+            (
+                'n',
+                self.corr.input == Input::OneNonZero || self.corr.input == Input::AnyNonZero,
+            ),
+            ('d', self.corr.dissatisfiable),
+            ('u', self.corr.unit),
+            ('s', self.mall.safe),
+            ('f', self.mall.dissat == Dissat::None),
+            ('e', self.mall.dissat == Dissat::Unique),
+            ('m', self.mall.non_malleable),
+        ];
+        for &(ch, has) in &properties {
+            if has {
+                fmt::Write::write_char(f, ch)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Trait representing a type property, which defines how the property
 /// propagates from terminals to the root of a Miniscript
 pub trait Property: Sized {
@@ -287,16 +342,16 @@ pub trait Property: Sized {
     /// Type property of a timelock
     fn from_time(t: u32) -> Self;
 
-    /// Type property of a relative timelock. Default implementation simply
+    /// Type property of an absolute timelock. Default implementation simply
     /// passes through to `from_time`
-    fn from_after(t: u32) -> Self {
-        Self::from_time(t)
+    fn from_after(t: AbsLockTime) -> Self {
+        Self::from_time(t.to_consensus_u32())
     }
 
-    /// Type property of an absolute timelock. Default implementation simply
+    /// Type property of a relative timelock. Default implementation simply
     /// passes through to `from_time`
-    fn from_older(t: u32) -> Self {
-        Self::from_time(t)
+    fn from_older(t: RelLockTime) -> Self {
+        Self::from_time(t.to_consensus_u32())
     }
 
     /// Cast using the `Alt` wrapper
@@ -413,7 +468,7 @@ pub trait Property: Sized {
                 Ok(Self::from_multi(k, pks.len()))
             }
             Terminal::After(t) => {
-                if t == 0 {
+                if t.to_consensus_u32() == 0 {
                     return Err(Error {
                         fragment: fragment.clone(),
                         error: ErrorKind::ZeroTime,
@@ -422,13 +477,18 @@ pub trait Property: Sized {
                 Ok(Self::from_after(t))
             }
             Terminal::Older(t) => {
-                // FIXME check if t > 2^31 - 1
-                if t == 0 {
+                if t.value() == 0 {
                     return Err(Error {
                         fragment: fragment.clone(),
                         error: ErrorKind::ZeroTime,
                     });
                 }
+                if t.is_disabled() {
+                    return Err(Error {
+                        fragment: fragment.clone(),
+                        error: ErrorKind::DisabledTime,
+                    });
+                }
                 Ok(Self::from_older(t))
             }
             Terminal::Sha256(..) => Ok(Self::from_sha256()),
@@ -601,14 +661,14 @@ impl Property for Type {
         }
     }
 
-    fn from_after(t: u32) -> Self {
+    fn from_after(t: AbsLockTime) -> Self {
         Type {
             corr: Property::from_after(t),
             mall: Property::from_after(t),
         }
     }
 
-    fn from_older(t: u32) -> Self {
+    fn from_older(t: RelLockTime) -> Self {
         Type {
             corr: Property::from_older(t),
             mall: Property::from_older(t),
@@ -791,7 +851,7 @@ impl Property for Type {
             }
             Terminal::After(t) => {
                 // FIXME check if t > 2^31 - 1
-                if t == 0 {
+                if t.to_consensus_u32() == 0 {
                     return Err(Error {
                         fragment: fragment.clone(),
                         error: ErrorKind::ZeroTime,
@@ -800,12 +860,18 @@ impl Property for Type {
                 Ok(Self::from_after(t))
             }
             Terminal::Older(t) => {
-                if t == 0 {
+                if t.value() == 0 {
                     return Err(Error {
                         fragment: fragment.clone(),
                         error: ErrorKind::ZeroTime,
                     });
                 }
+                if t.is_disabled() {
+                    return Err(Error {
+                        fragment: fragment.clone(),
+                        error: ErrorKind::DisabledTime,
+                    });
+                }
                 Ok(Self::from_older(t))
             }
             Terminal::Sha256(..) => Ok(Self::from_sha256()),
@@ -0,0 +1,420 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Wrapper Inference
+//!
+//! Which wrappers a fragment needs depends on the types of its children
+//! and on what its parent expects, so building a Miniscript in code means
+//! working out `a:`, `s:`, `c:`, `v:` and the like by hand. An `Unwrapped`
+//! tree leaves them out, and `Unwrapped::build` finds them, trying the
+//! same casts as the compiler does on its compilations.
+//!
+
+use std::collections::vec_deque::VecDeque;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use super::decode::Terminal;
+use super::types::{Base, ErrorKind, Property, Type};
+use super::{Miniscript, ScriptContext};
+use {Error, MiniscriptKey};
+
+/// A wrapper, including `t:`, `l:` and `u:`, which are not wrappers in
+/// the script but are written as such
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum Wrapper {
+    /// `c:`
+    Check,
+    /// `d:`
+    DupIf,
+    /// `l:`
+    Likely,
+    /// `u:`
+    Unlikely,
+    /// `v:`
+    Verify,
+    /// `j:`
+    NonZero,
+    /// `t:`
+    True,
+    /// `s:`
+    Swap,
+    /// `a:`
+    Alt,
+    /// `n:`
+    ZeroNotEqual,
+}
+
+impl Wrapper {
+    /// All wrappers
+    pub(crate) const ALL: [Wrapper; 10] = [
+        Wrapper::Check,
+        Wrapper::DupIf,
+        Wrapper::Likely,
+        Wrapper::Unlikely,
+        Wrapper::Verify,
+        Wrapper::NonZero,
+        Wrapper::True,
+        Wrapper::Swap,
+        Wrapper::Alt,
+        Wrapper::ZeroNotEqual,
+    ];
+
+    /// The fragment made by wrapping `ms`
+    fn node<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        self,
+        ms: Arc<Miniscript<Pk, Ctx>>,
+    ) -> Terminal<Pk, Ctx> {
+        match self {
+            Wrapper::Check => Terminal::Check(ms),
+            Wrapper::DupIf => Terminal::DupIf(ms),
+            Wrapper::Likely => Terminal::OrI(
+                Arc::new(Miniscript::from_ast(Terminal::False).expect("False Miniscript creation")),
+                ms,
+            ),
+            Wrapper::Unlikely => Terminal::OrI(
+                ms,
+                Arc::new(Miniscript::from_ast(Terminal::False).expect("False Miniscript creation")),
+            ),
+            Wrapper::Verify => Terminal::Verify(ms),
+            Wrapper::NonZero => Terminal::NonZero(ms),
+            Wrapper::True => Terminal::AndV(
+                ms,
+                Arc::new(Miniscript::from_ast(Terminal::True).expect("True Miniscript creation")),
+            ),
+            Wrapper::Swap => Terminal::Swap(ms),
+            Wrapper::Alt => Terminal::Alt(ms),
+            Wrapper::ZeroNotEqual => Terminal::ZeroNotEqual(ms),
+        }
+    }
+
+    /// The type or extra properties, of any kind, of a wrapped fragment,
+    /// from those of the fragment
+    pub(crate) fn cast_property<P: Property>(self, prop: P) -> Result<P, ErrorKind> {
+        match self {
+            Wrapper::Check => prop.cast_check(),
+            Wrapper::DupIf => prop.cast_dupif(),
+            Wrapper::Likely => prop.cast_likely(),
+            Wrapper::Unlikely => prop.cast_unlikely(),
+            Wrapper::Verify => prop.cast_verify(),
+            Wrapper::NonZero => prop.cast_nonzero(),
+            Wrapper::True => prop.cast_true(),
+            Wrapper::Swap => prop.cast_swap(),
+            Wrapper::Alt => prop.cast_alt(),
+            Wrapper::ZeroNotEqual => prop.cast_zeronotequal(),
+        }
+    }
+
+    /// Wrap `ms`, computing the type and extra properties of the result
+    /// from those of `ms` rather than type checking it again
+    pub(crate) fn cast<Pk: MiniscriptKey, Ctx: ScriptContext>(
+        self,
+        ms: &Arc<Miniscript<Pk, Ctx>>,
+    ) -> Result<Miniscript<Pk, Ctx>, ErrorKind> {
+        Ok(Miniscript {
+            ty: self.cast_property(ms.ty)?,
+            ext: self.cast_property(ms.ext)?,
+            node: self.node(Arc::clone(ms)),
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// A Miniscript without wrappers, to be built by `Unwrapped::build`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Unwrapped<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    /// A fragment used as is, apart from wrappers around it: a leaf such
+    /// as `pk_k`, `older` or `multi`, or a fragment whose children are
+    /// already wrapped
+    Fragment(Terminal<Pk, Ctx>),
+    /// `and_v`
+    AndV(Box<Unwrapped<Pk, Ctx>>, Box<Unwrapped<Pk, Ctx>>),
+    /// `and_b`
+    AndB(Box<Unwrapped<Pk, Ctx>>, Box<Unwrapped<Pk, Ctx>>),
+    /// `andor`
+    AndOr(
+        Box<Unwrapped<Pk, Ctx>>,
+        Box<Unwrapped<Pk, Ctx>>,
+        Box<Unwrapped<Pk, Ctx>>,
+    ),
+    /// `or_b`
+    OrB(Box<Unwrapped<Pk, Ctx>>, Box<Unwrapped<Pk, Ctx>>),
+    /// `or_d`
+    OrD(Box<Unwrapped<Pk, Ctx>>, Box<Unwrapped<Pk, Ctx>>),
+    /// `or_c`
+    OrC(Box<Unwrapped<Pk, Ctx>>, Box<Unwrapped<Pk, Ctx>>),
+    /// `or_i`
+    OrI(Box<Unwrapped<Pk, Ctx>>, Box<Unwrapped<Pk, Ctx>>),
+    /// `thresh`
+    Thresh(usize, Vec<Unwrapped<Pk, Ctx>>),
+}
+
+/// A wrapped version of a fragment, with how many wrappers it has in all
+#[derive(Clone)]
+struct Variant<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    ms: Arc<Miniscript<Pk, Ctx>>,
+    wrappers: usize,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Variant<Pk, Ctx> {
+    /// Order of preference: non-malleable first, then with the fewest
+    /// wrappers, then the smallest
+    fn rank(&self) -> (bool, usize, usize) {
+        (
+            !self.ms.ty.mall.non_malleable,
+            self.wrappers,
+            self.ms.ext.pk_cost,
+        )
+    }
+}
+
+/// The best wrapped versions of a fragment, one per type
+type Variants<Pk, Ctx> = BTreeMap<Type, Variant<Pk, Ctx>>;
+
+/// Insert `variant` and all its wrapped versions into `map`, wherever
+/// they are preferable to the version of the same type already there
+fn insert_closure<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    map: &mut Variants<Pk, Ctx>,
+    variant: Variant<Pk, Ctx>,
+) {
+    let mut queue = VecDeque::new();
+    queue.push_back(variant);
+    while let Some(current) = queue.pop_front() {
+        let better = match map.get(&current.ms.ty) {
+            Some(existing) => current.rank() < existing.rank(),
+            None => true,
+        };
+        if !better {
+            continue;
+        }
+        for wrapper in &Wrapper::ALL {
+            if let Ok(ms) = wrapper.cast(&current.ms) {
+                queue.push_back(Variant {
+                    ms: Arc::new(ms),
+                    wrappers: current.wrappers + 1,
+                });
+            }
+        }
+        map.insert(current.ms.ty, current);
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Unwrapped<Pk, Ctx> {
+    /// Name of the fragment, for error messages
+    fn name(&self) -> &'static str {
+        match *self {
+            Unwrapped::Fragment(..) => "fragment",
+            Unwrapped::AndV(..) => "and_v",
+            Unwrapped::AndB(..) => "and_b",
+            Unwrapped::AndOr(..) => "andor",
+            Unwrapped::OrB(..) => "or_b",
+            Unwrapped::OrD(..) => "or_d",
+            Unwrapped::OrC(..) => "or_c",
+            Unwrapped::OrI(..) => "or_i",
+            Unwrapped::Thresh(..) => "thresh",
+        }
+    }
+
+    /// Find wrappers for every fragment which make the whole a valid
+    /// top-level Miniscript. Where several sets of wrappers do, the
+    /// result is non-malleable if possible, and otherwise has as few
+    /// wrappers as possible.
+    pub fn build(&self) -> Result<Miniscript<Pk, Ctx>, Error> {
+        self.variants()?
+            .values()
+            .filter(|variant| variant.ms.ty.corr.base == Base::B)
+            .min_by_key(|variant| variant.rank())
+            .map(|variant| (*variant.ms).clone())
+            .ok_or_else(|| Error::TypeCheck("no wrappers make a B expression".to_owned()))
+    }
+
+    /// The best wrapped versions of the fragment, for each type it can
+    /// be given
+    fn variants(&self) -> Result<Variants<Pk, Ctx>, Error> {
+        let mut map = BTreeMap::new();
+        match *self {
+            Unwrapped::Fragment(ref node) => {
+                let ms = Miniscript::from_ast(node.clone())?;
+                insert_closure(
+                    &mut map,
+                    Variant {
+                        ms: Arc::new(ms),
+                        wrappers: 0,
+                    },
+                );
+            }
+            Unwrapped::AndV(ref l, ref r) => binary(&mut map, l, r, Terminal::AndV)?,
+            Unwrapped::AndB(ref l, ref r) => binary(&mut map, l, r, Terminal::AndB)?,
+            Unwrapped::OrB(ref l, ref r) => binary(&mut map, l, r, Terminal::OrB)?,
+            Unwrapped::OrD(ref l, ref r) => binary(&mut map, l, r, Terminal::OrD)?,
+            Unwrapped::OrC(ref l, ref r) => binary(&mut map, l, r, Terminal::OrC)?,
+            Unwrapped::OrI(ref l, ref r) => binary(&mut map, l, r, Terminal::OrI)?,
+            Unwrapped::AndOr(ref a, ref b, ref c) => {
+                let (a, b, c) = (a.variants()?, b.variants()?, c.variants()?);
+                for a in a.values().filter(|a| a.ms.ty.corr.base == Base::B) {
+                    for b in b.values() {
+                        for c in c.values() {
+                            combine(
+                                &mut map,
+                                Terminal::AndOr(
+                                    Arc::clone(&a.ms),
+                                    Arc::clone(&b.ms),
+                                    Arc::clone(&c.ms),
+                                ),
+                                a.wrappers + b.wrappers + c.wrappers,
+                            );
+                        }
+                    }
+                }
+            }
+            // Each child is typed on its own, since the number of
+            // combinations of their variants grows exponentially
+            Unwrapped::Thresh(k, ref subs) => {
+                let mut best = vec![];
+                let mut wrappers = 0;
+                for (i, sub) in subs.iter().enumerate() {
+                    let base = if i == 0 { Base::B } else { Base::W };
+                    let variants = sub.variants()?;
+                    let variant = variants
+                        .values()
+                        .filter(|variant| {
+                            let corr = variant.ms.ty.corr;
+                            corr.base == base && corr.dissatisfiable && corr.unit
+                        })
+                        .min_by_key(|variant| variant.rank());
+                    match variant {
+                        Some(variant) => {
+                            wrappers += variant.wrappers;
+                            best.push(Arc::clone(&variant.ms));
+                        }
+                        None => break,
+                    }
+                }
+                if best.len() == subs.len() {
+                    combine(&mut map, Terminal::Thresh(k, best), wrappers);
+                }
+            }
+        }
+        if map.is_empty() {
+            return Err(Error::TypeCheck(format!(
+                "no wrappers make the children of «{}» fit",
+                self.name()
+            )));
+        }
+        Ok(map)
+    }
+}
+
+/// Type check `node`, made of wrapped children with `wrappers` wrappers
+/// in all, and insert it and its wrapped versions into `map`
+fn combine<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    map: &mut Variants<Pk, Ctx>,
+    node: Terminal<Pk, Ctx>,
+    wrappers: usize,
+) {
+    if let Ok(ms) = Miniscript::from_ast(node) {
+        insert_closure(
+            map,
+            Variant {
+                ms: Arc::new(ms),
+                wrappers,
+            },
+        );
+    }
+}
+
+/// Insert into `map` the wrapped versions of the binary fragment built by
+/// `node` from every combination of the variants of `l` and `r`
+fn binary<Pk, Ctx, F>(
+    map: &mut Variants<Pk, Ctx>,
+    l: &Unwrapped<Pk, Ctx>,
+    r: &Unwrapped<Pk, Ctx>,
+    node: F,
+) -> Result<(), Error>
+where
+    Pk: MiniscriptKey,
+    Ctx: ScriptContext,
+    F: Fn(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
+{
+    let (l, r) = (l.variants()?, r.variants()?);
+    for l in l.values() {
+        for r in r.values() {
+            combine(
+                map,
+                node(Arc::clone(&l.ms), Arc::clone(&r.ms)),
+                l.wrappers + r.wrappers,
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miniscript::{Legacy, Segwitv0};
+    use DummyKey;
+
+    type Segwit = Unwrapped<DummyKey, Segwitv0>;
+
+    fn pk() -> Box<Segwit> {
+        Box::new(Unwrapped::Fragment(Terminal::PkK(DummyKey)))
+    }
+
+    fn older(n: u16) -> Box<Segwit> {
+        Box::new(Unwrapped::Fragment(Terminal::Older(
+            ::RelLockTime::from_height(n),
+        )))
+    }
+
+    #[test]
+    fn build() {
+        assert_eq!(pk().build().unwrap().to_string(), "pk()");
+        assert_eq!(
+            Segwit::AndV(pk(), pk()).build().unwrap().to_string(),
+            "and_v(v:pk(),pk())"
+        );
+        assert_eq!(
+            Segwit::AndB(pk(), pk()).build().unwrap().to_string(),
+            "and_b(pk(),s:pk())"
+        );
+        assert_eq!(
+            Segwit::OrD(pk(), Box::new(Segwit::AndV(pk(), older(144))))
+                .build()
+                .unwrap()
+                .to_string(),
+            "or_d(pk(),and_v(v:pk(),older(144)))"
+        );
+        assert_eq!(
+            Segwit::Thresh(2, vec![*pk(), *pk(), *older(144)])
+                .build()
+                .unwrap()
+                .to_string(),
+            "thresh(2,pk(),s:pk(),sdv:older(144))"
+        );
+
+        // A single `c:` around the `or_i` rather than one on each side
+        let legacy = Unwrapped::<DummyKey, Legacy>::OrI(
+            Box::new(Unwrapped::Fragment(Terminal::PkK(DummyKey))),
+            Box::new(Unwrapped::Fragment(Terminal::PkK(DummyKey))),
+        );
+        assert_eq!(legacy.build().unwrap().to_string(), "c:or_i(pk_k(),pk_k())");
+
+        assert_eq!(
+            Segwit::Thresh(1, vec![]).build().unwrap_err().to_string(),
+            "typecheck: no wrappers make the children of «thresh» fit"
+        );
+    }
+}
@@ -17,23 +17,78 @@
 //! Optimizing compiler from concrete policies to Miniscript
 //!
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
 use std::convert::From;
 use std::marker::PhantomData;
 use std::{cmp, error, f64, fmt};
 
 use miniscript::types::extra_props::MAX_OPS_PER_SCRIPT;
 use miniscript::types::{self, ErrorKind, ExtData, Property, Type};
+use miniscript::wrap::Wrapper;
 use miniscript::ScriptContext;
+use policy::semantic::ConstraintViolation;
 use policy::Concrete;
 use std::collections::vec_deque::VecDeque;
 use std::hash;
 use std::sync::Arc;
+use std::time::Instant;
 use {policy, Terminal};
 use {Miniscript, MiniscriptKey};
 
-type PolicyCache<Pk, Ctx> =
-    HashMap<(Concrete<Pk>, OrdF64, Option<OrdF64>), HashMap<CompilationKey, AstElemExt<Pk, Ctx>>>;
+/// Maximum depth of a leaf in a script tree
+pub const MAX_TREE_DEPTH: usize = 128;
+
+/// Maximum number of `k`-of-`n` combinations a threshold is split into when
+/// building a script tree; larger thresholds are kept as a single leaf
+const MAX_THRESH_SPLIT: usize = 32;
+
+/// Leaves of a compiled script tree, each along with its depth in the tree
+pub type TreeLeaves<Pk, Ctx> = Vec<(usize, Miniscript<Pk, Ctx>)>;
+
+type CompilationMap<Pk, Ctx> = BTreeMap<CompilationKey, AstElemExt<Pk, Ctx>>;
+type ProbCompilationMap<Pk, Ctx> = HashMap<(OrdF64, Option<OrdF64>), CompilationMap<Pk, Ctx>>;
+
+/// Best compilations of every sub-policy seen so far, keyed by the policy
+/// and then by its sat and dissat probabilities. A cache may be kept and
+/// passed to `best_compilation_with_cache` for several policies, so that
+/// sub-policies they share, e.g. a common recovery branch, are compiled
+/// only once.
+pub struct PolicyCache<Pk: MiniscriptKey, Ctx: ScriptContext> {
+    map: HashMap<Concrete<Pk>, ProbCompilationMap<Pk, Ctx>>,
+    /// Point in time after which the current compilation is abandoned
+    deadline: Option<Instant>,
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> PolicyCache<Pk, Ctx> {
+    /// Create an empty cache
+    pub fn new() -> PolicyCache<Pk, Ctx> {
+        PolicyCache {
+            map: HashMap::new(),
+            deadline: None,
+        }
+    }
+
+    /// Number of distinct sub-policies in the cache
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether the cache holds no compilations
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Forget all cached compilations
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Default for PolicyCache<Pk, Ctx> {
+    fn default() -> PolicyCache<Pk, Ctx> {
+        PolicyCache::new()
+    }
+}
 
 ///Ordered f64 for comparison
 #[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
@@ -59,6 +114,11 @@ pub enum CompilerError {
     /// miniscripts which are under `MAX_OPS_PER_SCRIPT` but the compiler
     /// currently does not find them.
     MaxOpCountExceeded,
+    /// Splitting the policy into a script tree produced a leaf deeper
+    /// than `MAX_TREE_DEPTH`(128).
+    MaxTreeDepthExceeded,
+    /// Compilation did not finish within the given time limit.
+    TimeLimitExceeded,
     ///Policy related errors
     PolicyError(policy::concrete::PolicyError),
 }
@@ -86,6 +146,12 @@ impl fmt::Display for CompilerError {
                 "Atleast one spending path has more op codes executed than \
                  MAX_OPS_PER_SCRIPT",
             ),
+            CompilerError::MaxTreeDepthExceeded => {
+                f.write_str("Script tree has a leaf deeper than MAX_TREE_DEPTH")
+            }
+            CompilerError::TimeLimitExceeded => {
+                f.write_str("Compilation did not finish within the time limit")
+            }
             CompilerError::PolicyError(ref e) => fmt::Display::fmt(e, f),
         }
     }
@@ -98,6 +164,53 @@ impl From<policy::concrete::PolicyError> for CompilerError {
     }
 }
 
+/// Error from compiling a policy under path constraints
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ConstraintError<Pk: MiniscriptKey> {
+    /// Every spending path of the policy which violates a constraint
+    Violations(Vec<ConstraintViolation<Pk>>),
+    /// The policy has too many spending paths to check, or satisfies the
+    /// constraints but could not be compiled
+    CompilerError(CompilerError),
+}
+
+impl<Pk: MiniscriptKey> error::Error for ConstraintError<Pk> {
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            ConstraintError::Violations(..) => None,
+            ConstraintError::CompilerError(ref e) => Some(e),
+        }
+    }
+
+    fn description(&self) -> &str {
+        ""
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for ConstraintError<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConstraintError::Violations(ref violations) => {
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    fmt::Display::fmt(violation, f)?;
+                }
+                Ok(())
+            }
+            ConstraintError::CompilerError(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+#[doc(hidden)]
+impl<Pk: MiniscriptKey> From<CompilerError> for ConstraintError<Pk> {
+    fn from(e: CompilerError) -> ConstraintError<Pk> {
+        ConstraintError::CompilerError(e)
+    }
+}
+
 /// Hash required for using OrdF64 as key for hashmap
 impl hash::Hash for OrdF64 {
     fn hash<H: hash::Hasher>(&self, state: &mut H) {
@@ -107,7 +220,7 @@ impl hash::Hash for OrdF64 {
 
 /// Compilation key: This represents the state of the best possible compilation
 /// of a given policy(implicitly keyed).
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Hash)]
 struct CompilationKey {
     /// The type of the compilation result
     ty: Type,
@@ -460,9 +573,28 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> AstElemExt<Pk, Ctx> {
                 (None, None) => 0.0,
             }
     }
+
+    /// Compare two compilations by their 1-dimensional cost, breaking ties
+    /// by script size and then by the order on the Miniscripts themselves.
+    /// This is a total order, so the choice between equally good compilations
+    /// never depends on the order in which they were found.
+    fn cmp_cost(&self, other: &Self, sat_prob: f64, dissat_prob: Option<f64>) -> cmp::Ordering {
+        OrdF64(self.cost_1d(sat_prob, dissat_prob))
+            .cmp(&OrdF64(other.cost_1d(sat_prob, dissat_prob)))
+            .then_with(|| self.ms.ext.pk_cost.cmp(&other.ms.ext.pk_cost))
+            .then_with(|| self.ms.cmp(&other.ms))
+    }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> AstElemExt<Pk, Ctx> {
+    /// Wrap the compilation with `wrapper`
+    fn cast(&self, wrapper: Wrapper) -> Result<AstElemExt<Pk, Ctx>, ErrorKind> {
+        Ok(AstElemExt {
+            ms: Arc::new(wrapper.cast(&self.ms)?),
+            comp_ext_data: wrapper.cast_property(self.comp_ext_data)?,
+        })
+    }
+
     fn terminal(ast: Terminal<Pk, Ctx>) -> AstElemExt<Pk, Ctx> {
         AstElemExt {
             comp_ext_data: CompilerExtData::type_check(&ast, |_| None).unwrap(),
@@ -525,115 +657,6 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> AstElemExt<Pk, Ctx> {
     }
 }
 
-/// Different types of casts possible for each node.
-#[derive(Copy, Clone)]
-struct Cast<Pk: MiniscriptKey, Ctx: ScriptContext> {
-    node: fn(Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
-    ast_type: fn(types::Type) -> Result<types::Type, ErrorKind>,
-    ext_data: fn(types::ExtData) -> Result<types::ExtData, ErrorKind>,
-    comp_ext_data: fn(CompilerExtData) -> Result<CompilerExtData, types::ErrorKind>,
-}
-
-impl<Pk: MiniscriptKey, Ctx: ScriptContext> Cast<Pk, Ctx> {
-    fn cast(&self, ast: &AstElemExt<Pk, Ctx>) -> Result<AstElemExt<Pk, Ctx>, ErrorKind> {
-        Ok(AstElemExt {
-            ms: Arc::new(Miniscript {
-                ty: (self.ast_type)(ast.ms.ty)?,
-                ext: (self.ext_data)(ast.ms.ext)?,
-                node: (self.node)(Arc::clone(&ast.ms)),
-                phantom: PhantomData,
-            }),
-            comp_ext_data: (self.comp_ext_data)(ast.comp_ext_data)?,
-        })
-    }
-}
-
-fn all_casts<Pk: MiniscriptKey, Ctx: ScriptContext>() -> [Cast<Pk, Ctx>; 10] {
-    [
-        Cast {
-            ext_data: types::ExtData::cast_check,
-            node: Terminal::Check,
-            ast_type: types::Type::cast_check,
-            comp_ext_data: CompilerExtData::cast_check,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_dupif,
-            node: Terminal::DupIf,
-            ast_type: types::Type::cast_dupif,
-            comp_ext_data: CompilerExtData::cast_dupif,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_likely,
-            node: |ms| {
-                Terminal::OrI(
-                    Arc::new(
-                        Miniscript::from_ast(Terminal::False).expect("False Miniscript creation"),
-                    ),
-                    ms,
-                )
-            },
-            ast_type: types::Type::cast_likely,
-            comp_ext_data: CompilerExtData::cast_likely,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_unlikely,
-            node: |ms| {
-                Terminal::OrI(
-                    ms,
-                    Arc::new(
-                        Miniscript::from_ast(Terminal::False).expect("False Miniscript creation"),
-                    ),
-                )
-            },
-            ast_type: types::Type::cast_unlikely,
-            comp_ext_data: CompilerExtData::cast_unlikely,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_verify,
-            node: Terminal::Verify,
-            ast_type: types::Type::cast_verify,
-            comp_ext_data: CompilerExtData::cast_verify,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_nonzero,
-            node: Terminal::NonZero,
-            ast_type: types::Type::cast_nonzero,
-            comp_ext_data: CompilerExtData::cast_nonzero,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_true,
-            node: |ms| {
-                Terminal::AndV(
-                    ms,
-                    Arc::new(
-                        Miniscript::from_ast(Terminal::True).expect("True Miniscript creation"),
-                    ),
-                )
-            },
-            ast_type: types::Type::cast_true,
-            comp_ext_data: CompilerExtData::cast_true,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_swap,
-            node: Terminal::Swap,
-            ast_type: types::Type::cast_swap,
-            comp_ext_data: CompilerExtData::cast_swap,
-        },
-        Cast {
-            node: Terminal::Alt,
-            ast_type: types::Type::cast_alt,
-            ext_data: types::ExtData::cast_alt,
-            comp_ext_data: CompilerExtData::cast_alt,
-        },
-        Cast {
-            ext_data: types::ExtData::cast_zeronotequal,
-            node: Terminal::ZeroNotEqual,
-            ast_type: types::Type::cast_zeronotequal,
-            comp_ext_data: CompilerExtData::cast_zeronotequal,
-        },
-    ]
-}
-
 /// Insert an element into the global map and return whether it got inserted
 /// If there is any element which is already better than current element
 /// (by subtyping rules), then don't process the element and return `False`.
@@ -643,7 +666,7 @@ fn all_casts<Pk: MiniscriptKey, Ctx: ScriptContext>() -> [Cast<Pk, Ctx>; 10] {
 /// In general, we maintain the invariant that if anything is inserted into the
 /// map, it's cast closure must also be considered for best compilations.
 fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
-    map: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
+    map: &mut CompilationMap<Pk, Ctx>,
     elem: AstElemExt<Pk, Ctx>,
     sat_prob: f64,
     dissat_prob: Option<f64>,
@@ -660,8 +683,6 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
         }
     }
 
-    let elem_cost = elem.cost_1d(sat_prob, dissat_prob);
-
     let elem_key = CompilationKey::from_type(elem.ms.ty, elem.ms.ext.has_verify_form, dissat_prob);
 
     // Check whether the new element is worse than any existing element. If there
@@ -670,17 +691,24 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
     let is_worse = map
         .iter()
         .map(|(existing_key, existing_elem)| {
-            let existing_elem_cost = existing_elem.cost_1d(sat_prob, dissat_prob);
-            existing_key.is_subtype(elem_key) && existing_elem_cost <= elem_cost
+            existing_key.is_subtype(elem_key)
+                && existing_elem.cmp_cost(&elem, sat_prob, dissat_prob) != cmp::Ordering::Greater
         })
         .fold(false, |acc, x| acc || x);
     if !is_worse {
         // If the element is not worse any element in the map, remove elements
         // whose subtype is the current element and have worse cost.
-        map.retain(|&existing_key, existing_elem| {
-            let existing_elem_cost = existing_elem.cost_1d(sat_prob, dissat_prob);
-            !(elem_key.is_subtype(existing_key) && existing_elem_cost >= elem_cost)
-        });
+        let dominated: Vec<CompilationKey> = map
+            .iter()
+            .filter(|&(&existing_key, existing_elem)| {
+                elem_key.is_subtype(existing_key)
+                    && existing_elem.cmp_cost(&elem, sat_prob, dissat_prob) != cmp::Ordering::Less
+            })
+            .map(|(&existing_key, _)| existing_key)
+            .collect();
+        for existing_key in dominated {
+            map.remove(&existing_key);
+        }
         map.insert(elem_key, elem);
     }
     !is_worse
@@ -695,7 +723,7 @@ fn insert_elem<Pk: MiniscriptKey, Ctx: ScriptContext>(
 /// all map is smallest possible closure of all compilations of a policy with
 /// given sat and dissat probabilities.
 fn insert_elem_closure<Pk: MiniscriptKey, Ctx: ScriptContext>(
-    map: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
+    map: &mut CompilationMap<Pk, Ctx>,
     astelem_ext: AstElemExt<Pk, Ctx>,
     sat_prob: f64,
     dissat_prob: Option<f64>,
@@ -705,12 +733,11 @@ fn insert_elem_closure<Pk: MiniscriptKey, Ctx: ScriptContext>(
         cast_stack.push_back(astelem_ext);
     }
 
-    let casts: [Cast<Pk, Ctx>; 10] = all_casts::<Pk, Ctx>();
     while !cast_stack.is_empty() {
         let current = cast_stack.pop_front().unwrap();
 
-        for i in 0..casts.len() {
-            if let Ok(new_ext) = casts[i].cast(&current) {
+        for &wrapper in &Wrapper::ALL {
+            if let Ok(new_ext) = current.cast(wrapper) {
                 if insert_elem(map, new_ext.clone(), sat_prob, dissat_prob) {
                     cast_stack.push_back(new_ext);
                 }
@@ -719,31 +746,30 @@ fn insert_elem_closure<Pk: MiniscriptKey, Ctx: ScriptContext>(
     }
 }
 
-/// Insert the best wrapped compilations of a particular Terminal. If the
-/// dissat probability is None, then we directly get the closure of the element
-/// Otherwise, some wrappers require the compilation of the policy with dissat
-/// `None` because they convert it into a dissat around it.
+/// Insert the best wrapped compilations of the policy with dissat `None`.
+/// If the dissat probability is None, then the closures of the elements
+/// already inserted are all we need. Otherwise, some wrappers require the
+/// compilation of the policy with dissat `None` because they convert it into
+/// a dissat around it.
 /// For example, `l` wrapper should it argument it dissat. `None` because it can
 /// always dissatisfy the policy outside and it find the better inner compilation
 /// given that it may be not be necessary to dissatisfy. For these elements, we
 /// apply the wrappers around the element once and bring them into the same
-/// dissat probability map and get their closure.
+/// dissat probability map and get their closure. This only depends on the
+/// policy, so it is done once after all the fragments have been inserted.
 fn insert_best_wrapped<Pk: MiniscriptKey, Ctx: ScriptContext>(
     policy_cache: &mut PolicyCache<Pk, Ctx>,
     policy: &Concrete<Pk>,
-    map: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
-    data: AstElemExt<Pk, Ctx>,
+    map: &mut CompilationMap<Pk, Ctx>,
     sat_prob: f64,
     dissat_prob: Option<f64>,
 ) -> Result<(), CompilerError> {
-    insert_elem_closure(map, data, sat_prob, dissat_prob);
-
     if dissat_prob.is_some() {
-        let casts: [Cast<Pk, Ctx>; 10] = all_casts::<Pk, Ctx>();
+        let q_zero = best_compilations(policy_cache, policy, sat_prob, None)?;
 
-        for i in 0..casts.len() {
-            for x in best_compilations(policy_cache, policy, sat_prob, None)?.values() {
-                if let Ok(new_ext) = casts[i].cast(x) {
+        for &wrapper in &Wrapper::ALL {
+            for x in q_zero.values() {
+                if let Ok(new_ext) = x.cast(wrapper) {
                     insert_elem_closure(map, new_ext, sat_prob, dissat_prob);
                 }
             }
@@ -759,7 +785,7 @@ fn best_compilations<Pk, Ctx>(
     policy: &Concrete<Pk>,
     sat_prob: f64,
     dissat_prob: Option<f64>,
-) -> Result<HashMap<CompilationKey, AstElemExt<Pk, Ctx>>, CompilerError>
+) -> Result<CompilationMap<Pk, Ctx>, CompilerError>
 where
     Pk: MiniscriptKey,
     Ctx: ScriptContext,
@@ -767,46 +793,35 @@ where
     //Check the cache for hits
     let ord_sat_prob = OrdF64(sat_prob);
     let ord_dissat_prob = dissat_prob.and_then(|x| Some(OrdF64(x)));
-    if let Some(ret) = policy_cache.get(&(policy.clone(), ord_sat_prob, ord_dissat_prob)) {
+    if let Some(ret) = policy_cache
+        .map
+        .get(policy)
+        .and_then(|m| m.get(&(ord_sat_prob, ord_dissat_prob)))
+    {
         return Ok(ret.clone());
     }
+    if let Some(deadline) = policy_cache.deadline {
+        if Instant::now() >= deadline {
+            return Err(CompilerError::TimeLimitExceeded);
+        }
+    }
 
-    let mut ret = HashMap::new();
+    let mut ret = BTreeMap::new();
 
     //handy macro for good looking code
     macro_rules! insert_wrap {
         ($x:expr) => {
-            insert_best_wrapped(policy_cache, policy, &mut ret, $x, sat_prob, dissat_prob)?
+            insert_elem_closure(&mut ret, $x, sat_prob, dissat_prob)
         };
     }
     macro_rules! compile_binary {
         ($l:expr, $r:expr, $w: expr, $f: expr) => {
-            compile_binary(
-                policy_cache,
-                policy,
-                &mut ret,
-                $l,
-                $r,
-                $w,
-                sat_prob,
-                dissat_prob,
-                $f,
-            )?
+            compile_binary(&mut ret, $l, $r, $w, sat_prob, dissat_prob, $f)
         };
     }
     macro_rules! compile_tern {
         ($a:expr, $b:expr, $c: expr, $w: expr) => {
-            compile_tern(
-                policy_cache,
-                policy,
-                &mut ret,
-                $a,
-                $b,
-                $c,
-                $w,
-                sat_prob,
-                dissat_prob,
-            )?
+            compile_tern(&mut ret, $a, $b, $c, $w, sat_prob, dissat_prob)
         };
     }
 
@@ -817,6 +832,9 @@ where
             )));
             insert_wrap!(AstElemExt::terminal(Terminal::PkK(pk.clone())));
         }
+        Concrete::KeyHash(ref pkh) => {
+            insert_wrap!(AstElemExt::terminal(Terminal::PkH(pkh.clone())));
+        }
         Concrete::After(n) => insert_wrap!(AstElemExt::terminal(Terminal::After(n))),
         Concrete::Older(n) => insert_wrap!(AstElemExt::terminal(Terminal::Older(n))),
         Concrete::Sha256(hash) => insert_wrap!(AstElemExt::terminal(Terminal::Sha256(hash))),
@@ -834,7 +852,7 @@ where
             compile_binary!(&mut right, &mut left, [1.0, 1.0], Terminal::AndB);
             compile_binary!(&mut left, &mut right, [1.0, 1.0], Terminal::AndV);
             compile_binary!(&mut right, &mut left, [1.0, 1.0], Terminal::AndV);
-            let mut zero_comp = HashMap::new();
+            let mut zero_comp = BTreeMap::new();
             zero_comp.insert(
                 CompilationKey::from_type(
                     Type::from_false(),
@@ -997,6 +1015,7 @@ where
             }
         }
     }
+    insert_best_wrapped(policy_cache, policy, &mut ret, sat_prob, dissat_prob)?;
     for k in ret.keys() {
         debug_assert_eq!(k.dissat_prob, ord_dissat_prob);
     }
@@ -1009,7 +1028,13 @@ where
         // this compile function
         Err(CompilerError::MaxOpCountExceeded)
     } else {
-        policy_cache.insert((policy.clone(), ord_sat_prob, ord_dissat_prob), ret.clone());
+        // `or_default` needs Rust 1.28, above our MSRV
+        #[allow(clippy::unwrap_or_default)]
+        policy_cache
+            .map
+            .entry(policy.clone())
+            .or_insert_with(HashMap::new)
+            .insert((ord_sat_prob, ord_dissat_prob), ret.clone());
         Ok(ret)
     }
 }
@@ -1018,17 +1043,14 @@ where
 /// `sat_prob` and `dissat_prob` represent the sat and dissat probabilities of
 /// root or. `weights` represent the odds for taking each sub branch
 fn compile_binary<Pk, Ctx, F>(
-    policy_cache: &mut PolicyCache<Pk, Ctx>,
-    policy: &Concrete<Pk>,
-    ret: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
-    left_comp: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
-    right_comp: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
+    ret: &mut CompilationMap<Pk, Ctx>,
+    left_comp: &mut CompilationMap<Pk, Ctx>,
+    right_comp: &mut CompilationMap<Pk, Ctx>,
     weights: [f64; 2],
     sat_prob: f64,
     dissat_prob: Option<f64>,
     bin_func: F,
-) -> Result<(), CompilerError>
-where
+) where
     Pk: MiniscriptKey,
     Ctx: ScriptContext,
     F: Fn(Arc<Miniscript<Pk, Ctx>>, Arc<Miniscript<Pk, Ctx>>) -> Terminal<Pk, Ctx>,
@@ -1041,27 +1063,24 @@ where
             l.comp_ext_data.branch_prob = Some(weights[0]);
             r.comp_ext_data.branch_prob = Some(weights[1]);
             if let Ok(new_ext) = AstElemExt::binary(ast, l, r) {
-                insert_best_wrapped(policy_cache, policy, ret, new_ext, sat_prob, dissat_prob)?;
+                insert_elem_closure(ret, new_ext, sat_prob, dissat_prob);
             }
         }
     }
-    Ok(())
 }
 
 /// Helper function to compile different order of and_or fragments.
 /// `sat_prob` and `dissat_prob` represent the sat and dissat probabilities of
 /// root and_or node. `weights` represent the odds for taking each sub branch
 fn compile_tern<Pk: MiniscriptKey, Ctx: ScriptContext>(
-    policy_cache: &mut PolicyCache<Pk, Ctx>,
-    policy: &Concrete<Pk>,
-    ret: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
-    a_comp: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
-    b_comp: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
-    c_comp: &mut HashMap<CompilationKey, AstElemExt<Pk, Ctx>>,
+    ret: &mut CompilationMap<Pk, Ctx>,
+    a_comp: &mut CompilationMap<Pk, Ctx>,
+    b_comp: &mut CompilationMap<Pk, Ctx>,
+    c_comp: &mut CompilationMap<Pk, Ctx>,
     weights: [f64; 2],
     sat_prob: f64,
     dissat_prob: Option<f64>,
-) -> Result<(), CompilerError> {
+) {
     for a in a_comp.values_mut() {
         let aref = Arc::clone(&a.ms);
         for b in b_comp.values_mut() {
@@ -1073,20 +1092,50 @@ fn compile_tern<Pk: MiniscriptKey, Ctx: ScriptContext>(
                 b.comp_ext_data.branch_prob = Some(weights[0]);
                 c.comp_ext_data.branch_prob = Some(weights[1]);
                 if let Ok(new_ext) = AstElemExt::ternary(ast, a, b, c) {
-                    insert_best_wrapped(policy_cache, policy, ret, new_ext, sat_prob, dissat_prob)?;
+                    insert_elem_closure(ret, new_ext, sat_prob, dissat_prob);
                 }
             }
         }
     }
-    Ok(())
 }
 
 /// Obtain the best compilation of for p=1.0 and q=0
 pub fn best_compilation<Pk: MiniscriptKey, Ctx: ScriptContext>(
     policy: &Concrete<Pk>,
 ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
-    let mut policy_cache = PolicyCache::<Pk, Ctx>::new();
-    let x = &*best_t(&mut policy_cache, policy, 1.0, None)?.ms;
+    best_compilation_cached(&mut PolicyCache::new(), policy)
+}
+
+/// Obtain the best compilation of for p=1.0 and q=0, giving up with
+/// `CompilerError::TimeLimitExceeded` if it is not found before `deadline`
+pub fn best_compilation_with_deadline<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+    deadline: Instant,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    best_compilation_with_cache(&mut PolicyCache::new(), policy, Some(deadline))
+}
+
+/// Obtain the best compilation of for p=1.0 and q=0, reusing and adding to
+/// the compilations of sub-policies in `policy_cache`. Gives up with
+/// `CompilerError::TimeLimitExceeded` if a `deadline` is given and the
+/// compilation is not found before it; whatever was compiled until then
+/// stays in the cache.
+pub fn best_compilation_with_cache<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy_cache: &mut PolicyCache<Pk, Ctx>,
+    policy: &Concrete<Pk>,
+    deadline: Option<Instant>,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    policy_cache.deadline = deadline;
+    let ret = best_compilation_cached(policy_cache, policy);
+    policy_cache.deadline = None;
+    ret
+}
+
+fn best_compilation_cached<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy_cache: &mut PolicyCache<Pk, Ctx>,
+    policy: &Concrete<Pk>,
+) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+    let x = &*best_t(policy_cache, policy, 1.0, None)?.ms;
     if !x.ty.mall.safe {
         Err(CompilerError::TopLevelNonSafe)
     } else if !x.ty.mall.non_malleable {
@@ -1096,6 +1145,104 @@ pub fn best_compilation<Pk: MiniscriptKey, Ctx: ScriptContext>(
     }
 }
 
+/// Split a policy into script tree leaves at its disjunctions, weighting
+/// each leaf by the probability of it being used to satisfy `policy`
+fn split_tree_leaves<Pk: MiniscriptKey>(
+    policy: &Concrete<Pk>,
+    prob: f64,
+    leaves: &mut Vec<(f64, Concrete<Pk>)>,
+) {
+    match *policy {
+        Concrete::Or(ref subs) => {
+            let total = subs.iter().map(|&(w, _)| w).sum::<usize>();
+            for &(w, ref sub) in subs {
+                // Branches all weighted zero are taken to be equally likely
+                let sub_prob = if total == 0 {
+                    prob / subs.len() as f64
+                } else {
+                    prob * w as f64 / total as f64
+                };
+                split_tree_leaves(sub, sub_prob, leaves);
+            }
+        }
+        Concrete::Threshold(1, ref subs) => {
+            for sub in subs {
+                split_tree_leaves(sub, prob / subs.len() as f64, leaves);
+            }
+        }
+        Concrete::Threshold(k, ref subs)
+            if k < subs.len() && n_choose_k(subs.len(), k) <= MAX_THRESH_SPLIT =>
+        {
+            let n_combs = n_choose_k(subs.len(), k) as f64;
+            // Walk the `k`-subsets of `subs` in lexicographic order
+            let mut idx: Vec<usize> = (0..k).collect();
+            loop {
+                let comb = idx.iter().map(|&i| subs[i].clone()).collect();
+                leaves.push((prob / n_combs, Concrete::Threshold(k, comb)));
+
+                match (0..k).rev().find(|&i| idx[i] != i + subs.len() - k) {
+                    Some(i) => {
+                        idx[i] += 1;
+                        for j in i + 1..k {
+                            idx[j] = idx[j - 1] + 1;
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+        _ => leaves.push((prob, policy.clone())),
+    }
+}
+
+/// Binomial coefficient, saturating rather than overflowing
+fn n_choose_k(n: usize, k: usize) -> usize {
+    let k = cmp::min(k, n - k);
+    (0..k).fold(1usize, |acc, i| acc.saturating_mul(n - i) / (i + 1))
+}
+
+/// Obtain the best compilation of a policy split into a script tree.
+/// The top-level disjunctions of the policy are broken up into separate
+/// leaves, which are arranged by Huffman coding on their probabilities, so
+/// that likely spending paths sit closer to the root. Returns each leaf's
+/// compilation along with its depth in the tree.
+pub fn best_compilation_tree<Pk: MiniscriptKey, Ctx: ScriptContext>(
+    policy: &Concrete<Pk>,
+) -> Result<TreeLeaves<Pk, Ctx>, CompilerError> {
+    let mut leaves = vec![];
+    split_tree_leaves(policy, 1.0, &mut leaves);
+
+    let mut policy_cache = PolicyCache::new();
+    let mut ret = Vec::with_capacity(leaves.len());
+    for leaf in leaves.iter().map(|x| &x.1) {
+        ret.push((0, best_compilation_cached(&mut policy_cache, leaf)?));
+    }
+
+    // Huffman coding; ties are broken by insertion order so that the
+    // resulting tree is deterministic
+    let mut heap = BinaryHeap::new();
+    for (i, &(prob, _)) in leaves.iter().enumerate() {
+        heap.push(cmp::Reverse((OrdF64(prob), i, vec![i])));
+    }
+    let mut next_id = leaves.len();
+    while heap.len() > 1 {
+        let cmp::Reverse((p1, _, mut l1)) = heap.pop().expect("heap has two elements");
+        let cmp::Reverse((p2, _, l2)) = heap.pop().expect("heap has two elements");
+        l1.extend(l2);
+        for &i in &l1 {
+            ret[i].0 += 1;
+        }
+        heap.push(cmp::Reverse((OrdF64(p1.0 + p2.0), next_id, l1)));
+        next_id += 1;
+    }
+
+    if ret.iter().any(|&(depth, _)| depth > MAX_TREE_DEPTH) {
+        Err(CompilerError::MaxTreeDepthExceeded)
+    } else {
+        Ok(ret)
+    }
+}
+
 /// Obtain the best B expression with given sat and dissat
 fn best_t<Pk, Ctx>(
     policy_cache: &mut PolicyCache<Pk, Ctx>,
@@ -1114,7 +1261,7 @@ where
                 && key.dissat_prob == dissat_prob.and_then(|x| Some(OrdF64(x)))
         })
         .map(|(_, val)| val)
-        .min_by_key(|ext| OrdF64(ext.cost_1d(sat_prob, dissat_prob)))
+        .min_by(|a, b| a.cmp_cost(b, sat_prob, dissat_prob))
         .ok_or(CompilerError::MaxOpCountExceeded)
 }
 
@@ -1139,7 +1286,7 @@ where
                 && key.dissat_prob == dissat_prob.and_then(|x| Some(OrdF64(x)))
         })
         .map(|(_, val)| val)
-        .min_by_key(|ext| OrdF64(ext.cost_1d(sat_prob, dissat_prob)))
+        .min_by(|a, b| a.cmp_cost(b, sat_prob, dissat_prob))
         .ok_or(CompilerError::MaxOpCountExceeded)
 }
 
@@ -1150,11 +1297,15 @@ mod tests {
     use bitcoin::{self, hashes, secp256k1, SigHashType};
     use std::str::FromStr;
     use std::string::String;
+    use std::time::Duration;
 
     use miniscript::{satisfy, Segwitv0};
-    use policy::Liftable;
+    use policy::concrete::PolicyError;
+    use policy::semantic::PathConstraint;
+    use policy::{Liftable, Semantic};
     use BitcoinSig;
-    use DummyKey;
+    use RelLockTime;
+    use {DummyKey, DummyKeyHash};
 
     type SPolicy = Concrete<String>;
     type DummyPolicy = Concrete<DummyKey>;
@@ -1215,6 +1366,9 @@ mod tests {
         assert!(policy_compile_lift_check("and(pk(),pk())").is_ok());
         assert!(policy_compile_lift_check("or(pk(),pk())").is_ok());
         assert!(policy_compile_lift_check("thresh(2,pk(),pk(),pk())").is_ok());
+        assert!(policy_compile_lift_check("pkh()").is_ok());
+        assert!(policy_compile_lift_check("or(pk(),pkh())").is_ok());
+        assert!(policy_compile_lift_check("thresh(2,pkh(),pk(),pkh())").is_ok());
 
         assert_eq!(
             policy_compile_lift_check("thresh(2,after(9),after(9),pk())"),
@@ -1231,7 +1385,7 @@ mod tests {
     fn compile_q() {
         let policy = SPolicy::from_str("or(1@and(pk(),pk()),127@pk())").expect("parsing");
         let compilation: DummySegwitAstElemExt =
-            best_t(&mut HashMap::new(), &policy, 1.0, None).unwrap();
+            best_t(&mut PolicyCache::new(), &policy, 1.0, None).unwrap();
 
         assert_eq!(compilation.cost_1d(1.0, None), 88.0 + 74.109375);
         assert_eq!(policy.lift().sorted(), compilation.ms.lift().sorted());
@@ -1240,12 +1394,309 @@ mod tests {
                 "and(and(and(or(127@thresh(2,pk(),pk(),thresh(2,or(127@pk(),1@pk()),after(100),or(and(pk(),after(200)),and(pk(),sha256(66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925))),pk())),1@pk()),sha256(66687aadf862bd776c8fc18b8e9f8e20089714856ee233b3902a591d0d5f2925)),or(127@pk(),1@after(300))),or(127@after(400),pk()))"
             ).expect("parsing");
         let compilation: DummySegwitAstElemExt =
-            best_t(&mut HashMap::new(), &policy, 1.0, None).unwrap();
+            best_t(&mut PolicyCache::new(), &policy, 1.0, None).unwrap();
 
         assert_eq!(compilation.cost_1d(1.0, None), 437.0 + 299.4003295898438);
         assert_eq!(policy.lift().sorted(), compilation.ms.lift().sorted());
     }
 
+    #[test]
+    fn compile_time_limit() {
+        let keys: Vec<String> = (0..15).map(|i| format!("pk(K{})", i)).collect();
+        let policy = SPolicy::from_str(&format!(
+            "or(99@thresh(3,{}),1@and(older(1000),pk(R)))",
+            keys.join(",")
+        ))
+        .unwrap();
+
+        let ms: Miniscript<String, Segwitv0> = policy
+            .compile_with_time_limit(Duration::from_secs(600))
+            .unwrap();
+        assert_eq!(ms.lift().sorted(), policy.lift().sorted());
+
+        // Far too little time for a large policy
+        let large = SPolicy::from_str(
+            "or(pk(A),thresh(4,pk(B),older(100),pk(C),and(after(100),or(pk(D),or(pk(E),\
+             and(pk(F),thresh(2,pk(G),or(pk(H),and(thresh(5,pk(I),or(pk(J),pk(K)),pk(L),\
+             pk(M),pk(N),pk(O),pk(P),pk(Q),pk(R),pk(S),pk(T)),pk(U))),pk(V),or(and(pk(W),\
+             pk(X)),pk(Y)),after(100)))))),pk(Z)))",
+        )
+        .unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            large.compile_with_time_limit::<Segwitv0>(Duration::from_millis(1)),
+            Err(CompilerError::TimeLimitExceeded)
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // A deadline which has already passed
+        let deadline = Instant::now();
+        assert_eq!(
+            best_compilation_with_deadline::<String, Segwitv0>(&policy, deadline),
+            Err(CompilerError::TimeLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn compile_with_cache() {
+        let keys: Vec<String> = (0..15).map(|i| format!("pk(K{})", i)).collect();
+        let recovery = "and(older(1000),pk(R))";
+        let first = SPolicy::from_str(&format!(
+            "or(99@thresh(3,{}),1@{})",
+            keys.join(","),
+            recovery
+        ))
+        .unwrap();
+        let second = SPolicy::from_str(&format!("or(pk(A),{})", recovery)).unwrap();
+
+        let mut cache = PolicyCache::new();
+        let ms: Miniscript<String, Segwitv0> = first.compile_with_cache(&mut cache).unwrap();
+        assert_eq!(ms, first.compile().unwrap());
+        let n_cached = cache.len();
+        assert!(n_cached > 0);
+        let ms: Miniscript<String, Segwitv0> = second.compile_with_cache(&mut cache).unwrap();
+        assert_eq!(ms, second.compile().unwrap());
+        // Only `pk(A)` and `second` itself are new
+        assert_eq!(cache.len(), n_cached + 2);
+        // Cached compilations are reused as they are
+        let ms: Miniscript<String, Segwitv0> = first.compile_with_cache(&mut cache).unwrap();
+        assert_eq!(ms, first.compile().unwrap());
+        assert_eq!(cache.len(), n_cached + 2);
+    }
+
+    /// Policies whose compilations are pinned by `compile_deterministic`
+    fn corpus() -> Vec<(String, String)> {
+        let h = "1111111111111111111111111111111111111111111111111111111111111111";
+        vec![
+            ("pk(A)".to_owned(), "pk(A)".to_owned()),
+            (
+                "or(pk(A),pk(B))".to_owned(),
+                "or_b(pk(A),s:pk(B))".to_owned(),
+            ),
+            (
+                "or(pkh(A),pk(B))".to_owned(),
+                "c:or_i(pk_k(B),pk_h(A))".to_owned(),
+            ),
+            (
+                "and(pk(A),pk(B))".to_owned(),
+                "c:and_v(v:pk(A),pk_k(B))".to_owned(),
+            ),
+            (
+                "and(pk(A),or(pk(B),older(144)))".to_owned(),
+                "c:and_v(or_c(pk(B),v:older(144)),pk_k(A))".to_owned(),
+            ),
+            (
+                "thresh(2,pk(A),pk(B),pk(C))".to_owned(),
+                "multi(2,A,B,C)".to_owned(),
+            ),
+            (
+                "or(99@thresh(2,pk(A),pk(B),pk(C)),1@and(pk(D),older(1000)))".to_owned(),
+                "or_d(multi(2,A,B,C),and_v(v:pkh(D),older(1000)))".to_owned(),
+            ),
+            (
+                "and(pk(A),or(pk(B),or(9@pk(C),1@and(pk(D),after(500000)))))".to_owned(),
+                "c:and_v(or_c(pk(B),or_c(pk(C),vc:and_v(v:after(500000),pk_h(D)))),pk_k(A))"
+                    .to_owned(),
+            ),
+            (
+                "thresh(3,pk(A),pk(B),pk(C),pk(D),older(10000))".to_owned(),
+                "thresh(3,pk(A),s:pk(B),s:pk(C),s:pk(D),sdv:older(10000))".to_owned(),
+            ),
+            (
+                format!("or(pk(A),and(sha256({}),pk(B)))", h),
+                format!("c:or_i(pk_k(A),and_v(v:sha256({}),pk_k(B)))", h),
+            ),
+            (
+                "and(or(pk(A),pk(B)),or(pk(C),pk(D)))".to_owned(),
+                "and_v(or_c(pk(A),v:pk(B)),or_b(pk(C),s:pk(D)))".to_owned(),
+            ),
+            (
+                "or(and(pk(A),pk(B)),and(pk(C),pk(D)))".to_owned(),
+                "c:andor(pk(A),pk_k(B),and_v(v:pk(C),pk_k(D)))".to_owned(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn compile_deterministic() {
+        // Recompiling a stored policy must always give the same miniscript,
+        // so any change to these compilations is a breaking change.
+        for (policy, expected) in corpus() {
+            let policy = SPolicy::from_str(&policy).unwrap();
+            for _ in 0..5 {
+                let ms: Miniscript<String, Segwitv0> = policy.compile().unwrap();
+                assert_eq!(ms.to_string(), expected);
+            }
+        }
+    }
+
+    /// Insert `seed` and every cast of it up to `depth` wrappers deep,
+    /// casting even those which did not make it into the map
+    fn insert_all_casts(
+        map: &mut CompilationMap<String, Segwitv0>,
+        seed: AstElemExt<String, Segwitv0>,
+        depth: usize,
+        sat_prob: f64,
+        dissat_prob: Option<f64>,
+    ) {
+        let mut layer = vec![seed];
+        for i in 0..depth + 1 {
+            let mut next = vec![];
+            for elem in layer {
+                if i < depth {
+                    for &wrapper in &Wrapper::ALL {
+                        if let Ok(new_ext) = elem.cast(wrapper) {
+                            next.push(new_ext);
+                        }
+                    }
+                }
+                insert_elem(map, elem, sat_prob, dissat_prob);
+            }
+            layer = next;
+        }
+    }
+
+    #[test]
+    fn closure_is_lossless() {
+        // Whatever the order candidates come in, the closure must keep
+        // compilations at least as good as casting every candidate ever
+        // found, including those later evicted from the map
+        for (policy, _) in corpus() {
+            let policy = SPolicy::from_str(&policy).unwrap();
+            let mut cache = PolicyCache::<String, Segwitv0>::new();
+            best_compilation_with_cache(&mut cache, &policy, None).unwrap();
+            for (sub, prob_maps) in &cache.map {
+                // Candidates found for other probabilities are good ones
+                // to be later evicted
+                let seeds: Vec<_> = prob_maps.values().flat_map(|map| map.values()).collect();
+                for &(OrdF64(sat_prob), dissat_prob) in prob_maps.keys() {
+                    let dissat_prob = dissat_prob.map(|p| p.0);
+                    let mut closure = BTreeMap::new();
+                    let mut exhaustive = BTreeMap::new();
+                    for &seed in &seeds {
+                        insert_elem_closure(&mut closure, seed.clone(), sat_prob, dissat_prob);
+                        insert_all_casts(&mut exhaustive, seed.clone(), 2, sat_prob, dissat_prob);
+                    }
+                    for (&key, elem) in &exhaustive {
+                        assert!(
+                            closure.iter().any(|(&closure_key, closure_elem)| {
+                                closure_key.is_subtype(key)
+                                    && closure_elem.cmp_cost(elem, sat_prob, dissat_prob)
+                                        != cmp::Ordering::Greater
+                            }),
+                            "closure of {} lost {}",
+                            sub,
+                            elem.ms,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compile_constraints() {
+        let policy = SPolicy::from_str("and(pk(A),or(pk(B),and(pk(C),older(144))))").unwrap();
+        let never_ab = PathConstraint::NeverTogether(vec!["A".to_owned(), "B".to_owned()]);
+        let lock_c = PathConstraint::RequiresTimelock("C".to_owned());
+
+        let ms: Miniscript<String, Segwitv0> = policy
+            .compile_with_constraints(&[lock_c.clone()], 100)
+            .unwrap();
+        assert_eq!(ms.lift().sorted(), policy.lift().sorted());
+        let err = policy
+            .compile_with_constraints::<Segwitv0>(&[never_ab.clone()], 100)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "spending path and(pkh(A),pkh(B)) violates never_together(A,B)"
+        );
+        match err {
+            ConstraintError::Violations(violations) => {
+                assert_eq!(violations.len(), 1);
+                assert_eq!(violations[0].constraint, never_ab);
+                assert_eq!(violations[0].path.key_hashes, vec!["A", "B"]);
+            }
+            e => panic!("unexpected error {}", e),
+        }
+        assert_eq!(
+            policy.compile_with_constraints::<Segwitv0>(&[lock_c], 1),
+            Err(ConstraintError::CompilerError(CompilerError::PolicyError(
+                PolicyError::TooManySpendingPaths(1)
+            )))
+        );
+    }
+
+    #[test]
+    fn compile_mixed_timelocks() {
+        let policy =
+            SPolicy::from_str("and(pk(A),or(and(pk(B),after(700000)),after(1600000000)))").unwrap();
+        assert_eq!(policy.check_timelocks(), Ok(()));
+        assert!(policy
+            .compile_rejecting_mixed_timelocks::<Segwitv0>()
+            .is_ok());
+
+        let policy =
+            SPolicy::from_str("and(pk(A),thresh(2,pk(B),after(700000),after(1600000000)))")
+                .unwrap();
+        assert_eq!(
+            policy.check_timelocks(),
+            Err(PolicyError::HeightTimelockCombination)
+        );
+        assert_eq!(
+            policy.compile_rejecting_mixed_timelocks::<Segwitv0>(),
+            Err(CompilerError::PolicyError(
+                PolicyError::HeightTimelockCombination
+            ))
+        );
+        // Without the check the policy still compiles, and the type system
+        // flags the result
+        let ms: Miniscript<String, Segwitv0> = policy.compile().unwrap();
+        assert!(ms.has_mixed_timelocks());
+    }
+
+    #[test]
+    fn compile_tree() {
+        let policy = DummyPolicy::from_str("or(7@pk(),1@and(pk(),older(10)))").unwrap();
+        let leaves = policy.compile_tree::<Segwitv0>().unwrap();
+        assert_eq!(leaves.len(), 2);
+        assert_eq!(leaves[0].0, 1);
+        assert_eq!(leaves[0].1.lift(), Semantic::KeyHash(DummyKeyHash));
+        assert_eq!(leaves[1].0, 1);
+
+        // Unlikely branches sink deeper into the tree
+        let policy =
+            DummyPolicy::from_str("or(3@pk(),1@or(1@pk(),1@thresh(2,pk(),pk(),pk())))").unwrap();
+        let leaves = policy.compile_tree::<Segwitv0>().unwrap();
+        let depths: Vec<usize> = leaves.iter().map(|&(d, _)| d).collect();
+        assert_eq!(depths, vec![1, 2, 4, 4, 3]);
+        for &(_, ref ms) in &leaves[2..] {
+            assert_eq!(ms.lift().n_keys(), 2);
+        }
+
+        // Zero weights everywhere are equal weights
+        let policy = DummyPolicy::from_str("or(0@pk(),0@and(pk(),older(10)))").unwrap();
+        let leaves = policy.compile_tree::<Segwitv0>().unwrap();
+        let depths: Vec<usize> = leaves.iter().map(|&(d, _)| d).collect();
+        assert_eq!(depths, vec![1, 1]);
+
+        // Every leaf must be safe on its own
+        let policy = DummyPolicy::from_str("or(pk(),after(9))").unwrap();
+        assert_eq!(
+            policy.compile_tree::<Segwitv0>(),
+            Err(CompilerError::TopLevelNonSafe)
+        );
+
+        // A non-disjunctive policy is a single leaf at the root
+        let policy = DummyPolicy::from_str("and(pk(),pk())").unwrap();
+        let leaves = policy.compile_tree::<Segwitv0>().unwrap();
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].0, 0);
+        assert_eq!(
+            leaves[0].1.script_size(),
+            policy.compile::<Segwitv0>().unwrap().script_size()
+        );
+    }
+
     #[test]
     fn compile_misc() {
         let (keys, sig) = pubkeys_and_a_sig(10);
@@ -1261,6 +1712,21 @@ mod tests {
                 .into_script()
         );
 
+        // Key hashes compile to pk_h
+        let policy: BPolicy = Concrete::KeyHash(keys[0].to_pubkeyhash());
+        let ms: SegwitMiniScript = policy.compile().unwrap();
+        assert_eq!(
+            ms.encode(),
+            script::Builder::new()
+                .push_opcode(opcodes::all::OP_DUP)
+                .push_opcode(opcodes::all::OP_HASH160)
+                .push_slice(&keys[0].to_pubkeyhash()[..])
+                .push_opcode(opcodes::all::OP_EQUALVERIFY)
+                .push_opcode(opcodes::all::OP_CHECKSIG)
+                .into_script()
+        );
+        assert_eq!(policy.lift(), ms.lift());
+
         // CSV reordering trick
         let policy: BPolicy = policy_str!(
             "and(older(10000),thresh(2,pk({}),pk({}),pk({})))",
@@ -1289,7 +1755,7 @@ mod tests {
             (
                 1,
                 Concrete::And(vec![
-                    Concrete::Older(10000),
+                    Concrete::Older(RelLockTime::from_height(10000)),
                     Concrete::Threshold(2, key_pol[5..8].to_owned()),
                 ]),
             ),
@@ -1415,6 +1881,20 @@ mod benches {
         });
     }
 
+    #[bench]
+    pub fn compile_thresh_recovery(bh: &mut Bencher) {
+        let keys: Vec<String> = (0..15).map(|i| format!("pk(K{})", i)).collect();
+        let pol = Concrete::<String>::from_str(&format!(
+            "or(99@thresh(3,{}),1@and(older(1000),thresh(2,pk(R1),pk(R2),pk(R3))))",
+            keys.join(",")
+        ))
+        .expect("parsing");
+        bh.iter(|| {
+            let pt: Result<Miniscript<String, Segwitv0>, _> = pol.compile();
+            black_box(pt).unwrap();
+        });
+    }
+
     #[bench]
     pub fn compile_xlarge(bh: &mut Bencher) {
         let pol = Concrete::<DummyKey>::from_str(
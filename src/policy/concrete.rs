@@ -17,18 +17,26 @@
 
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d};
+#[cfg(feature = "compiler")]
+use std::time::{Duration, Instant};
 use std::{error, fmt, str};
 
 use errstr;
 use expression::{self, FromTree};
+use miniscript::types::extra_props::TimeLockInfo;
 #[cfg(feature = "compiler")]
 use miniscript::ScriptContext;
 #[cfg(feature = "compiler")]
 use policy::compiler;
 #[cfg(feature = "compiler")]
-use policy::compiler::CompilerError;
+use policy::compiler::{CompilerError, ConstraintError};
+#[cfg(feature = "compiler")]
+use policy::semantic::PathConstraint;
+#[cfg(feature = "compiler")]
+use policy::Liftable;
 #[cfg(feature = "compiler")]
 use Miniscript;
+use {AbsLockTime, RelLockTime};
 use {Error, MiniscriptKey};
 
 /// Concrete policy which corresponds directly to a Miniscript structure,
@@ -38,10 +46,13 @@ use {Error, MiniscriptKey};
 pub enum Policy<Pk: MiniscriptKey> {
     /// A public key which must sign to satisfy the descriptor
     Key(Pk),
-    /// A relative locktime restriction
-    After(u32),
+    /// A public key hash; the key matching it must sign to satisfy the
+    /// descriptor, and is revealed only at spend time
+    KeyHash(Pk::Hash),
     /// An absolute locktime restriction
-    Older(u32),
+    After(AbsLockTime),
+    /// A relative locktime restriction
+    Older(RelLockTime),
     /// A SHA256 whose preimage must be provided to satisfy the descriptor
     Sha256(sha256::Hash),
     /// A SHA256d whose preimage must be provided to satisfy the descriptor
@@ -72,6 +83,24 @@ pub enum PolicyError {
     ZeroTime,
     /// `after` fragment can only have ` n < 2^31`
     TimeTooFar,
+    /// `older` fragment has the disable flag (`1 << 31`) set, and so would
+    /// not constrain the spending transaction at all
+    DisabledTimelock,
+    /// Enumerating the spending paths of a policy produced more of them
+    /// than the given limit
+    TooManySpendingPaths(usize),
+    /// Comparing two policies required a truth table with more rows than
+    /// `semantic::MAX_TRUTH_TABLE_ROWS`
+    TruthTableTooLarge,
+    /// A spending path requires both a block height and a time based
+    /// `after`, or both kinds of `older`, and so can never be used
+    HeightTimelockCombination,
+    /// A decompiled Miniscript can be satisfied without any conditions,
+    /// which a concrete policy cannot express
+    TriviallySatisfiable,
+    /// A decompiled Miniscript can never be satisfied, which a concrete
+    /// policy cannot express
+    Unsatisfiable,
 }
 
 impl error::Error for PolicyError {
@@ -97,6 +126,22 @@ impl fmt::Display for PolicyError {
                 f.write_str("Relative/Absolute time must be less than 2^31; n < 2^31")
             }
             PolicyError::ZeroTime => f.write_str("Time must be greater than 0; n > 0"),
+            PolicyError::DisabledTimelock => {
+                f.write_str("Relative time must not have the disable flag (1 << 31) set")
+            }
+            PolicyError::TooManySpendingPaths(n) => {
+                write!(f, "Policy has more than {} spending paths", n)
+            }
+            PolicyError::TruthTableTooLarge => {
+                f.write_str("Policies have too many conditions to compare exactly")
+            }
+            PolicyError::HeightTimelockCombination => f.write_str(
+                "Cannot mix block heights and times in the timelocks of a spending path",
+            ),
+            PolicyError::TriviallySatisfiable => {
+                f.write_str("Policy is satisfiable without any conditions")
+            }
+            PolicyError::Unsatisfiable => f.write_str("Policy can never be satisfied"),
         }
     }
 }
@@ -112,18 +157,100 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             _ => compiler::best_compilation(self),
         }
     }
+
+    /// Compile the descriptor into an optimized `Miniscript` representation,
+    /// refusing policies with a spending path that can never be used
+    /// because it mixes block heights and times (see `check_timelocks`)
+    #[cfg(feature = "compiler")]
+    pub fn compile_rejecting_mixed_timelocks<Ctx: ScriptContext>(
+        &self,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        self.check_timelocks()?;
+        self.compile()
+    }
+
+    /// Compile the descriptor into an optimized `Miniscript` representation,
+    /// after checking that none of its spending paths violate the given
+    /// constraints. Fails with all the violations if there are any, or if
+    /// it has more than `max_paths` spending paths to check.
+    #[cfg(feature = "compiler")]
+    pub fn compile_with_constraints<Ctx: ScriptContext>(
+        &self,
+        constraints: &[PathConstraint<Pk>],
+        max_paths: usize,
+    ) -> Result<Miniscript<Pk, Ctx>, ConstraintError<Pk>> {
+        let violations = self
+            .lift()
+            .check_constraints(constraints, max_paths)
+            .map_err(CompilerError::PolicyError)?;
+        if !violations.is_empty() {
+            return Err(ConstraintError::Violations(violations));
+        }
+        Ok(self.compile()?)
+    }
+
+    /// Compile the descriptor into an optimized `Miniscript` representation,
+    /// giving up with `CompilerError::TimeLimitExceeded` if this takes longer
+    /// than `limit`
+    #[cfg(feature = "compiler")]
+    pub fn compile_with_time_limit<Ctx: ScriptContext>(
+        &self,
+        limit: Duration,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        let deadline = Instant::now() + limit;
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::best_compilation_with_deadline(self, deadline),
+        }
+    }
+
+    /// Compile the descriptor into an optimized `Miniscript` representation,
+    /// reusing the compilations of any sub-policies found in `cache` by
+    /// earlier calls and adding those of this policy to it
+    #[cfg(feature = "compiler")]
+    pub fn compile_with_cache<Ctx: ScriptContext>(
+        &self,
+        cache: &mut compiler::PolicyCache<Pk, Ctx>,
+    ) -> Result<Miniscript<Pk, Ctx>, CompilerError> {
+        self.is_valid()?;
+        match self.is_safe_nonmalleable() {
+            (false, _) => Err(CompilerError::TopLevelNonSafe),
+            (_, false) => Err(CompilerError::ImpossibleNonMalleableCompilation),
+            _ => compiler::best_compilation_with_cache(cache, self, None),
+        }
+    }
+
+    /// Compile the descriptor into a script tree of optimized `Miniscript`
+    /// leaves, splitting it at its top-level disjunctions. Each leaf is
+    /// returned along with its depth in the tree; more probable leaves,
+    /// according to the `@` weights, are placed closer to the root.
+    #[cfg(feature = "compiler")]
+    pub fn compile_tree<Ctx: ScriptContext>(
+        &self,
+    ) -> Result<compiler::TreeLeaves<Pk, Ctx>, CompilerError> {
+        self.is_valid()?;
+        compiler::best_compilation_tree(self)
+    }
 }
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Convert a policy using one kind of public key to another
     /// type of public key
-    pub fn translate_pk<Fpk, Q, E>(&self, mut translatefpk: Fpk) -> Result<Policy<Q>, E>
+    pub fn translate_pk<Fpk, Fpkh, Q, E>(
+        &self,
+        mut translatefpk: Fpk,
+        mut translatefpkh: Fpkh,
+    ) -> Result<Policy<Q>, E>
     where
         Fpk: FnMut(&Pk) -> Result<Q, E>,
+        Fpkh: FnMut(&Pk::Hash) -> Result<Q::Hash, E>,
         Q: MiniscriptKey,
     {
         match *self {
             Policy::Key(ref pk) => translatefpk(pk).map(Policy::Key),
+            Policy::KeyHash(ref pkh) => translatefpkh(pkh).map(Policy::KeyHash),
             Policy::Sha256(ref h) => Ok(Policy::Sha256(h.clone())),
             Policy::Hash256(ref h) => Ok(Policy::Hash256(h.clone())),
             Policy::Ripemd160(ref h) => Ok(Policy::Ripemd160(h.clone())),
@@ -133,23 +260,60 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             Policy::Threshold(k, ref subs) => {
                 let new_subs: Result<Vec<Policy<Q>>, _> = subs
                     .iter()
-                    .map(|sub| sub.translate_pk(&mut translatefpk))
+                    .map(|sub| sub.translate_pk(&mut translatefpk, &mut translatefpkh))
                     .collect();
                 new_subs.map(|ok| Policy::Threshold(k, ok))
             }
             Policy::And(ref subs) => Ok(Policy::And(
                 subs.iter()
-                    .map(|sub| sub.translate_pk(&mut translatefpk))
+                    .map(|sub| sub.translate_pk(&mut translatefpk, &mut translatefpkh))
                     .collect::<Result<Vec<Policy<Q>>, E>>()?,
             )),
             Policy::Or(ref subs) => Ok(Policy::Or(
                 subs.iter()
-                    .map(|&(ref prob, ref sub)| Ok((*prob, sub.translate_pk(&mut translatefpk)?)))
+                    .map(|&(ref prob, ref sub)| {
+                        Ok((
+                            *prob,
+                            sub.translate_pk(&mut translatefpk, &mut translatefpkh)?,
+                        ))
+                    })
                     .collect::<Result<Vec<(usize, Policy<Q>)>, E>>()?,
             )),
         }
     }
 
+    /// Check that no spending path of the policy requires both a block
+    /// height and a time based `after`, or both kinds of `older`. Such a
+    /// path can never be used, since a transaction has a single `nLockTime`
+    /// and each input a single `nSequence`.
+    pub fn check_timelocks(&self) -> Result<(), PolicyError> {
+        if self.timelock_info().contains_unspendable_path() {
+            Err(PolicyError::HeightTimelockCombination)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Helper function to compute the kinds of timelock in each spending
+    /// path, as the type system does for `Miniscript`s
+    fn timelock_info(&self) -> TimeLockInfo {
+        match *self {
+            Policy::After(t) => TimeLockInfo::from_after(t),
+            Policy::Older(t) => TimeLockInfo::from_older(t),
+            Policy::And(ref subs) => TimeLockInfo::combine_threshold(
+                subs.len(),
+                subs.iter().map(|sub| sub.timelock_info()),
+            ),
+            Policy::Or(ref subs) => {
+                TimeLockInfo::combine_threshold(1, subs.iter().map(|sub| sub.1.timelock_info()))
+            }
+            Policy::Threshold(k, ref subs) => {
+                TimeLockInfo::combine_threshold(k, subs.iter().map(|sub| sub.timelock_info()))
+            }
+            _ => TimeLockInfo::default(),
+        }
+    }
+
     /// This returns whether the given policy is valid or not. It maybe possible that the policy
     /// contains Non-two argument `and`, `or` or a `0` arg thresh.
     pub fn is_valid(&self) -> Result<(), PolicyError> {
@@ -184,15 +348,24 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
                     Ok(())
                 }
             }
-            Policy::After(n) | Policy::Older(n) => {
-                if n == 0 {
+            Policy::After(n) => {
+                if n.to_consensus_u32() == 0 {
                     Err(PolicyError::ZeroTime)
-                } else if n > 2u32.pow(31) {
+                } else if n.to_consensus_u32() > 2u32.pow(31) {
                     Err(PolicyError::TimeTooFar)
                 } else {
                     Ok(())
                 }
             }
+            Policy::Older(n) => {
+                if n.value() == 0 {
+                    Err(PolicyError::ZeroTime)
+                } else if n.is_disabled() {
+                    Err(PolicyError::DisabledTimelock)
+                } else {
+                    Ok(())
+                }
+            }
             _ => Ok(()),
         }
     }
@@ -203,7 +376,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     ///
     pub fn is_safe_nonmalleable(&self) -> (bool, bool) {
         match *self {
-            Policy::Key(_) => (true, true),
+            Policy::Key(_) | Policy::KeyHash(_) => (true, true),
             Policy::Sha256(_)
             | Policy::Hash256(_)
             | Policy::Ripemd160(_)
@@ -250,8 +423,9 @@ impl<Pk: MiniscriptKey> fmt::Debug for Policy<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Policy::Key(ref pk) => write!(f, "pk({:?})", pk),
-            Policy::After(n) => write!(f, "after({})", n),
-            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::KeyHash(ref pkh) => write!(f, "pkh({:?})", pkh),
+            Policy::After(n) => write!(f, "after({})", n.to_consensus_u32()),
+            Policy::Older(n) => write!(f, "older({})", n.to_consensus_u32()),
             Policy::Sha256(h) => write!(f, "sha256({})", h),
             Policy::Hash256(h) => write!(f, "hash256({})", h),
             Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
@@ -291,8 +465,9 @@ impl<Pk: MiniscriptKey> fmt::Display for Policy<Pk> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Policy::Key(ref pk) => write!(f, "pk({})", pk),
-            Policy::After(n) => write!(f, "after({})", n),
-            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::KeyHash(ref pkh) => write!(f, "pkh({})", pkh),
+            Policy::After(n) => write!(f, "after({})", n.to_consensus_u32()),
+            Policy::Older(n) => write!(f, "older({})", n.to_consensus_u32()),
             Policy::Sha256(h) => write!(f, "sha256({})", h),
             Policy::Hash256(h) => write!(f, "hash256({})", h),
             Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
@@ -344,7 +519,7 @@ where
         }
 
         let tree = expression::Tree::from_str(s)?;
-        FromTree::from_tree(&tree)
+        expression::from_tree_at(&tree)
     }
 }
 
@@ -352,6 +527,7 @@ impl<Pk> Policy<Pk>
 where
     Pk: MiniscriptKey,
     <Pk as str::FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as str::FromStr>::Err: ToString,
 {
     /// Helper function for `from_tree` to parse subexpressions with
     /// names of the form x@y
@@ -384,6 +560,9 @@ where
         }
         match (frag_name, top.args.len() as u32) {
             ("pk", 1) => expression::terminal(&top.args[0], |pk| Pk::from_str(pk).map(Policy::Key)),
+            ("pkh", 1) => expression::terminal(&top.args[0], |pkh| {
+                <Pk::Hash as str::FromStr>::from_str(pkh).map(Policy::KeyHash)
+            }),
             ("after", 1) => {
                 let num = expression::terminal(&top.args[0], |x| expression::parse_num(x))?;
                 if num > 2u32.pow(31) {
@@ -391,13 +570,14 @@ where
                 } else if num == 0 {
                     return Err(Error::PolicyError(PolicyError::ZeroTime));
                 }
-                Ok(Policy::After(num))
+                Ok(Policy::After(AbsLockTime::from_consensus(num)))
             }
             ("older", 1) => {
                 let num = expression::terminal(&top.args[0], |x| expression::parse_num(x))?;
-                if num > 2u32.pow(31) {
-                    return Err(Error::PolicyError(PolicyError::TimeTooFar));
-                } else if num == 0 {
+                let num = RelLockTime::from_consensus(num);
+                if num.is_disabled() {
+                    return Err(Error::PolicyError(PolicyError::DisabledTimelock));
+                } else if num.value() == 0 {
                     return Err(Error::PolicyError(PolicyError::ZeroTime));
                 }
                 Ok(Policy::Older(num))
@@ -420,7 +600,7 @@ where
                 }
                 let mut subs = Vec::with_capacity(top.args.len());
                 for arg in &top.args {
-                    subs.push(Policy::from_tree(arg)?);
+                    subs.push(expression::from_tree_at(arg)?);
                 }
                 Ok(Policy::And(subs))
             }
@@ -446,7 +626,7 @@ where
 
                 let mut subs = Vec::with_capacity(top.args.len() - 1);
                 for arg in &top.args[1..] {
-                    subs.push(Policy::from_tree(arg)?);
+                    subs.push(expression::from_tree_at(arg)?);
                 }
                 Ok(Policy::Threshold(thresh as usize, subs))
             }
@@ -460,6 +640,7 @@ impl<Pk> FromTree for Policy<Pk>
 where
     Pk: MiniscriptKey,
     <Pk as str::FromStr>::Err: ToString,
+    <<Pk as MiniscriptKey>::Hash as str::FromStr>::Err: ToString,
 {
     fn from_tree(top: &expression::Tree) -> Result<Policy<Pk>, Error> {
         Policy::from_tree_prob(top, false).map(|(_, result)| result)
@@ -27,10 +27,15 @@ pub mod compiler;
 pub mod concrete;
 pub mod semantic;
 
+use std::fmt;
+
 use descriptor::Descriptor;
 use miniscript::{Miniscript, ScriptContext};
 use Terminal;
 
+use self::concrete::PolicyError;
+use self::semantic::SpendingPath;
+
 pub use self::concrete::Policy as Concrete;
 /// Semantic policies are "abstract" policies elsewhere; but we
 /// avoid this word because it is a reserved keyword in Rust
@@ -45,18 +50,134 @@ use MiniscriptKey;
 pub trait Liftable<Pk: MiniscriptKey> {
     /// Convert the object into an abstract policy
     fn lift(&self) -> Semantic<Pk>;
+
+    /// Convert the object into an abstract policy, keeping each key as a
+    /// `Key` wherever the object has it rather than only its hash (as for
+    /// `pk_h`), e.g. to match the policy against a list of known keys.
+    /// Calling `hash_keys` on the result gives `lift`.
+    fn lift_keys(&self) -> Semantic<Pk> {
+        self.lift()
+    }
+
+    /// Compare the spending paths of this object with those of `new`,
+    /// e.g. an upgraded version of it. Fails if either of them has more
+    /// than `max_paths` minimal spending paths.
+    fn diff<L: Liftable<Pk>>(
+        &self,
+        new: &L,
+        max_paths: usize,
+    ) -> Result<PolicyDiff<Pk>, PolicyError>
+    where
+        Self: Sized,
+    {
+        let old_paths = self.lift().minimal_spending_paths(max_paths)?;
+        let new_paths = new.lift().minimal_spending_paths(max_paths)?;
+
+        let mut ret = PolicyDiff {
+            added: vec![],
+            removed: vec![],
+            weakened: vec![],
+            strengthened: vec![],
+        };
+        let mut old_matched = vec![false; old_paths.len()];
+        for new_path in &new_paths {
+            if old_paths.contains(new_path) {
+                continue;
+            }
+            // The first changed old path which requires more than `new_path`
+            // (`weaker`) or less than it (`!weaker`)
+            let find = |weaker: bool| {
+                old_paths.iter().position(|old_path| {
+                    !new_paths.contains(old_path)
+                        && if weaker {
+                            old_path.implies(new_path)
+                        } else {
+                            new_path.implies(old_path)
+                        }
+                })
+            };
+            if let Some(i) = find(true) {
+                old_matched[i] = true;
+                ret.weakened.push((old_paths[i].clone(), new_path.clone()));
+            } else if let Some(i) = find(false) {
+                old_matched[i] = true;
+                ret.strengthened
+                    .push((old_paths[i].clone(), new_path.clone()));
+            } else {
+                ret.added.push(new_path.clone());
+            }
+        }
+        for (i, old_path) in old_paths.iter().enumerate() {
+            if !old_matched[i] && !new_paths.contains(old_path) {
+                ret.removed.push(old_path.clone());
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// Differences between the minimal spending paths of two policies, as
+/// computed by `Liftable::diff`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PolicyDiff<Pk: MiniscriptKey> {
+    /// Spending paths of the new policy unrelated to any of the old one
+    pub added: Vec<SpendingPath<Pk>>,
+    /// Spending paths of the old policy unrelated to any of the new one
+    pub removed: Vec<SpendingPath<Pk>>,
+    /// Spending paths of the old policy replaced by ones requiring strictly
+    /// less, as `(old, new)` pairs
+    pub weakened: Vec<(SpendingPath<Pk>, SpendingPath<Pk>)>,
+    /// Spending paths of the old policy replaced by ones requiring strictly
+    /// more, as `(old, new)` pairs
+    pub strengthened: Vec<(SpendingPath<Pk>, SpendingPath<Pk>)>,
+}
+
+impl<Pk: MiniscriptKey> PolicyDiff<Pk> {
+    /// Whether both policies have the same spending paths
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.weakened.is_empty()
+            && self.strengthened.is_empty()
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PolicyDiff<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for path in &self.added {
+            writeln!(f, "+ {}", path)?;
+        }
+        for path in &self.removed {
+            writeln!(f, "- {}", path)?;
+        }
+        for pair in &self.weakened {
+            writeln!(f, "~ {} -> {} (weaker)", pair.0, pair.1)?;
+        }
+        for pair in &self.strengthened {
+            writeln!(f, "~ {} -> {} (stronger)", pair.0, pair.1)?;
+        }
+        Ok(())
+    }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Miniscript<Pk, Ctx> {
     fn lift(&self) -> Semantic<Pk> {
         self.as_inner().lift()
     }
+
+    fn lift_keys(&self) -> Semantic<Pk> {
+        self.as_inner().lift_keys()
+    }
 }
 
 impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Terminal<Pk, Ctx> {
     fn lift(&self) -> Semantic<Pk> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<Pk> {
         match *self {
-            Terminal::PkK(ref pk) => Semantic::KeyHash(pk.to_pubkeyhash()),
+            Terminal::PkK(ref pk) => Semantic::Key(pk.clone()),
             Terminal::PkH(ref pkh) => Semantic::KeyHash(pkh.clone()),
             Terminal::After(t) => Semantic::After(t),
             Terminal::Older(t) => Semantic::Older(t),
@@ -72,28 +193,26 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Terminal<Pk, Ctx> {
             | Terminal::DupIf(ref sub)
             | Terminal::Verify(ref sub)
             | Terminal::NonZero(ref sub)
-            | Terminal::ZeroNotEqual(ref sub) => sub.node.lift(),
+            | Terminal::ZeroNotEqual(ref sub) => sub.node.lift_keys(),
             Terminal::AndV(ref left, ref right) | Terminal::AndB(ref left, ref right) => {
-                Semantic::And(vec![left.node.lift(), right.node.lift()])
+                Semantic::And(vec![left.node.lift_keys(), right.node.lift_keys()])
             }
             Terminal::AndOr(ref a, ref b, ref c) => Semantic::Or(vec![
-                Semantic::And(vec![a.node.lift(), c.node.lift()]),
-                b.node.lift(),
+                Semantic::And(vec![a.node.lift_keys(), b.node.lift_keys()]),
+                c.node.lift_keys(),
             ]),
             Terminal::OrB(ref left, ref right)
             | Terminal::OrD(ref left, ref right)
             | Terminal::OrC(ref left, ref right)
             | Terminal::OrI(ref left, ref right) => {
-                Semantic::Or(vec![left.node.lift(), right.node.lift()])
+                Semantic::Or(vec![left.node.lift_keys(), right.node.lift_keys()])
             }
             Terminal::Thresh(k, ref subs) => {
-                Semantic::Threshold(k, subs.into_iter().map(|s| s.node.lift()).collect())
+                Semantic::Threshold(k, subs.into_iter().map(|s| s.node.lift_keys()).collect())
             }
             Terminal::Multi(k, ref keys) => Semantic::Threshold(
                 k,
-                keys.into_iter()
-                    .map(|k| Semantic::KeyHash(k.to_pubkeyhash()))
-                    .collect(),
+                keys.into_iter().map(|k| Semantic::Key(k.clone())).collect(),
             ),
         }
         .normalized()
@@ -102,50 +221,186 @@ impl<Pk: MiniscriptKey, Ctx: ScriptContext> Liftable<Pk> for Terminal<Pk, Ctx> {
 
 impl<Pk: MiniscriptKey> Liftable<Pk> for Descriptor<Pk> {
     fn lift(&self) -> Semantic<Pk> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<Pk> {
         match *self {
-            Descriptor::Bare(ref d) | Descriptor::Sh(ref d) => d.node.lift(),
-            Descriptor::Wsh(ref d) | Descriptor::ShWsh(ref d) => d.node.lift(),
+            Descriptor::Bare(ref d) | Descriptor::Sh(ref d) => d.node.lift_keys(),
+            Descriptor::Wsh(ref d) | Descriptor::ShWsh(ref d) => d.node.lift_keys(),
             Descriptor::Pk(ref p)
             | Descriptor::Pkh(ref p)
             | Descriptor::Wpkh(ref p)
-            | Descriptor::ShWpkh(ref p) => Semantic::KeyHash(p.to_pubkeyhash()),
+            | Descriptor::ShWpkh(ref p) => Semantic::Key(p.clone()),
         }
     }
 }
 
 impl<Pk: MiniscriptKey> Liftable<Pk> for Semantic<Pk> {
     fn lift(&self) -> Semantic<Pk> {
+        self.clone().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<Pk> {
         self.clone()
     }
 }
 
 impl<Pk: MiniscriptKey> Liftable<Pk> for Concrete<Pk> {
     fn lift(&self) -> Semantic<Pk> {
+        self.lift_keys().hash_keys()
+    }
+
+    fn lift_keys(&self) -> Semantic<Pk> {
         match *self {
-            Concrete::Key(ref pk) => Semantic::KeyHash(pk.to_pubkeyhash()),
+            Concrete::Key(ref pk) => Semantic::Key(pk.clone()),
+            Concrete::KeyHash(ref pkh) => Semantic::KeyHash(pkh.clone()),
             Concrete::After(t) => Semantic::After(t),
             Concrete::Older(t) => Semantic::Older(t),
             Concrete::Sha256(h) => Semantic::Sha256(h),
             Concrete::Hash256(h) => Semantic::Hash256(h),
             Concrete::Ripemd160(h) => Semantic::Ripemd160(h),
             Concrete::Hash160(h) => Semantic::Hash160(h),
-            Concrete::And(ref subs) => Semantic::And(subs.iter().map(Liftable::lift).collect()),
+            Concrete::And(ref subs) => {
+                Semantic::And(subs.iter().map(Liftable::lift_keys).collect())
+            }
             Concrete::Or(ref subs) => {
-                Semantic::Or(subs.iter().map(|&(_, ref sub)| sub.lift()).collect())
+                Semantic::Or(subs.iter().map(|&(_, ref sub)| sub.lift_keys()).collect())
             }
             Concrete::Threshold(k, ref subs) => {
-                Semantic::Threshold(k, subs.iter().map(Liftable::lift).collect())
+                Semantic::Threshold(k, subs.iter().map(Liftable::lift_keys).collect())
             }
         }
         .normalized()
     }
 }
 
+impl<Pk: MiniscriptKey, Ctx: ScriptContext> Miniscript<Pk, Ctx> {
+    /// Reconstruct a concrete policy with the same `and`/`or`/`thresh`
+    /// structure as the Miniscript, e.g. to recompile an existing script
+    /// and see whether the compiler finds a cheaper equivalent. Keys used
+    /// with `pk_k` are kept as keys, and only `pk_h` ones become key hashes.
+    /// Branch probabilities are not recoverable from a script: the
+    /// compiler only uses them to choose between fragments, and a script
+    /// does not record them. Every `or` branch of the result therefore
+    /// gets weight 1, i.e. all branches are taken to be equally likely;
+    /// reweight the result before recompiling if better odds are known.
+    /// Fails if the Miniscript is trivially satisfiable or unsatisfiable,
+    /// which no concrete policy is.
+    pub fn decompile(&self) -> Result<Concrete<Pk>, PolicyError> {
+        match decompile(&self.node) {
+            Decompiled::Policy(policy) => Ok(policy),
+            Decompiled::Trivial => Err(PolicyError::TriviallySatisfiable),
+            Decompiled::Unsatisfiable => Err(PolicyError::Unsatisfiable),
+        }
+    }
+}
+
+/// A decompiled fragment, which unlike a concrete policy may be trivially
+/// satisfiable (e.g. `1`) or unsatisfiable (e.g. `0`)
+enum Decompiled<Pk: MiniscriptKey> {
+    Trivial,
+    Unsatisfiable,
+    Policy(Concrete<Pk>),
+}
+
+/// Helper function to do the recursion in `Miniscript::decompile`
+fn decompile<Pk: MiniscriptKey, Ctx: ScriptContext>(node: &Terminal<Pk, Ctx>) -> Decompiled<Pk> {
+    match *node {
+        Terminal::True => Decompiled::Trivial,
+        Terminal::False => Decompiled::Unsatisfiable,
+        Terminal::PkK(ref pk) => Decompiled::Policy(Concrete::Key(pk.clone())),
+        Terminal::PkH(ref pkh) => Decompiled::Policy(Concrete::KeyHash(pkh.clone())),
+        Terminal::After(t) => Decompiled::Policy(Concrete::After(t)),
+        Terminal::Older(t) => Decompiled::Policy(Concrete::Older(t)),
+        Terminal::Sha256(h) => Decompiled::Policy(Concrete::Sha256(h)),
+        Terminal::Hash256(h) => Decompiled::Policy(Concrete::Hash256(h)),
+        Terminal::Ripemd160(h) => Decompiled::Policy(Concrete::Ripemd160(h)),
+        Terminal::Hash160(h) => Decompiled::Policy(Concrete::Hash160(h)),
+        Terminal::Alt(ref sub)
+        | Terminal::Swap(ref sub)
+        | Terminal::Check(ref sub)
+        | Terminal::DupIf(ref sub)
+        | Terminal::Verify(ref sub)
+        | Terminal::NonZero(ref sub)
+        | Terminal::ZeroNotEqual(ref sub) => decompile(&sub.node),
+        Terminal::AndV(ref left, ref right) | Terminal::AndB(ref left, ref right) => {
+            decompile_and(decompile(&left.node), decompile(&right.node))
+        }
+        Terminal::AndOr(ref a, ref b, ref c) => decompile_or(
+            decompile_and(decompile(&a.node), decompile(&b.node)),
+            decompile(&c.node),
+        ),
+        Terminal::OrB(ref left, ref right)
+        | Terminal::OrD(ref left, ref right)
+        | Terminal::OrC(ref left, ref right)
+        | Terminal::OrI(ref left, ref right) => {
+            decompile_or(decompile(&left.node), decompile(&right.node))
+        }
+        Terminal::Thresh(k, ref subs) => {
+            decompile_thresh(k, subs.iter().map(|sub| decompile(&sub.node)).collect())
+        }
+        Terminal::Multi(k, ref keys) => decompile_thresh(
+            k,
+            keys.iter()
+                .map(|pk| Decompiled::Policy(Concrete::Key(pk.clone())))
+                .collect(),
+        ),
+    }
+}
+
+/// Conjunction of two decompiled fragments
+fn decompile_and<Pk: MiniscriptKey>(left: Decompiled<Pk>, right: Decompiled<Pk>) -> Decompiled<Pk> {
+    match (left, right) {
+        (Decompiled::Unsatisfiable, _) | (_, Decompiled::Unsatisfiable) => {
+            Decompiled::Unsatisfiable
+        }
+        (Decompiled::Trivial, x) | (x, Decompiled::Trivial) => x,
+        (Decompiled::Policy(l), Decompiled::Policy(r)) => {
+            Decompiled::Policy(Concrete::And(vec![l, r]))
+        }
+    }
+}
+
+/// Disjunction of two decompiled fragments, with equal probabilities
+fn decompile_or<Pk: MiniscriptKey>(left: Decompiled<Pk>, right: Decompiled<Pk>) -> Decompiled<Pk> {
+    match (left, right) {
+        (Decompiled::Trivial, _) | (_, Decompiled::Trivial) => Decompiled::Trivial,
+        (Decompiled::Unsatisfiable, x) | (x, Decompiled::Unsatisfiable) => x,
+        (Decompiled::Policy(l), Decompiled::Policy(r)) => {
+            // The script does not say which branch is likelier
+            Decompiled::Policy(Concrete::Or(vec![(1, l), (1, r)]))
+        }
+    }
+}
+
+/// `k`-of-n threshold of decompiled fragments
+fn decompile_thresh<Pk: MiniscriptKey>(mut k: usize, subs: Vec<Decompiled<Pk>>) -> Decompiled<Pk> {
+    let mut policies = Vec::with_capacity(subs.len());
+    for sub in subs {
+        match sub {
+            Decompiled::Trivial => k = k.saturating_sub(1),
+            Decompiled::Unsatisfiable => {}
+            Decompiled::Policy(policy) => policies.push(policy),
+        }
+    }
+    if k == 0 {
+        Decompiled::Trivial
+    } else if k > policies.len() {
+        Decompiled::Unsatisfiable
+    } else if policies.len() == 1 {
+        Decompiled::Policy(policies.pop().unwrap())
+    } else {
+        Decompiled::Policy(Concrete::Threshold(k, policies))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Concrete, Semantic};
+    use super::{Concrete, Liftable, PolicyError, Semantic};
     use std::str::FromStr;
     use DummyKey;
+    use {Miniscript, Segwitv0};
 
     type ConcretePol = Concrete<DummyKey>;
     type SemanticPol = Semantic<DummyKey>;
@@ -167,6 +422,8 @@ mod tests {
         concrete_policy_rtt("pk()");
         concrete_policy_rtt("or(1@pk(),1@pk())");
         concrete_policy_rtt("or(99@pk(),1@pk())");
+        concrete_policy_rtt("pkh()");
+        concrete_policy_rtt("or(1@pk(),1@pkh())");
         concrete_policy_rtt("and(pk(),or(99@pk(),1@older(12960)))");
 
         semantic_policy_rtt("pkh()");
@@ -186,34 +443,46 @@ mod tests {
             ConcretePol::from_str("thresh(2,pk(),thresh(0))")
                 .unwrap_err()
                 .to_string(),
-            "Threshold k must be greater than 0 and less than or equal to n 0<k<=n"
+            "Threshold k must be greater than 0 and less than or equal to n 0<k<=n in «thresh» at byte 14"
         );
         assert_eq!(
             ConcretePol::from_str("thresh(2,pk(),thresh(0,pk()))")
                 .unwrap_err()
                 .to_string(),
-            "Threshold k must be greater than 0 and less than or equal to n 0<k<=n"
+            "Threshold k must be greater than 0 and less than or equal to n 0<k<=n in «thresh» at byte 14"
         );
         assert_eq!(
             ConcretePol::from_str("and(pk())").unwrap_err().to_string(),
-            "And policy fragment must take 2 arguments"
+            "And policy fragment must take 2 arguments in «and» at byte 0"
         );
         assert_eq!(
             ConcretePol::from_str("or(pk())").unwrap_err().to_string(),
-            "Or policy fragment must take 2 arguments"
+            "Or policy fragment must take 2 arguments in «or» at byte 0"
         );
         assert_eq!(
             ConcretePol::from_str("thresh(3,after(0),pk(),pk())")
                 .unwrap_err()
                 .to_string(),
-            "Time must be greater than 0; n > 0"
+            "Time must be greater than 0; n > 0 in «after» at byte 9"
         );
 
         assert_eq!(
             ConcretePol::from_str("thresh(2,older(2147483650),pk(),pk())")
                 .unwrap_err()
                 .to_string(),
-            "Relative/Absolute time must be less than 2^31; n < 2^31"
+            "Relative time must not have the disable flag (1 << 31) set in «older» at byte 9"
+        );
+        assert_eq!(
+            ConcretePol::from_str("after(2147483650)")
+                .unwrap_err()
+                .to_string(),
+            "Relative/Absolute time must be less than 2^31; n < 2^31 in «after» at byte 0"
+        );
+        assert_eq!(
+            ConcretePol::from_str("older(4194304)")
+                .unwrap_err()
+                .to_string(),
+            "Time must be greater than 0; n > 0 in «older» at byte 0"
         );
     }
 
@@ -223,4 +492,131 @@ mod tests {
         let policy_string = "thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk(),thresh(1,pk(),pk(),pk()))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))))";
         ConcretePol::from_str(&policy_string).unwrap_err();
     }
+
+    #[test]
+    fn lift_andor() {
+        let ms = Miniscript::<String, Segwitv0>::from_str("andor(pk(A),pk(B),pk(C))").unwrap();
+        assert_eq!(
+            ms.lift(),
+            Semantic::from_str("or(and(pkh(A),pkh(B)),pkh(C))").unwrap()
+        );
+    }
+
+    #[test]
+    fn policy_diff() {
+        let old = Concrete::<String>::from_str(
+            "or(and(pk(A),pk(B)),or(and(pk(A),older(1000)),and(pk(C),older(5000))))",
+        )
+        .unwrap();
+        let new = Miniscript::<String, Segwitv0>::from_str(
+            "or_d(multi(2,A,B,D),or_i(and_v(v:pk(A),older(2000)),and_v(v:pk(C),pk(E))))",
+        )
+        .unwrap();
+
+        let diff = old.diff(&new, 100).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "+ and(pkh(A),pkh(D))\n\
+             + and(pkh(B),pkh(D))\n\
+             + and(pkh(C),pkh(E))\n\
+             - and(pkh(C),older(5000))\n\
+             ~ and(pkh(A),older(1000)) -> and(pkh(A),older(2000)) (stronger)\n"
+        );
+        assert_eq!(diff.strengthened.len(), 1);
+        assert!(diff.weakened.is_empty());
+        assert!(old.diff(&old.lift(), 100).unwrap().is_empty());
+
+        let weaker = Concrete::<String>::from_str("or(pk(A),and(pk(C),older(5000)))").unwrap();
+        let diff = old.diff(&weaker, 100).unwrap();
+        assert_eq!(diff.weakened.len(), 1);
+        assert_eq!(diff.weakened[0].1.to_string(), "pkh(A)");
+
+        // Paths with more conditions than a truth table could compare
+        let big = |prefix: &str, n: usize| {
+            let keys: Vec<String> = (0..n).map(|i| format!("pkh({}{})", prefix, i)).collect();
+            Semantic::<String>::from_str(&format!("thresh({},{})", n, keys.join(","))).unwrap()
+        };
+        let diff = big("A", 11).diff(&big("B", 11), 100).unwrap();
+        assert_eq!(diff.added, big("B", 11).minimal_spending_paths(1).unwrap());
+        assert_eq!(
+            diff.removed,
+            big("A", 11).minimal_spending_paths(1).unwrap()
+        );
+        let diff = big("A", 11).diff(&big("A", 12), 100).unwrap();
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(diff.strengthened.len(), 1);
+        assert_eq!(diff.strengthened[0].1.n_signatures(), 12);
+    }
+
+    #[test]
+    fn decompile() {
+        let ms = Miniscript::<String, Segwitv0>::from_str(
+            "andor(pk(A),older(144),or_d(multi(2,B,C,D),and_v(v:pkh(E),after(500))))",
+        )
+        .unwrap();
+        let policy = ms.decompile().unwrap();
+        assert_eq!(
+            policy.to_string(),
+            "or(1@and(pk(A),older(144)),1@or(1@thresh(2,pk(B),pk(C),pk(D)),1@and(pkh(E),after(500))))"
+        );
+        assert_eq!(policy.lift(), ms.lift());
+
+        // Trivial and unsatisfiable branches are dropped
+        let ms = Miniscript::<String, Segwitv0>::from_str(
+            "or_i(and_v(v:pk(A),0),thresh(2,pk(B),s:pk(C),a:0))",
+        )
+        .unwrap();
+        assert_eq!(ms.decompile().unwrap().to_string(), "thresh(2,pk(B),pk(C))");
+
+        let ms = Miniscript::<String, Segwitv0>::from_str("and_v(v:pk(A),0)").unwrap();
+        assert_eq!(ms.decompile(), Err(PolicyError::Unsatisfiable));
+        let ms = Miniscript::<String, Segwitv0>::from_str("or_i(pk(A),1)").unwrap();
+        assert_eq!(ms.decompile(), Err(PolicyError::TriviallySatisfiable));
+    }
+
+    #[test]
+    #[cfg(feature = "compiler")]
+    fn decompile_recompile() {
+        // `and_b` costs an extra `OP_BOOLAND` over `and_v`
+        let ms = Miniscript::<DummyKey, Segwitv0>::from_str("and_b(pk(),s:pk())").unwrap();
+        let policy = ms.decompile().unwrap();
+        assert_eq!(policy.to_string(), "and(pk(),pk())");
+        let recompiled = policy.compile::<Segwitv0>().unwrap();
+        assert_eq!(recompiled.lift(), ms.lift());
+        assert!(recompiled.script_size() <= ms.script_size());
+        assert_eq!(recompiled.decompile().unwrap(), policy);
+    }
+
+    #[test]
+    fn lift_keys() {
+        let ms = Miniscript::<String, Segwitv0>::from_str(
+            "or_d(multi(1,A,B),and_v(v:pkh(C),older(144)))",
+        )
+        .unwrap();
+        let with_keys = ms.lift_keys();
+        assert_eq!(
+            with_keys.to_string(),
+            "or(thresh(1,pk(A),pk(B)),and(pkh(C),older(144)))"
+        );
+        assert_eq!(with_keys.clone().hash_keys(), ms.lift());
+        assert_eq!(with_keys.lift(), ms.lift());
+        assert_eq!(with_keys.is_equivalent(&ms.lift()), Ok(true));
+        assert_eq!(
+            with_keys.minimal_spending_paths(10),
+            ms.lift().minimal_spending_paths(10)
+        );
+        assert_eq!(
+            with_keys
+                .clone()
+                .without_keys(&["A".to_owned()])
+                .to_string(),
+            "or(pk(B),and(pkh(C),older(144)))"
+        );
+        semantic_policy_rtt("or(pk(),pkh())");
+
+        let policy = Concrete::<String>::from_str("and(pk(A),pkh(B))").unwrap();
+        assert_eq!(policy.lift_keys().to_string(), "and(pk(A),pkh(B))");
+        assert_eq!(policy.lift().to_string(), "and(pkh(A),pkh(B))");
+    }
 }
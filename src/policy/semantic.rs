@@ -16,31 +16,47 @@
 
 use bitcoin::hashes::hex::FromHex;
 use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d};
-use std::{fmt, str};
+use std::collections::BTreeMap;
+use std::{cmp, error, fmt, str};
 
 use super::concrete::PolicyError;
 use errstr;
 use std::str::FromStr;
 use Error;
 use {expression, MiniscriptKey};
+use {AbsLockTime, RelLockTime};
+
+/// Maximum number of rows in the truth table built by `Policy::implies`
+/// and `Policy::is_equivalent`
+pub const MAX_TRUTH_TABLE_ROWS: usize = 1 << 20;
+
+/// Maximum number of nodes `Policy::simplified` may produce when splitting
+/// the duplicate children out of a threshold; larger thresholds are kept
+/// as they are
+pub const MAX_SIMPLIFIED_SIZE: usize = 1000;
+
 /// Abstract policy which corresponds to the semantics of a Miniscript
 /// and which allows complex forms of analysis, e.g. filtering and
 /// normalization.
 /// Semantic policies store only hashes of keys to ensure that objects
 /// representing the same policy are lifted to the same `Semantic`,
-/// regardless of their choice of `pk` or `pk_h` nodes.
+/// regardless of their choice of `pk` or `pk_h` nodes. The exception is
+/// `Liftable::lift_keys`, which keeps the keys it has; `hash_keys` turns
+/// its output into that of `lift`.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Policy<Pk: MiniscriptKey> {
     /// Unsatisfiable
     Unsatisfiable,
     /// Trivially satisfiable
     Trivial,
+    /// Signature for a given public key is required
+    Key(Pk),
     /// Signature and public key matching a given hash is required
     KeyHash(Pk::Hash),
-    /// A relative locktime restriction
-    After(u32),
     /// An absolute locktime restriction
-    Older(u32),
+    After(AbsLockTime),
+    /// A relative locktime restriction
+    Older(RelLockTime),
     /// A SHA256 whose preimage must be provided to satisfy the descriptor
     Sha256(sha256::Hash),
     /// A SHA256d whose preimage must be provided to satisfy the descriptor
@@ -59,7 +75,7 @@ pub enum Policy<Pk: MiniscriptKey> {
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Convert a policy using one kind of public key to another
-    /// type of public key
+    /// type of public key. Keys are hashed before being translated.
     pub fn translate_pkh<Fpkh, Q, E>(&self, mut translatefpkh: Fpkh) -> Result<Policy<Q>, E>
     where
         Fpkh: FnMut(&Pk::Hash) -> Result<Q::Hash, E>,
@@ -68,6 +84,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         match *self {
             Policy::Unsatisfiable => Ok(Policy::Unsatisfiable),
             Policy::Trivial => Ok(Policy::Trivial),
+            Policy::Key(ref pk) => translatefpkh(&pk.to_pubkeyhash()).map(Policy::KeyHash),
             Policy::KeyHash(ref pkh) => translatefpkh(pkh).map(Policy::KeyHash),
             Policy::Sha256(ref h) => Ok(Policy::Sha256(h.clone())),
             Policy::Hash256(ref h) => Ok(Policy::Hash256(h.clone())),
@@ -101,9 +118,10 @@ impl<Pk: MiniscriptKey> fmt::Debug for Policy<Pk> {
         match *self {
             Policy::Unsatisfiable => f.write_str("UNSATISFIABLE()"),
             Policy::Trivial => f.write_str("TRIVIAL()"),
+            Policy::Key(ref pk) => write!(f, "pk({:?})", pk),
             Policy::KeyHash(ref pkh) => write!(f, "pkh({:?})", pkh),
-            Policy::After(n) => write!(f, "after({})", n),
-            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::After(n) => write!(f, "after({})", n.to_consensus_u32()),
+            Policy::Older(n) => write!(f, "older({})", n.to_consensus_u32()),
             Policy::Sha256(h) => write!(f, "sha256({})", h),
             Policy::Hash256(h) => write!(f, "hash256({})", h),
             Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
@@ -144,9 +162,10 @@ impl<Pk: MiniscriptKey> fmt::Display for Policy<Pk> {
         match *self {
             Policy::Unsatisfiable => f.write_str("UNSATISFIABLE"),
             Policy::Trivial => f.write_str("TRIVIAL"),
+            Policy::Key(ref pk) => write!(f, "pk({})", pk),
             Policy::KeyHash(ref pkh) => write!(f, "pkh({})", pkh),
-            Policy::After(n) => write!(f, "after({})", n),
-            Policy::Older(n) => write!(f, "older({})", n),
+            Policy::After(n) => write!(f, "after({})", n.to_consensus_u32()),
+            Policy::Older(n) => write!(f, "older({})", n.to_consensus_u32()),
             Policy::Sha256(h) => write!(f, "sha256({})", h),
             Policy::Hash256(h) => write!(f, "hash256({})", h),
             Policy::Ripemd160(h) => write!(f, "ripemd160({})", h),
@@ -198,7 +217,7 @@ where
         }
 
         let tree = expression::Tree::from_str(s)?;
-        expression::FromTree::from_tree(&tree)
+        expression::from_tree_at(&tree)
     }
 }
 
@@ -212,14 +231,15 @@ where
         match (top.name, top.args.len() as u32) {
             ("UNSATISFIABLE", 0) => Ok(Policy::Unsatisfiable),
             ("TRIVIAL", 0) => Ok(Policy::Trivial),
+            ("pk", 1) => expression::terminal(&top.args[0], |pk| Pk::from_str(pk).map(Policy::Key)),
             ("pkh", 1) => expression::terminal(&top.args[0], |pk| {
                 Pk::Hash::from_str(pk).map(Policy::KeyHash)
             }),
             ("after", 1) => expression::terminal(&top.args[0], |x| {
-                expression::parse_num(x).map(Policy::After)
+                expression::parse_num(x).map(|x| Policy::After(AbsLockTime::from_consensus(x)))
             }),
             ("older", 1) => expression::terminal(&top.args[0], |x| {
-                expression::parse_num(x).map(Policy::Older)
+                expression::parse_num(x).map(|x| Policy::Older(RelLockTime::from_consensus(x)))
             }),
             ("sha256", 1) => expression::terminal(&top.args[0], |x| {
                 sha256::Hash::from_hex(x).map(Policy::Sha256)
@@ -239,7 +259,7 @@ where
                 }
                 let mut subs = Vec::with_capacity(top.args.len());
                 for arg in &top.args {
-                    subs.push(Policy::from_tree(arg)?);
+                    subs.push(expression::from_tree_at(arg)?);
                 }
                 Ok(Policy::And(subs))
             }
@@ -249,7 +269,7 @@ where
                 }
                 let mut subs = Vec::with_capacity(top.args.len());
                 for arg in &top.args {
-                    subs.push(Policy::from_tree(arg)?);
+                    subs.push(expression::from_tree_at(arg)?);
                 }
                 Ok(Policy::Or(subs))
             }
@@ -268,7 +288,7 @@ where
 
                 let mut subs = Vec::with_capacity(top.args.len() - 1);
                 for arg in &top.args[1..] {
-                    subs.push(Policy::from_tree(arg)?);
+                    subs.push(expression::from_tree_at(arg)?);
                 }
                 Ok(Policy::Threshold(thresh as usize, subs))
             }
@@ -279,7 +299,13 @@ where
 
 impl<Pk: MiniscriptKey> Policy<Pk> {
     /// Flatten out trees of `And`s and `Or`s; eliminate `Trivial` and
-    /// `Unsatisfiable`s. Does not reorder any branches; use `.sort`.
+    /// `Unsatisfiable`s, also from thresholds. Does not reorder any branches;
+    /// use `.sort`. An `And` or `Threshold` which cannot be satisfied
+    /// without timelocks of different units among its direct children,
+    /// e.g. `thresh(2,after(100),after(1600000000))`, becomes
+    /// `Unsatisfiable`; timelocks further down, as in
+    /// `thresh(2,and(pkh(A),after(100)),after(1600000000))`, are not
+    /// compared (see `check_timelocks` and `minimal_spending_paths`).
     pub fn normalized(self) -> Policy<Pk> {
         match self {
             Policy::And(subs) => {
@@ -292,6 +318,9 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
                         x => ret_subs.push(x),
                     }
                 }
+                if mixes_timelock_units(&ret_subs) {
+                    return Policy::Unsatisfiable;
+                }
                 match ret_subs.len() {
                     0 => Policy::Trivial,
                     1 => ret_subs.pop().unwrap(),
@@ -301,7 +330,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
             Policy::Or(subs) => {
                 let mut ret_subs = Vec::with_capacity(subs.len());
                 for sub in subs {
-                    match sub {
+                    match sub.normalized() {
                         Policy::Trivial => return Policy::Trivial,
                         Policy::Unsatisfiable => {}
                         Policy::Or(or_subs) => ret_subs.extend(or_subs),
@@ -309,11 +338,30 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
                     }
                 }
                 match ret_subs.len() {
-                    0 => Policy::Trivial,
+                    0 => Policy::Unsatisfiable,
                     1 => ret_subs.pop().unwrap(),
                     _ => Policy::Or(ret_subs),
                 }
             }
+            Policy::Threshold(mut k, subs) => {
+                let mut ret_subs = Vec::with_capacity(subs.len());
+                for sub in subs {
+                    match sub.normalized() {
+                        Policy::Trivial => k = k.saturating_sub(1),
+                        Policy::Unsatisfiable => {}
+                        x => ret_subs.push(x),
+                    }
+                }
+                if k == 0 {
+                    Policy::Trivial
+                } else if k > max_compatible(&ret_subs) {
+                    Policy::Unsatisfiable
+                } else if ret_subs.len() == 1 {
+                    ret_subs.pop().unwrap()
+                } else {
+                    Policy::Threshold(k, ret_subs)
+                }
+            }
             x => x,
         }
     }
@@ -335,10 +383,11 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 
     /// Helper function to do the recursion in `timelocks`.
-    fn real_relative_timelocks(&self) -> Vec<u32> {
+    fn real_relative_timelocks(&self) -> Vec<RelLockTime> {
         match *self {
             Policy::Unsatisfiable
             | Policy::Trivial
+            | Policy::Key(..)
             | Policy::KeyHash(..)
             | Policy::Sha256(..)
             | Policy::Hash256(..)
@@ -361,7 +410,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
 
     /// Returns a list of all relative timelocks, not including 0,
     /// which appear in the policy
-    pub fn relative_timelocks(&self) -> Vec<u32> {
+    pub fn relative_timelocks(&self) -> Vec<RelLockTime> {
         let mut ret = self.real_relative_timelocks();
         ret.sort();
         ret.dedup();
@@ -369,11 +418,13 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 
     /// Filter a policy by eliminating relative timelock constraints
-    /// that are not satisfied at the given age.
+    /// that are not satisfied at the given age, given as the `nSequence`
+    /// of the spending input. Timelocks in the other unit (blocks vs.
+    /// 512-second intervals) are never satisfied.
     pub fn at_age(mut self, time: u32) -> Policy<Pk> {
         self = match self {
             Policy::Older(t) => {
-                if t > time {
+                if !t.is_satisfied_by(time) {
                     Policy::Unsatisfiable
                 } else {
                     Policy::Older(t)
@@ -391,12 +442,92 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
         self.normalized()
     }
 
+    /// Helper function to replace each leaf of the policy by `f(leaf)`,
+    /// keeping the structure above the leaves
+    fn replace_leaves<F>(self, f: &mut F) -> Policy<Pk>
+    where
+        F: FnMut(Policy<Pk>) -> Policy<Pk>,
+    {
+        match self {
+            Policy::And(subs) => {
+                Policy::And(subs.into_iter().map(|sub| sub.replace_leaves(f)).collect())
+            }
+            Policy::Or(subs) => {
+                Policy::Or(subs.into_iter().map(|sub| sub.replace_leaves(f)).collect())
+            }
+            Policy::Threshold(k, subs) => Policy::Threshold(
+                k,
+                subs.into_iter().map(|sub| sub.replace_leaves(f)).collect(),
+            ),
+            x => f(x),
+        }
+    }
+
+    /// Filter a policy by eliminating absolute timelock constraints
+    /// that are not satisfied at the given block height. Timelocks
+    /// given as a UNIX timestamp are left in place.
+    pub fn at_height(self, height: u32) -> Policy<Pk> {
+        self.replace_leaves(&mut |leaf| match leaf {
+            Policy::After(t) if t.is_block_height() && !t.is_satisfied_by(height) => {
+                Policy::Unsatisfiable
+            }
+            x => x,
+        })
+        .normalized()
+    }
+
+    /// Filter a policy by eliminating absolute timelock constraints
+    /// that are not satisfied at the given UNIX time. Timelocks
+    /// given as a block height are left in place.
+    pub fn at_time(self, time: u32) -> Policy<Pk> {
+        self.replace_leaves(&mut |leaf| match leaf {
+            Policy::After(t) if t.is_block_time() && !t.is_satisfied_by(time) => {
+                Policy::Unsatisfiable
+            }
+            x => x,
+        })
+        .normalized()
+    }
+
+    /// Filter a policy by eliminating every spending path which needs a
+    /// signature from one of the given keys, e.g. because they were lost.
+    /// The policy can still be satisfied without them iff the result is
+    /// not `Unsatisfiable`.
+    pub fn without_keys(self, pkhs: &[Pk::Hash]) -> Policy<Pk> {
+        self.replace_leaves(&mut |leaf| match leaf {
+            Policy::KeyHash(ref pkh) if pkhs.contains(pkh) => Policy::Unsatisfiable,
+            Policy::Key(ref pk) if pkhs.contains(&pk.to_pubkeyhash()) => Policy::Unsatisfiable,
+            x => x,
+        })
+        .normalized()
+    }
+
+    /// Filter a policy by treating signatures from the given keys as
+    /// available, leaving only what remains to be satisfied. The given keys
+    /// suffice to satisfy the policy iff the result is `Trivial`.
+    pub fn with_keys_signed(self, pkhs: &[Pk::Hash]) -> Policy<Pk> {
+        self.replace_leaves(&mut |leaf| match leaf {
+            Policy::KeyHash(ref pkh) if pkhs.contains(pkh) => Policy::Trivial,
+            Policy::Key(ref pk) if pkhs.contains(&pk.to_pubkeyhash()) => Policy::Trivial,
+            x => x,
+        })
+        .normalized()
+    }
+
+    /// Replace every key by its hash, as `Liftable::lift` does
+    pub fn hash_keys(self) -> Policy<Pk> {
+        self.replace_leaves(&mut |leaf| match leaf {
+            Policy::Key(pk) => Policy::KeyHash(pk.to_pubkeyhash()),
+            x => x,
+        })
+    }
+
     /// Count the number of public keys and keyhashes referenced in a policy.
     /// Duplicate keys will be double-counted.
     pub fn n_keys(&self) -> usize {
         match *self {
             Policy::Unsatisfiable | Policy::Trivial => 0,
-            Policy::KeyHash(..) => 1,
+            Policy::Key(..) | Policy::KeyHash(..) => 1,
             Policy::After(..)
             | Policy::Older(..)
             | Policy::Sha256(..)
@@ -415,7 +546,7 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     pub fn minimum_n_keys(&self) -> usize {
         match *self {
             Policy::Unsatisfiable | Policy::Trivial => 0,
-            Policy::KeyHash(..) => 1,
+            Policy::Key(..) | Policy::KeyHash(..) => 1,
             Policy::After(..)
             | Policy::Older(..)
             | Policy::Sha256(..)
@@ -433,6 +564,694 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 }
 
+/// A restriction on which combinations of conditions may be used together
+/// to satisfy a policy
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum PathConstraint<Pk: MiniscriptKey> {
+    /// No spending path may require signatures from all of these keys
+    NeverTogether(Vec<Pk::Hash>),
+    /// Every spending path requiring a signature from this key must also
+    /// require a relative or absolute timelock
+    RequiresTimelock(Pk::Hash),
+}
+
+impl<Pk: MiniscriptKey> PathConstraint<Pk> {
+    /// Whether a spending path violates the constraint. A `NeverTogether`
+    /// listing no keys constrains nothing, and so is never violated.
+    fn is_violated_by(&self, path: &SpendingPath<Pk>) -> bool {
+        match *self {
+            PathConstraint::NeverTogether(ref pkhs) => {
+                !pkhs.is_empty() && pkhs.iter().all(|pkh| path.key_hashes.contains(pkh))
+            }
+            PathConstraint::RequiresTimelock(ref pkh) => {
+                path.key_hashes.contains(pkh)
+                    && path.relative_timelocks.is_empty()
+                    && path.absolute_timelocks.is_empty()
+            }
+        }
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PathConstraint<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PathConstraint::NeverTogether(ref pkhs) => {
+                f.write_str("never_together(")?;
+                for (i, pkh) in pkhs.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", pkh)?;
+                }
+                f.write_str(")")
+            }
+            PathConstraint::RequiresTimelock(ref pkh) => write!(f, "requires_timelock({})", pkh),
+        }
+    }
+}
+
+/// A spending path of a policy which violates a `PathConstraint`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ConstraintViolation<Pk: MiniscriptKey> {
+    /// The constraint which was violated
+    pub constraint: PathConstraint<Pk>,
+    /// The offending spending path
+    pub path: SpendingPath<Pk>,
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for ConstraintViolation<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "spending path {} violates {}",
+            self.path, self.constraint
+        )
+    }
+}
+
+impl<Pk: MiniscriptKey> error::Error for ConstraintViolation<Pk> {
+    fn cause(&self) -> Option<&error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        ""
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// List every violation of the given constraints by the minimal
+    /// spending paths of the policy (see `minimal_spending_paths`), which
+    /// is empty if the policy satisfies all of them. Fails if the policy
+    /// has more than `max_paths` spending paths to check.
+    pub fn check_constraints(
+        &self,
+        constraints: &[PathConstraint<Pk>],
+        max_paths: usize,
+    ) -> Result<Vec<ConstraintViolation<Pk>>, PolicyError> {
+        let mut ret = vec![];
+        for path in self.minimal_spending_paths(max_paths)? {
+            for constraint in constraints {
+                if constraint.is_violated_by(&path) {
+                    ret.push(ConstraintViolation {
+                        constraint: constraint.clone(),
+                        path: path.clone(),
+                    });
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+/// A minimal set of conditions which together satisfy a policy
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct SpendingPath<Pk: MiniscriptKey> {
+    /// Hashes of the keys which must sign
+    pub key_hashes: Vec<Pk::Hash>,
+    /// SHA256 hashes whose preimages must be revealed
+    pub sha256_preimages: Vec<sha256::Hash>,
+    /// HASH256 hashes whose preimages must be revealed
+    pub hash256_preimages: Vec<sha256d::Hash>,
+    /// RIPEMD160 hashes whose preimages must be revealed
+    pub ripemd160_preimages: Vec<ripemd160::Hash>,
+    /// HASH160 hashes whose preimages must be revealed
+    pub hash160_preimages: Vec<hash160::Hash>,
+    /// Relative timelocks (`older`) which must have expired
+    pub relative_timelocks: Vec<RelLockTime>,
+    /// Absolute timelocks (`after`) which must have expired
+    pub absolute_timelocks: Vec<AbsLockTime>,
+}
+
+impl<Pk: MiniscriptKey> SpendingPath<Pk> {
+    fn from_leaves(leaves: &[&Policy<Pk>]) -> SpendingPath<Pk> {
+        let mut ret = SpendingPath {
+            key_hashes: vec![],
+            sha256_preimages: vec![],
+            hash256_preimages: vec![],
+            ripemd160_preimages: vec![],
+            hash160_preimages: vec![],
+            relative_timelocks: vec![],
+            absolute_timelocks: vec![],
+        };
+        for leaf in leaves {
+            match **leaf {
+                Policy::Key(ref pk) => ret.key_hashes.push(pk.to_pubkeyhash()),
+                Policy::KeyHash(ref pkh) => ret.key_hashes.push(pkh.clone()),
+                Policy::Sha256(h) => ret.sha256_preimages.push(h),
+                Policy::Hash256(h) => ret.hash256_preimages.push(h),
+                Policy::Ripemd160(h) => ret.ripemd160_preimages.push(h),
+                Policy::Hash160(h) => ret.hash160_preimages.push(h),
+                Policy::Older(t) => ret.relative_timelocks.push(t),
+                Policy::After(t) => ret.absolute_timelocks.push(t),
+                _ => unreachable!("spending paths contain only leaves"),
+            }
+        }
+        ret
+    }
+}
+
+impl<Pk: MiniscriptKey> SpendingPath<Pk> {
+    /// The conditions of the spending path as a policy: the conjunction
+    /// of all of them
+    pub fn to_policy(&self) -> Policy<Pk> {
+        let mut leaves: Vec<Policy<Pk>> = vec![];
+        leaves.extend(self.key_hashes.iter().cloned().map(Policy::KeyHash));
+        leaves.extend(self.sha256_preimages.iter().cloned().map(Policy::Sha256));
+        leaves.extend(self.hash256_preimages.iter().cloned().map(Policy::Hash256));
+        leaves.extend(
+            self.ripemd160_preimages
+                .iter()
+                .cloned()
+                .map(Policy::Ripemd160),
+        );
+        leaves.extend(self.hash160_preimages.iter().cloned().map(Policy::Hash160));
+        leaves.extend(self.relative_timelocks.iter().cloned().map(Policy::Older));
+        leaves.extend(self.absolute_timelocks.iter().cloned().map(Policy::After));
+        match leaves.len() {
+            0 => Policy::Trivial,
+            1 => leaves.pop().unwrap(),
+            _ => Policy::And(leaves),
+        }
+    }
+
+    /// Whether this spending path requires at least everything `other`
+    /// does, so that whoever can use it can also use `other`: every key
+    /// and preimage of `other` is required here too, and each of its
+    /// timelocks is implied by one here. A path mixing timelock units can
+    /// never be used, and so implies every other path.
+    pub fn implies(&self, other: &SpendingPath<Pk>) -> bool {
+        fn includes<T: PartialEq>(this: &[T], other: &[T]) -> bool {
+            other.iter().all(|x| this.contains(x))
+        }
+
+        let mixed = self
+            .relative_timelocks
+            .windows(2)
+            .any(|pair| !pair[0].is_same_unit(pair[1]))
+            || self
+                .absolute_timelocks
+                .windows(2)
+                .any(|pair| !pair[0].is_same_unit(pair[1]));
+        mixed
+            || (includes(&self.key_hashes, &other.key_hashes)
+                && includes(&self.sha256_preimages, &other.sha256_preimages)
+                && includes(&self.hash256_preimages, &other.hash256_preimages)
+                && includes(&self.ripemd160_preimages, &other.ripemd160_preimages)
+                && includes(&self.hash160_preimages, &other.hash160_preimages)
+                && other
+                    .relative_timelocks
+                    .iter()
+                    .all(|t| self.relative_timelocks.iter().any(|&u| t.is_implied_by(u)))
+                && other
+                    .absolute_timelocks
+                    .iter()
+                    .all(|t| self.absolute_timelocks.iter().any(|&u| t.is_implied_by(u))))
+    }
+}
+
+impl<Pk: MiniscriptKey> SpendingPath<Pk> {
+    /// Number of signatures needed to use the spending path
+    pub fn n_signatures(&self) -> usize {
+        self.key_hashes.len()
+    }
+
+    /// Number of hash preimages needed to use the spending path
+    pub fn n_preimages(&self) -> usize {
+        self.sha256_preimages.len()
+            + self.hash256_preimages.len()
+            + self.ripemd160_preimages.len()
+            + self.hash160_preimages.len()
+    }
+
+    /// Upper bound on the size of the witness elements which satisfy the
+    /// spending path's conditions: a 73-byte signature and a 33-byte key
+    /// per signer (a semantic policy cannot tell `pk` from `pk_h`, so
+    /// every key is assumed revealed) and a 32-byte preimage per hash,
+    /// each with its push opcode. Dissatisfactions of the other branches
+    /// and the script itself are not included.
+    pub fn max_satisfaction_size(&self) -> usize {
+        self.n_signatures() * (1 + 73 + 1 + 33) + self.n_preimages() * (1 + 32)
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for SpendingPath<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_policy(), f)
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// List the minimal spending paths of the policy: every set of keys,
+    /// hash preimages and timelocks which together satisfy it, and of which
+    /// no proper subset does. Since large thresholds may have a huge number
+    /// of these, the enumeration fails once more than `max_paths` are found,
+    /// counting the combinations of the paths of an `and` or threshold's
+    /// children before those implied by shorter ones are dropped.
+    pub fn minimal_spending_paths(
+        &self,
+        max_paths: usize,
+    ) -> Result<Vec<SpendingPath<Pk>>, PolicyError> {
+        // Hash the keys first, so that a key and its hash are one leaf
+        let hashed = self.clone().hash_keys();
+        let paths = hashed.minimal_paths(max_paths)?;
+        Ok(paths
+            .iter()
+            .map(|path| SpendingPath::from_leaves(path))
+            .collect())
+    }
+
+    /// Helper function to do the recursion in `minimal_spending_paths`.
+    /// Each path is kept sorted and without duplicate leaves.
+    fn minimal_paths(&self, max_paths: usize) -> Result<Vec<Vec<&Policy<Pk>>>, PolicyError> {
+        match *self {
+            Policy::Unsatisfiable => Ok(vec![]),
+            Policy::Trivial => Ok(vec![vec![]]),
+            Policy::And(ref subs) => {
+                let mut ret = vec![vec![]];
+                for sub in subs {
+                    let sub_paths = sub.minimal_paths(max_paths)?;
+                    ret = minimize_paths(cross_paths(&ret, &sub_paths, max_paths)?, max_paths)?;
+                }
+                Ok(ret)
+            }
+            Policy::Or(ref subs) => {
+                let mut ret = vec![];
+                for sub in subs {
+                    ret.extend(sub.minimal_paths(max_paths)?);
+                }
+                minimize_paths(ret, max_paths)
+            }
+            Policy::Threshold(k, ref subs) => {
+                let sub_paths = subs
+                    .iter()
+                    .map(|sub| sub.minimal_paths(max_paths))
+                    .collect::<Result<Vec<_>, _>>()?;
+                minimal_threshold_paths(k, &sub_paths, max_paths)
+            }
+            ref leaf => Ok(vec![vec![leaf]]),
+        }
+    }
+}
+
+/// Sort and deduplicate the leaves of each path, then drop every path
+/// which is a superset of another one
+fn minimize_paths<Pk: MiniscriptKey>(
+    mut paths: Vec<Vec<&Policy<Pk>>>,
+    max_paths: usize,
+) -> Result<Vec<Vec<&Policy<Pk>>>, PolicyError> {
+    for path in &mut paths {
+        path.sort();
+        path.dedup();
+    }
+    // Shorter paths first, so that a path can only be a superset of
+    // those before it
+    paths.sort_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)));
+    paths.dedup();
+    // No transaction can satisfy timelocks of different units at once
+    paths.retain(|path| !mixes_timelock_units(path.iter().cloned()));
+
+    // A kept path is a subset of `path` if all of its leaves are among
+    // those of `path`, so count the leaves each has in common with it
+    let mut ret: Vec<Vec<&Policy<Pk>>> = vec![];
+    let mut containing: BTreeMap<&Policy<Pk>, Vec<usize>> = BTreeMap::new();
+    let mut common: Vec<usize> = vec![];
+    let mut touched = vec![];
+    for path in paths {
+        // The empty path, which comes first, is a subset of every other one
+        let mut redundant = !ret.is_empty() && ret[0].is_empty();
+        for leaf in &path {
+            if let Some(indices) = containing.get(leaf) {
+                for &i in indices {
+                    if common[i] == 0 {
+                        touched.push(i);
+                    }
+                    common[i] += 1;
+                    redundant |= common[i] == ret[i].len();
+                }
+            }
+        }
+        for i in touched.drain(..) {
+            common[i] = 0;
+        }
+        if redundant {
+            continue;
+        }
+        if ret.len() == max_paths {
+            return Err(PolicyError::TooManySpendingPaths(max_paths));
+        }
+        for &leaf in &path {
+            containing.entry(leaf).or_insert(vec![]).push(ret.len());
+        }
+        ret.push(path);
+        common.push(0);
+    }
+    Ok(ret)
+}
+
+/// Minimal spending paths of a `k`-of-`sub_paths.len()` threshold, given
+/// the minimal spending paths of each of its children
+fn minimal_threshold_paths<'a, Pk: MiniscriptKey>(
+    k: usize,
+    sub_paths: &[Vec<Vec<&'a Policy<Pk>>>],
+    max_paths: usize,
+) -> Result<Vec<Vec<&'a Policy<Pk>>>, PolicyError> {
+    if k == 0 {
+        Ok(vec![vec![]])
+    } else if sub_paths.len() < k {
+        Ok(vec![])
+    } else {
+        let mut ret = cross_paths(
+            &sub_paths[0],
+            &minimal_threshold_paths(k - 1, &sub_paths[1..], max_paths)?,
+            max_paths,
+        )?;
+        ret.extend(minimal_threshold_paths(k, &sub_paths[1..], max_paths)?);
+        minimize_paths(ret, max_paths)
+    }
+}
+
+/// The minimal spending paths of a policy, with the signatures,
+/// preimages and timelocks each one needs, indexed by key
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PathTable<Pk: MiniscriptKey> {
+    /// The minimal spending paths, in the order of
+    /// `Policy::minimal_spending_paths`
+    pub paths: Vec<SpendingPath<Pk>>,
+    /// For each key hash, the indices in `paths` of the spending paths
+    /// which need a signature from it
+    pub paths_by_key: BTreeMap<Pk::Hash, Vec<usize>>,
+}
+
+impl<Pk: MiniscriptKey> PathTable<Pk> {
+    /// Spending paths which need a signature from the given key
+    pub fn paths_with_key(&self, pkh: &Pk::Hash) -> Vec<&SpendingPath<Pk>> {
+        match self.paths_by_key.get(pkh) {
+            Some(indices) => indices.iter().map(|&i| &self.paths[i]).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Fewest signatures with which the policy can be satisfied, or `None`
+    /// if it cannot be satisfied at all
+    pub fn min_signatures(&self) -> Option<usize> {
+        self.paths.iter().map(SpendingPath::n_signatures).min()
+    }
+
+    /// Most signatures any minimal spending path needs, or `None` if the
+    /// policy cannot be satisfied at all
+    pub fn max_signatures(&self) -> Option<usize> {
+        self.paths.iter().map(SpendingPath::n_signatures).max()
+    }
+}
+
+impl<Pk: MiniscriptKey> fmt::Display for PathTable<Pk> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "path sigs preimages max_size timelocks")?;
+        for (i, path) in self.paths.iter().enumerate() {
+            let mut timelocks: Vec<String> = vec![];
+            timelocks.extend(
+                path.relative_timelocks
+                    .iter()
+                    .map(|t| format!("older {}", t)),
+            );
+            timelocks.extend(
+                path.absolute_timelocks
+                    .iter()
+                    .map(|t| format!("after {}", t)),
+            );
+            if timelocks.is_empty() {
+                timelocks.push("-".to_owned());
+            }
+            writeln!(
+                f,
+                "{:>4} {:>4} {:>9} {:>8} {}",
+                i,
+                path.n_signatures(),
+                path.n_preimages(),
+                path.max_satisfaction_size(),
+                timelocks.join(", ")
+            )?;
+        }
+        for (pkh, indices) in &self.paths_by_key {
+            let indices: Vec<String> = indices.iter().map(|i| i.to_string()).collect();
+            writeln!(f, "{}: {}", pkh, indices.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Tabulate the minimal spending paths of the policy with what each
+    /// of them needs, e.g. to show a signer which paths they take part
+    /// in. Fails like `minimal_spending_paths` if there are more than
+    /// `max_paths` of them.
+    pub fn path_table(&self, max_paths: usize) -> Result<PathTable<Pk>, PolicyError> {
+        let paths = self.minimal_spending_paths(max_paths)?;
+        let mut paths_by_key = BTreeMap::new();
+        for (i, path) in paths.iter().enumerate() {
+            for pkh in &path.key_hashes {
+                paths_by_key
+                    .entry(pkh.clone())
+                    .or_insert_with(Vec::new)
+                    .push(i);
+            }
+        }
+        Ok(PathTable {
+            paths,
+            paths_by_key,
+        })
+    }
+}
+
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Whether every way of satisfying `self` also satisfies `other`, i.e.
+    /// whether `self` enforces at least as much as `other`. This is exact,
+    /// being checked over a truth table of all the keys, hashes and
+    /// timelocks of both policies, and so fails if that table would have
+    /// more than `MAX_TRUTH_TABLE_ROWS` rows.
+    pub fn implies(&self, other: &Policy<Pk>) -> Result<bool, PolicyError> {
+        let (this, other) = (self.clone().hash_keys(), other.clone().hash_keys());
+        TruthTable::new(&this, &other)?.all(|a, b| !a || b)
+    }
+
+    /// Whether `self` and `other` are satisfied by exactly the same sets of
+    /// keys, hashes and timelocks. See `implies` for the size limit.
+    pub fn is_equivalent(&self, other: &Policy<Pk>) -> Result<bool, PolicyError> {
+        let (this, other) = (self.clone().hash_keys(), other.clone().hash_keys());
+        TruthTable::new(&this, &other)?.all(|a, b| a == b)
+    }
+
+    /// Helper function to evaluate the policy, given which of its leaves
+    /// (as listed in `atoms`) are satisfied
+    fn eval(&self, atoms: &[&Policy<Pk>], truth: &[bool]) -> bool {
+        match *self {
+            Policy::Unsatisfiable => false,
+            Policy::Trivial => true,
+            Policy::And(ref subs) => subs.iter().all(|sub| sub.eval(atoms, truth)),
+            Policy::Or(ref subs) => subs.iter().any(|sub| sub.eval(atoms, truth)),
+            Policy::Threshold(k, ref subs) => {
+                subs.iter().filter(|sub| sub.eval(atoms, truth)).count() >= k
+            }
+            ref leaf => {
+                let idx = atoms.binary_search(&leaf).expect("all leaves are atoms");
+                truth[idx]
+            }
+        }
+    }
+
+    /// Helper function to collect the leaves of the policy
+    fn leaves<'a>(&'a self, leaves: &mut Vec<&'a Policy<Pk>>) {
+        match *self {
+            Policy::Unsatisfiable | Policy::Trivial => {}
+            Policy::And(ref subs) | Policy::Or(ref subs) | Policy::Threshold(_, ref subs) => {
+                for sub in subs {
+                    sub.leaves(leaves);
+                }
+            }
+            ref leaf => leaves.push(leaf),
+        }
+    }
+}
+
+/// Truth table over the leaves of two policies. Keys and hashes are
+/// independent, but timelocks of the same kind are not: if `older(n)` is
+/// satisfied then so is every `older(m)` with `m <= n`, so each kind of
+/// timelock contributes one row per distinct value (plus one) rather than
+/// one per subset. Rows in which timelocks of both units of `older` (or
+/// of `after`) are satisfied are skipped, as no transaction has them.
+struct TruthTable<'a, Pk: MiniscriptKey + 'a> {
+    left: &'a Policy<Pk>,
+    right: &'a Policy<Pk>,
+    /// Sorted leaves of both policies
+    atoms: Vec<&'a Policy<Pk>>,
+    /// Indices into `atoms` of the keys and hashes
+    bools: Vec<usize>,
+    /// Indices into `atoms` of each kind of timelock, sorted by value:
+    /// relative height, relative time, absolute height, absolute time
+    chains: Vec<Vec<usize>>,
+}
+
+impl<'a, Pk: MiniscriptKey> TruthTable<'a, Pk> {
+    fn new(left: &'a Policy<Pk>, right: &'a Policy<Pk>) -> Result<Self, PolicyError> {
+        let mut atoms = vec![];
+        left.leaves(&mut atoms);
+        right.leaves(&mut atoms);
+        atoms.sort();
+        atoms.dedup();
+
+        let mut bools = vec![];
+        let mut chains = vec![vec![], vec![], vec![], vec![]];
+        for (i, atom) in atoms.iter().enumerate() {
+            match **atom {
+                Policy::Older(t) if t.is_height_locked() => chains[0].push(i),
+                Policy::Older(_) => chains[1].push(i),
+                Policy::After(t) if t.is_block_height() => chains[2].push(i),
+                Policy::After(_) => chains[3].push(i),
+                _ => bools.push(i),
+            }
+        }
+        // `atoms` is sorted by encoding, but only the masked value of a
+        // relative timelock counts
+        for chain in &mut chains[..2] {
+            chain.sort_by_key(|&i| match *atoms[i] {
+                Policy::Older(t) => t.value(),
+                _ => unreachable!(),
+            });
+        }
+
+        let mut rows = 1usize;
+        for _ in &bools {
+            rows = rows.saturating_mul(2);
+        }
+        for chain in &chains {
+            rows = rows.saturating_mul(chain.len() + 1);
+        }
+        if rows > MAX_TRUTH_TABLE_ROWS {
+            return Err(PolicyError::TruthTableTooLarge);
+        }
+
+        Ok(TruthTable {
+            left,
+            right,
+            atoms,
+            bools,
+            chains,
+        })
+    }
+
+    /// Whether `check` holds for the values of the two policies in every
+    /// row of the table
+    fn all<F: Fn(bool, bool) -> bool>(&self, check: F) -> Result<bool, PolicyError> {
+        let mut truth = vec![false; self.atoms.len()];
+        // How many of the smallest timelocks of each chain are satisfied
+        let mut levels = vec![0; self.chains.len()];
+        loop {
+            let mixed = (levels[0] > 0 && levels[1] > 0) || (levels[2] > 0 && levels[3] > 0);
+            if !mixed {
+                for bits in 0..(1u64 << self.bools.len()) {
+                    for (j, &i) in self.bools.iter().enumerate() {
+                        truth[i] = bits & (1 << j) != 0;
+                    }
+                    let l = self.left.eval(&self.atoms, &truth);
+                    let r = self.right.eval(&self.atoms, &truth);
+                    if !check(l, r) {
+                        return Ok(false);
+                    }
+                }
+            }
+
+            // Advance to the next combination of timelock levels
+            let mut c = 0;
+            loop {
+                if c == self.chains.len() {
+                    return Ok(true);
+                }
+                if levels[c] < self.chains[c].len() {
+                    truth[self.chains[c][levels[c]]] = true;
+                    levels[c] += 1;
+                    break;
+                }
+                for &i in &self.chains[c] {
+                    truth[i] = false;
+                }
+                levels[c] = 0;
+                c += 1;
+            }
+        }
+    }
+}
+
+/// Whether the given conjuncts include timelocks of different units, e.g.
+/// an `after` block height and an `after` UNIX timestamp, which no single
+/// transaction can satisfy together
+fn mixes_timelock_units<'a, Pk, I>(leaves: I) -> bool
+where
+    Pk: MiniscriptKey + 'a,
+    I: IntoIterator<Item = &'a Policy<Pk>>,
+{
+    let mut relative: Option<RelLockTime> = None;
+    let mut absolute: Option<AbsLockTime> = None;
+    for leaf in leaves {
+        match *leaf {
+            Policy::Older(t) => match relative {
+                Some(r) if !r.is_same_unit(t) => return true,
+                _ => relative = Some(t),
+            },
+            Policy::After(t) => match absolute {
+                Some(a) if !a.is_same_unit(t) => return true,
+                _ => absolute = Some(t),
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Largest number of `subs` which a single transaction could satisfy
+/// together, as far as their timelocks go: of the direct `older` children,
+/// only those of one unit count, and likewise for `after`
+fn max_compatible<Pk: MiniscriptKey>(subs: &[Policy<Pk>]) -> usize {
+    // Relative heights, relative times, absolute heights, absolute times
+    let mut timelocks = [0; 4];
+    let mut others = 0;
+    for sub in subs {
+        match *sub {
+            Policy::Older(t) if t.is_height_locked() => timelocks[0] += 1,
+            Policy::Older(_) => timelocks[1] += 1,
+            Policy::After(t) if t.is_block_height() => timelocks[2] += 1,
+            Policy::After(_) => timelocks[3] += 1,
+            _ => others += 1,
+        }
+    }
+    others + cmp::max(timelocks[0], timelocks[1]) + cmp::max(timelocks[2], timelocks[3])
+}
+
+/// Every combination of one path from `left` with one path from `right`,
+/// except those mixing timelock units. Fails as soon as there are more
+/// than `max_paths` of them, rather than after building the whole product.
+fn cross_paths<'a, Pk: MiniscriptKey>(
+    left: &[Vec<&'a Policy<Pk>>],
+    right: &[Vec<&'a Policy<Pk>>],
+    max_paths: usize,
+) -> Result<Vec<Vec<&'a Policy<Pk>>>, PolicyError> {
+    let mut ret = vec![];
+    for l in left {
+        for r in right {
+            let mut path = l.clone();
+            path.extend(r.iter().cloned());
+            if mixes_timelock_units(path.iter().cloned()) {
+                continue;
+            }
+            if ret.len() == max_paths {
+                return Err(PolicyError::TooManySpendingPaths(max_paths));
+            }
+            ret.push(path);
+        }
+    }
+    Ok(ret)
+}
+
 impl<Pk: MiniscriptKey> Policy<Pk> {
     /// "Sort" a policy to bring it into a canonical form to allow comparisons.
     /// Does **not** allow policies to be compared for functional equivalence;
@@ -460,6 +1279,160 @@ impl<Pk: MiniscriptKey> Policy<Pk> {
     }
 }
 
+impl<Pk: MiniscriptKey> Policy<Pk> {
+    /// Normalize the policy and then repeatedly apply boolean-algebra
+    /// reductions which preserve its meaning, until none applies:
+    /// `thresh(1,..)` and `thresh(n,..)` of `n` children become `or` and
+    /// `and` (and are then flattened into their parents), conjuncts and
+    /// disjuncts already implied by their siblings are removed (absorption,
+    /// e.g. `or(A,and(A,B))` becomes `A`, and `and(older(10),older(20))`
+    /// becomes `older(20)`), and duplicate threshold children are split
+    /// out, e.g. `thresh(2,A,A,B)` becomes `A`. Each distinct duplicate
+    /// doubles the other children, so a threshold whose fully split form
+    /// would exceed `MAX_SIMPLIFIED_SIZE` nodes is left unsplit. Does not
+    /// reorder branches; use `.sorted` for that.
+    pub fn simplified(self) -> Policy<Pk> {
+        let mut policy = self.normalized();
+        loop {
+            let next = policy.clone().simplify_once().normalized();
+            if next == policy {
+                return policy;
+            }
+            policy = next;
+        }
+    }
+
+    /// Helper function to do a single pass of `simplified`
+    fn simplify_once(self) -> Policy<Pk> {
+        match self {
+            Policy::And(subs) => {
+                let subs: Vec<_> = subs.into_iter().map(Policy::simplify_once).collect();
+                // A conjunct implied by another one adds nothing
+                Policy::And(absorb(subs, |strong, weak| {
+                    strong
+                        .disjuncts()
+                        .iter()
+                        .all(|s| weak.disjuncts().iter().any(|w| leaf_implies(s, w)))
+                }))
+            }
+            Policy::Or(subs) => {
+                let subs: Vec<_> = subs.into_iter().map(Policy::simplify_once).collect();
+                // A disjunct which implies another one is never needed
+                Policy::Or(absorb(subs, |weak, strong| {
+                    weak.conjuncts()
+                        .iter()
+                        .all(|w| strong.conjuncts().iter().any(|s| leaf_implies(s, w)))
+                }))
+            }
+            Policy::Threshold(k, subs) => {
+                let mut subs: Vec<_> = subs.into_iter().map(Policy::simplify_once).collect();
+                if k == 1 {
+                    return Policy::Or(subs);
+                } else if k == subs.len() {
+                    return Policy::And(subs);
+                }
+                let dup = subs
+                    .iter()
+                    .enumerate()
+                    .position(|(i, sub)| subs[..i].contains(sub));
+                match dup {
+                    Some(i) if split_size(&subs) <= MAX_SIMPLIFIED_SIZE => {
+                        // Split on the duplicated child: if it is satisfied
+                        // it counts once per copy, otherwise not at all
+                        let dup = subs.remove(i);
+                        let mut copies = 1;
+                        while let Some(j) = subs.iter().position(|sub| *sub == dup) {
+                            subs.remove(j);
+                            copies += 1;
+                        }
+                        let with_dup = if k > copies {
+                            Policy::Threshold(k - copies, subs.clone())
+                        } else {
+                            Policy::Trivial
+                        };
+                        Policy::Or(vec![
+                            Policy::And(vec![dup, with_dup]),
+                            Policy::Threshold(k, subs),
+                        ])
+                    }
+                    _ => Policy::Threshold(k, subs),
+                }
+            }
+            x => x,
+        }
+    }
+
+    /// Helper function to count the nodes of the policy
+    fn n_nodes(&self) -> usize {
+        match *self {
+            Policy::And(ref subs) | Policy::Or(ref subs) | Policy::Threshold(_, ref subs) => {
+                1 + subs.iter().map(Policy::n_nodes).sum::<usize>()
+            }
+            _ => 1,
+        }
+    }
+
+    /// Helper function to view the policy as a list of conjuncts
+    fn conjuncts(&self) -> Vec<&Policy<Pk>> {
+        match *self {
+            Policy::And(ref subs) => subs.iter().collect(),
+            ref x => vec![x],
+        }
+    }
+
+    /// Helper function to view the policy as a list of disjuncts
+    fn disjuncts(&self) -> Vec<&Policy<Pk>> {
+        match *self {
+            Policy::Or(ref subs) => subs.iter().collect(),
+            ref x => vec![x],
+        }
+    }
+}
+
+/// Remove each of `subs` which is `redundant` given one of the others.
+/// Of several equal subs, only the last is kept.
+fn absorb<Pk, F>(subs: Vec<Policy<Pk>>, redundant: F) -> Vec<Policy<Pk>>
+where
+    Pk: MiniscriptKey,
+    F: Fn(&Policy<Pk>, &Policy<Pk>) -> bool,
+{
+    let mut keep = vec![true; subs.len()];
+    for i in 0..subs.len() {
+        keep[i] = !(0..subs.len()).any(|j| j != i && keep[j] && redundant(&subs[j], &subs[i]));
+    }
+    subs.into_iter()
+        .zip(keep)
+        .filter(|pair| pair.1)
+        .map(|pair| pair.0)
+        .collect()
+}
+
+/// Whether satisfying `strong` is known to also satisfy `weak`, comparing
+/// them as opaque leaves except for timelocks of the same unit and keys
+/// against their hashes
+fn leaf_implies<Pk: MiniscriptKey>(strong: &Policy<Pk>, weak: &Policy<Pk>) -> bool {
+    match (strong, weak) {
+        (&Policy::Older(s), &Policy::Older(w)) => w.is_implied_by(s),
+        (&Policy::After(s), &Policy::After(w)) => w.is_implied_by(s),
+        (&Policy::Key(ref pk), &Policy::KeyHash(ref pkh))
+        | (&Policy::KeyHash(ref pkh), &Policy::Key(ref pk)) => pk.to_pubkeyhash() == *pkh,
+        _ => strong == weak,
+    }
+}
+
+/// Upper bound on the number of nodes of a threshold of `subs` once all of
+/// its duplicate children are split out, each distinct duplicate doubling
+/// the other children
+fn split_size<Pk: MiniscriptKey>(subs: &[Policy<Pk>]) -> usize {
+    let n_dups = subs
+        .iter()
+        .enumerate()
+        .filter(|&(i, sub)| !subs[..i].contains(sub) && subs[i + 1..].contains(sub))
+        .count();
+    let size = subs.iter().map(Policy::n_nodes).sum::<usize>() + 1;
+    (0..n_dups).fold(size, |acc, _| acc.saturating_mul(2))
+}
+
 #[cfg(test)]
 mod tests {
     use bitcoin::PublicKey;
@@ -498,8 +1471,11 @@ mod tests {
         assert_eq!(policy.minimum_n_keys(), 1);
 
         let policy = StringPolicy::from_str("older(1000)").unwrap();
-        assert_eq!(policy, Policy::Older(1000));
-        assert_eq!(policy.relative_timelocks(), vec![1000]);
+        assert_eq!(policy, Policy::Older(RelLockTime::from_height(1000)));
+        assert_eq!(
+            policy.relative_timelocks(),
+            vec![RelLockTime::from_height(1000)]
+        );
         assert_eq!(policy.clone().at_age(0), Policy::Unsatisfiable);
         assert_eq!(policy.clone().at_age(999), Policy::Unsatisfiable);
         assert_eq!(policy.clone().at_age(1000), policy.clone());
@@ -510,9 +1486,15 @@ mod tests {
         let policy = StringPolicy::from_str("or(pkh(),older(1000))").unwrap();
         assert_eq!(
             policy,
-            Policy::Or(vec![Policy::KeyHash("".to_owned()), Policy::Older(1000),])
+            Policy::Or(vec![
+                Policy::KeyHash("".to_owned()),
+                Policy::Older(RelLockTime::from_height(1000)),
+            ])
+        );
+        assert_eq!(
+            policy.relative_timelocks(),
+            vec![RelLockTime::from_height(1000)]
         );
-        assert_eq!(policy.relative_timelocks(), vec![1000]);
         assert_eq!(policy.clone().at_age(0), Policy::KeyHash("".to_owned()));
         assert_eq!(policy.clone().at_age(999), Policy::KeyHash("".to_owned()));
         assert_eq!(policy.clone().at_age(1000), policy.clone());
@@ -531,17 +1513,428 @@ mod tests {
             Policy::Threshold(
                 2,
                 vec![
-                    Policy::Older(1000),
-                    Policy::Older(10000),
-                    Policy::Older(1000),
-                    Policy::Older(2000),
-                    Policy::Older(2000),
+                    Policy::Older(RelLockTime::from_height(1000)),
+                    Policy::Older(RelLockTime::from_height(10000)),
+                    Policy::Older(RelLockTime::from_height(1000)),
+                    Policy::Older(RelLockTime::from_height(2000)),
+                    Policy::Older(RelLockTime::from_height(2000)),
                 ]
             )
         );
         assert_eq!(
             policy.relative_timelocks(),
-            vec![1000, 2000, 10000] //sorted and dedup'd
+            vec![
+                RelLockTime::from_height(1000),
+                RelLockTime::from_height(2000),
+                RelLockTime::from_height(10000),
+            ] //sorted and dedup'd
         );
     }
+
+    #[test]
+    fn normalized() {
+        let norm = |s: &str| StringPolicy::from_str(s).unwrap().normalized();
+
+        // An `or` none of whose branches can be satisfied cannot be either
+        assert_eq!(
+            norm("or(UNSATISFIABLE,UNSATISFIABLE)"),
+            Policy::Unsatisfiable
+        );
+        // Nested branches are normalized before being flattened
+        assert_eq!(
+            norm("or(pkh(A),or(and(TRIVIAL,pkh(B)),UNSATISFIABLE))"),
+            StringPolicy::from_str("or(pkh(A),pkh(B))").unwrap()
+        );
+        assert_eq!(norm("or(pkh(A),and(TRIVIAL,TRIVIAL))"), Policy::Trivial);
+
+        // A satisfied branch of a threshold lowers it, an unsatisfiable one
+        // is dropped
+        assert_eq!(
+            norm("thresh(2,pkh(A),TRIVIAL,pkh(B),UNSATISFIABLE)"),
+            StringPolicy::from_str("thresh(1,pkh(A),pkh(B))").unwrap()
+        );
+        assert_eq!(
+            norm("thresh(2,pkh(A),TRIVIAL,UNSATISFIABLE)"),
+            StringPolicy::from_str("pkh(A)").unwrap()
+        );
+        assert_eq!(norm("thresh(2,TRIVIAL,pkh(A),TRIVIAL)"), Policy::Trivial);
+        assert_eq!(
+            norm("thresh(2,pkh(A),UNSATISFIABLE,UNSATISFIABLE)"),
+            Policy::Unsatisfiable
+        );
+        assert_eq!(
+            norm("thresh(2,pkh(A),and(pkh(B),TRIVIAL),pkh(C))"),
+            StringPolicy::from_str("thresh(2,pkh(A),pkh(B),pkh(C))").unwrap()
+        );
+
+        // Timelocks of different units cannot be satisfied together
+        assert_eq!(
+            norm("thresh(2,after(100),after(1600000000))"),
+            Policy::Unsatisfiable
+        );
+        assert_eq!(
+            norm("thresh(3,pkh(A),older(10),older(4194305),older(20))"),
+            StringPolicy::from_str("thresh(3,pkh(A),older(10),older(4194305),older(20))").unwrap()
+        );
+        assert_eq!(
+            norm("thresh(3,pkh(A),older(10),older(4194305),older(4194306))"),
+            StringPolicy::from_str("thresh(3,pkh(A),older(10),older(4194305),older(4194306))")
+                .unwrap()
+        );
+        assert_eq!(
+            norm("thresh(4,pkh(A),older(10),older(4194305),after(100))"),
+            Policy::Unsatisfiable
+        );
+    }
+
+    #[test]
+    fn path_constraints() {
+        let policy =
+            StringPolicy::from_str("or(thresh(2,pkh(A),pkh(B),pkh(C)),and(pkh(D),older(1000)))")
+                .unwrap();
+        let never_ab = PathConstraint::NeverTogether(vec!["A".to_owned(), "B".to_owned()]);
+        let never_ad = PathConstraint::NeverTogether(vec!["A".to_owned(), "D".to_owned()]);
+        let lock_c = PathConstraint::RequiresTimelock("C".to_owned());
+        let lock_d = PathConstraint::RequiresTimelock("D".to_owned());
+        let path = |s: &str| {
+            StringPolicy::from_str(s)
+                .unwrap()
+                .minimal_spending_paths(1)
+                .unwrap()
+                .remove(0)
+        };
+
+        assert_eq!(policy.check_constraints(&[], 10), Ok(vec![]));
+        assert_eq!(
+            policy.check_constraints(&[never_ad.clone(), lock_d], 10),
+            Ok(vec![])
+        );
+
+        let violations = policy.check_constraints(&[never_ab.clone()], 10).unwrap();
+        assert_eq!(
+            violations,
+            vec![ConstraintViolation {
+                constraint: never_ab.clone(),
+                path: path("and(pkh(A),pkh(B))"),
+            }]
+        );
+        assert_eq!(
+            violations[0].to_string(),
+            "spending path and(pkh(A),pkh(B)) violates never_together(A,B)"
+        );
+
+        // Every violating path is reported, for every constraint
+        let violations = policy
+            .check_constraints(&[lock_c.clone(), never_ab.clone()], 10)
+            .unwrap();
+        assert_eq!(
+            violations,
+            vec![
+                ConstraintViolation {
+                    constraint: never_ab.clone(),
+                    path: path("and(pkh(A),pkh(B))"),
+                },
+                ConstraintViolation {
+                    constraint: lock_c.clone(),
+                    path: path("and(pkh(A),pkh(C))"),
+                },
+                ConstraintViolation {
+                    constraint: lock_c.clone(),
+                    path: path("and(pkh(B),pkh(C))"),
+                },
+            ]
+        );
+
+        // A constraint on no keys constrains nothing
+        assert_eq!(
+            policy.check_constraints(&[PathConstraint::NeverTogether(vec![])], 10),
+            Ok(vec![])
+        );
+
+        // Unsatisfiable branches have no spending paths to violate anything
+        let policy = StringPolicy::from_str("or(and(pkh(A),UNSATISFIABLE),pkh(B))").unwrap();
+        assert_eq!(
+            policy.check_constraints(&[PathConstraint::RequiresTimelock("A".to_owned())], 10),
+            Ok(vec![])
+        );
+
+        // Large thresholds have too many paths to enumerate
+        let keys: Vec<String> = (0..20).map(|i| format!("pkh(K{})", i)).collect();
+        let policy = StringPolicy::from_str(&format!("thresh(10,{})", keys.join(","))).unwrap();
+        assert_eq!(
+            policy.check_constraints(&[never_ad], 1000),
+            Err(PolicyError::TooManySpendingPaths(1000))
+        );
+    }
+
+    #[test]
+    fn too_many_spending_paths() {
+        // Each threshold alone has 4950 paths, but the product of the two
+        // must be given up on without building it
+        let thresh = |name: &str| {
+            let keys: Vec<_> = (0..100).map(|i| format!("pkh({}{})", name, i)).collect();
+            format!("thresh(2,{})", keys.join(","))
+        };
+        let s = format!("and({},{})", thresh("A"), thresh("B"));
+        let policy = StringPolicy::from_str(&s).unwrap();
+        let start = ::std::time::Instant::now();
+        assert_eq!(
+            policy.minimal_spending_paths(5000),
+            Err(PolicyError::TooManySpendingPaths(5000))
+        );
+        assert!(start.elapsed() < ::std::time::Duration::from_secs(10));
+    }
+
+    #[test]
+    fn minimal_spending_paths() {
+        let policy = StringPolicy::from_str(
+            "or(and(pkh(A),pkh(B)),or(and(pkh(A),older(12960)),and(pkh(A),and(pkh(B),after(10)))))",
+        )
+        .unwrap();
+        let paths = policy.minimal_spending_paths(10).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].key_hashes, vec!["A".to_owned(), "B".to_owned()]);
+        assert_eq!(paths[0].relative_timelocks, vec![]);
+        assert_eq!(paths[1].key_hashes, vec!["A".to_owned()]);
+        assert_eq!(
+            paths[1].relative_timelocks,
+            vec![RelLockTime::from_height(12960)]
+        );
+        assert_eq!(paths[1].absolute_timelocks, vec![]);
+
+        let policy = StringPolicy::from_str("thresh(2,pkh(A),pkh(B),pkh(C))").unwrap();
+        let paths = policy.minimal_spending_paths(10).unwrap();
+        let keys: Vec<_> = paths.iter().map(|p| p.key_hashes.clone()).collect();
+        assert_eq!(
+            keys,
+            vec![
+                vec!["A".to_owned(), "B".to_owned()],
+                vec!["A".to_owned(), "C".to_owned()],
+                vec!["B".to_owned(), "C".to_owned()],
+            ]
+        );
+        assert_eq!(
+            policy.minimal_spending_paths(2),
+            Err(PolicyError::TooManySpendingPaths(2))
+        );
+
+        assert_eq!(
+            Policy::<String>::Trivial
+                .minimal_spending_paths(1)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(Policy::<String>::Unsatisfiable
+            .minimal_spending_paths(1)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn filter_by_height_and_keys() {
+        let policy = StringPolicy::from_str(
+            "or(thresh(2,pkh(A),pkh(B),pkh(C)),or(and(pkh(D),after(700000)),and(pkh(E),after(1600000000))))",
+        )
+        .unwrap();
+
+        let at_height = policy.clone().at_height(600000);
+        assert_eq!(at_height.n_keys(), 4);
+        assert_eq!(
+            policy.clone().at_height(700000),
+            policy.clone().normalized()
+        );
+        assert_eq!(policy.clone().at_time(1500000000).n_keys(), 4);
+        assert_eq!(
+            policy.clone().at_time(1600000000),
+            policy.clone().normalized()
+        );
+
+        // Losing one key of the 2-of-3 still leaves it spendable
+        let lost = policy.clone().without_keys(&["A".to_owned()]);
+        assert_eq!(
+            lost.to_string(),
+            "or(thresh(2,pkh(B),pkh(C)),and(pkh(D),after(700000)),and(pkh(E),after(1600000000)))"
+        );
+        let lost = policy.clone().without_keys(&[
+            "A".to_owned(),
+            "B".to_owned(),
+            "D".to_owned(),
+            "E".to_owned(),
+        ]);
+        assert!(lost.is_unsatisfiable());
+
+        // Once A has signed, one more of B and C is needed
+        let signed = policy.clone().with_keys_signed(&["A".to_owned()]);
+        assert_eq!(
+            signed.to_string(),
+            "or(thresh(1,pkh(B),pkh(C)),and(pkh(D),after(700000)),and(pkh(E),after(1600000000)))"
+        );
+        assert!(policy
+            .with_keys_signed(&["A".to_owned(), "C".to_owned()])
+            .is_trivial());
+    }
+
+    #[test]
+    fn implication_and_equivalence() {
+        let p = |s: &str| StringPolicy::from_str(s).unwrap();
+
+        // Distributivity: same policy written differently
+        let a = p("and(pkh(A),or(pkh(B),pkh(C)))");
+        let b = p("or(and(pkh(A),pkh(B)),and(pkh(A),pkh(C)))");
+        assert_eq!(a.is_equivalent(&b), Ok(true));
+        assert_eq!(a.implies(&b), Ok(true));
+
+        let two_of_three = p("thresh(2,pkh(A),pkh(B),pkh(C))");
+        let a_and_b = p("and(pkh(A),pkh(B))");
+        assert_eq!(a_and_b.implies(&two_of_three), Ok(true));
+        assert_eq!(two_of_three.implies(&a_and_b), Ok(false));
+        assert_eq!(two_of_three.is_equivalent(&a_and_b), Ok(false));
+
+        // Longer timelocks are stronger
+        let long = p("and(pkh(A),older(2000))");
+        let short = p("and(pkh(A),older(1000))");
+        assert_eq!(long.implies(&short), Ok(true));
+        assert_eq!(short.implies(&long), Ok(false));
+        // ...but only against timelocks of the same kind
+        let by_time = p("and(pkh(A),after(1600000000))");
+        let by_height = p("and(pkh(A),after(600000))");
+        assert_eq!(by_time.implies(&by_height), Ok(false));
+
+        assert_eq!(Policy::<String>::Unsatisfiable.implies(&a), Ok(true));
+        assert_eq!(a.implies(&Policy::Trivial), Ok(true));
+
+        let many_keys =
+            StringPolicy::Threshold(1, (0..21).map(|i| Policy::KeyHash(i.to_string())).collect());
+        assert_eq!(
+            many_keys.is_equivalent(&many_keys),
+            Err(PolicyError::TruthTableTooLarge)
+        );
+    }
+
+    #[test]
+    fn timelock_units() {
+        // 4194305 is a single 512-second interval, not 4194305 blocks
+        let policy = StringPolicy::from_str("or(pkh(A),older(4194305))").unwrap();
+        assert_eq!(
+            policy.relative_timelocks(),
+            vec![RelLockTime::from_512_second_intervals(1)]
+        );
+        assert_eq!(policy.clone().at_age(1000), Policy::KeyHash("A".to_owned()));
+        assert_eq!(policy.clone().at_age(4194305), policy.clone());
+
+        // A single transaction cannot be both height- and time-locked
+        let mixed = StringPolicy::from_str("and(after(700000),after(1600000000))").unwrap();
+        assert_eq!(mixed.normalized(), Policy::Unsatisfiable);
+        let mixed = StringPolicy::from_str("and(older(144),and(pkh(A),older(4194305)))").unwrap();
+        assert_eq!(mixed.clone().normalized(), Policy::Unsatisfiable);
+        assert_eq!(mixed.implies(&Policy::Unsatisfiable), Ok(true));
+        assert_eq!(mixed.minimal_spending_paths(100).unwrap(), vec![]);
+
+        // Either unit on its own is fine
+        let policy =
+            StringPolicy::from_str("thresh(2,pkh(A),after(700000),after(1600000000))").unwrap();
+        let paths = policy.minimal_spending_paths(100).unwrap();
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].to_string(), "and(pkh(A),after(700000))");
+        assert_eq!(paths[1].to_string(), "and(pkh(A),after(1600000000))");
+        let one_of =
+            StringPolicy::from_str("and(pkh(A),or(after(700000),after(1600000000)))").unwrap();
+        assert_eq!(policy.is_equivalent(&one_of), Ok(true));
+    }
+
+    #[test]
+    fn simplification() {
+        let check = |s: &str, expected: &str| {
+            let policy = StringPolicy::from_str(s).unwrap();
+            let simplified = policy.clone().simplified();
+            assert_eq!(simplified.to_string(), expected);
+            assert_eq!(policy.is_equivalent(&simplified), Ok(true));
+        };
+
+        // Absorption
+        check("or(pkh(A),and(pkh(A),pkh(B)))", "pkh(A)");
+        check("and(pkh(A),or(pkh(A),pkh(B)))", "pkh(A)");
+        check(
+            "or(and(pkh(A),older(10)),or(and(pkh(A),older(20)),pkh(B)))",
+            "or(and(pkh(A),older(10)),pkh(B))",
+        );
+        check(
+            "and(older(10),and(pkh(A),older(20)))",
+            "and(pkh(A),older(20))",
+        );
+        check("or(pkh(A),pkh(A))", "pkh(A)");
+        // Different units of timelock do not absorb each other
+        check(
+            "or(older(10),older(4194314))",
+            "or(older(10),older(4194314))",
+        );
+
+        // Nested thresholds which are really `and`s and `or`s
+        check(
+            "thresh(1,pkh(A),thresh(2,pkh(B),pkh(C)),or(pkh(D),pkh(E)))",
+            "or(pkh(A),and(pkh(B),pkh(C)),pkh(D),pkh(E))",
+        );
+        check("thresh(2,pkh(A),thresh(1,pkh(A),pkh(B)))", "pkh(A)");
+
+        // Duplicate threshold children
+        check("thresh(2,pkh(A),pkh(A),pkh(B))", "pkh(A)");
+        check(
+            "thresh(2,pkh(A),pkh(B),pkh(A),pkh(C))",
+            "or(pkh(A),and(pkh(B),pkh(C)))",
+        );
+        check(
+            "thresh(3,pkh(A),pkh(A),pkh(B),pkh(C))",
+            "and(pkh(A),or(pkh(B),pkh(C)))",
+        );
+
+        // Splitting many distinct duplicates would double the policy once
+        // per duplicate, so it is left alone
+        let dups: Vec<_> = (0..20)
+            .map(|i| format!("pkh(K{}),pkh(K{})", i, i))
+            .collect();
+        let s = format!("thresh(20,{})", dups.join(","));
+        let policy = StringPolicy::from_str(&s).unwrap();
+        assert_eq!(policy.clone().simplified(), policy.normalized());
+        // but a few are still split out
+        check(
+            "thresh(3,pkh(A),pkh(A),pkh(B),pkh(B),pkh(C))",
+            "or(and(pkh(A),or(pkh(B),pkh(C))),and(pkh(B),pkh(C)))",
+        );
+
+        // Already simple
+        check(
+            "thresh(2,pkh(A),pkh(B),pkh(C))",
+            "thresh(2,pkh(A),pkh(B),pkh(C))",
+        );
+    }
+
+    #[test]
+    fn path_table() {
+        let policy = StringPolicy::from_str(
+            "or(thresh(2,pkh(A),pkh(B),pkh(C)),and(and(pkh(A),sha256(0000000000000000000000000000000000000000000000000000000000000000)),older(144)))",
+        )
+        .unwrap();
+        let table = policy.path_table(100).unwrap();
+        assert_eq!(table.paths.len(), 4);
+        assert_eq!(table.min_signatures(), Some(1));
+        assert_eq!(table.max_signatures(), Some(2));
+        assert_eq!(table.paths_with_key(&"A".to_owned()).len(), 3);
+        assert_eq!(table.paths_with_key(&"D".to_owned()).len(), 0);
+        assert_eq!(
+            table.to_string(),
+            "path sigs preimages max_size timelocks\n\
+             \x20  0    2         0      216 -\n\
+             \x20  1    2         0      216 -\n\
+             \x20  2    2         0      216 -\n\
+             \x20  3    1         1      141 older 144 blocks\n\
+             A: 0, 1, 3\n\
+             B: 0, 2\n\
+             C: 1, 2\n"
+        );
+
+        let table = StringPolicy::Unsatisfiable.path_table(100).unwrap();
+        assert!(table.paths.is_empty());
+        assert_eq!(table.min_signatures(), None);
+    }
 }
@@ -0,0 +1,298 @@
+// Miniscript
+// Written in 2026 by
+//     agent <agent@local>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the CC0 Public Domain Dedication
+// along with this software.
+// If not, see <http://creativecommons.org/publicdomain/zero/1.0/>.
+//
+
+//! # Timelocks
+//!
+//! Typed arguments of the `older` and `after` fragments. Both are stored
+//! as the `u32` which appears in the script, but interpret it the way
+//! consensus does: `older` (`OP_CHECKSEQUENCEVERIFY`, BIP 68/112) counts
+//! either blocks or 512-second intervals depending on a type flag, and
+//! `after` (`OP_CHECKLOCKTIMEVERIFY`, BIP 65) is either a block height or
+//! a UNIX timestamp depending on which side of `LOCKTIME_THRESHOLD` it is.
+//! A transaction has a single `nLockTime` and each input a single
+//! `nSequence`, so timelocks in different units can never be satisfied
+//! together.
+//!
+
+use std::fmt;
+
+/// Absolute locktimes below this are block heights; those at or above it
+/// are UNIX timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Bit of a relative locktime which, if set, disables it
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// Bit of a relative locktime which, if set, makes it a number of
+/// 512-second intervals rather than a number of blocks
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Bits of a relative locktime which hold its value
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// Argument of an `older` fragment: a relative locktime, checked by
+/// `OP_CHECKSEQUENCEVERIFY` against the `nSequence` of the spending input
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct RelLockTime(u32);
+
+impl RelLockTime {
+    /// Relative locktime from its encoding in script
+    pub fn from_consensus(n: u32) -> RelLockTime {
+        RelLockTime(n)
+    }
+
+    /// Relative locktime of the given number of blocks
+    pub fn from_height(blocks: u16) -> RelLockTime {
+        RelLockTime(blocks as u32)
+    }
+
+    /// Relative locktime of the given number of 512-second intervals
+    pub fn from_512_second_intervals(intervals: u16) -> RelLockTime {
+        RelLockTime(SEQUENCE_LOCKTIME_TYPE_FLAG | intervals as u32)
+    }
+
+    /// Encoding of the relative locktime in script
+    pub fn to_consensus_u32(self) -> u32 {
+        self.0
+    }
+
+    /// Whether the disable flag is set, in which case `OP_CSV` does not
+    /// enforce anything and Miniscript rejects the fragment
+    pub fn is_disabled(self) -> bool {
+        self.0 & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0
+    }
+
+    /// Whether the relative locktime counts blocks
+    pub fn is_height_locked(self) -> bool {
+        self.0 & SEQUENCE_LOCKTIME_TYPE_FLAG == 0
+    }
+
+    /// Whether the relative locktime counts 512-second intervals
+    pub fn is_time_locked(self) -> bool {
+        !self.is_height_locked()
+    }
+
+    /// Number of blocks or 512-second intervals, ignoring the flags and
+    /// the bits which have no consensus meaning
+    pub fn value(self) -> u16 {
+        (self.0 & SEQUENCE_LOCKTIME_MASK) as u16
+    }
+
+    /// Whether both relative locktimes are in the same unit, so that a
+    /// single `nSequence` can satisfy both of them
+    pub fn is_same_unit(self, other: RelLockTime) -> bool {
+        self.is_height_locked() == other.is_height_locked()
+    }
+
+    /// Whether any `nSequence` which satisfies `other` also satisfies `self`
+    pub fn is_implied_by(self, other: RelLockTime) -> bool {
+        self.is_same_unit(other) && self.value() <= other.value()
+    }
+
+    /// Whether an input with the given `nSequence` satisfies `OP_CSV` with
+    /// this relative locktime
+    pub fn is_satisfied_by(self, n_sequence: u32) -> bool {
+        n_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0
+            && self.is_implied_by(RelLockTime(n_sequence))
+    }
+}
+
+impl fmt::Display for RelLockTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_disabled() {
+            write!(f, "disabled relative locktime {:#x}", self.0)
+        } else if self.is_height_locked() {
+            match self.value() {
+                1 => f.write_str("1 block"),
+                n => write!(f, "{} blocks", n),
+            }
+        } else {
+            fmt_duration(f, self.value() as u32 * 512)
+        }
+    }
+}
+
+/// Argument of an `after` fragment: an absolute locktime, checked by
+/// `OP_CHECKLOCKTIMEVERIFY` against the `nLockTime` of the spending
+/// transaction
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct AbsLockTime(u32);
+
+impl AbsLockTime {
+    /// Absolute locktime from its encoding in script
+    pub fn from_consensus(n: u32) -> AbsLockTime {
+        AbsLockTime(n)
+    }
+
+    /// Encoding of the absolute locktime in script
+    pub fn to_consensus_u32(self) -> u32 {
+        self.0
+    }
+
+    /// Whether the absolute locktime is a block height
+    pub fn is_block_height(self) -> bool {
+        self.0 < LOCKTIME_THRESHOLD
+    }
+
+    /// Whether the absolute locktime is a UNIX timestamp
+    pub fn is_block_time(self) -> bool {
+        !self.is_block_height()
+    }
+
+    /// Whether both absolute locktimes are in the same unit, so that a
+    /// single `nLockTime` can satisfy both of them
+    pub fn is_same_unit(self, other: AbsLockTime) -> bool {
+        self.is_block_height() == other.is_block_height()
+    }
+
+    /// Whether any `nLockTime` which satisfies `other` also satisfies `self`
+    pub fn is_implied_by(self, other: AbsLockTime) -> bool {
+        self.is_same_unit(other) && self.0 <= other.0
+    }
+
+    /// Whether a transaction with the given `nLockTime` satisfies `OP_CLTV`
+    /// with this absolute locktime
+    pub fn is_satisfied_by(self, n_lock_time: u32) -> bool {
+        self.is_implied_by(AbsLockTime(n_lock_time))
+    }
+}
+
+impl fmt::Display for AbsLockTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_block_height() {
+            write!(f, "block {}", self.0)
+        } else {
+            let days = self.0 / 86_400;
+            let secs = self.0 % 86_400;
+            let (year, month, day) = civil_from_days(days);
+            write!(
+                f,
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+                year,
+                month,
+                day,
+                secs / 3600,
+                secs / 60 % 60,
+                secs % 60
+            )
+        }
+    }
+}
+
+/// Write a number of seconds as days, hours, minutes and seconds,
+/// omitting the zero ones
+fn fmt_duration(f: &mut fmt::Formatter, secs: u32) -> fmt::Result {
+    if secs == 0 {
+        return f.write_str("0s");
+    }
+    let parts = [
+        (secs / 86_400, "d"),
+        (secs / 3600 % 24, "h"),
+        (secs / 60 % 60, "m"),
+        (secs % 60, "s"),
+    ];
+    let mut first = true;
+    for &(n, unit) in &parts {
+        if n != 0 {
+            if !first {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}{}", n, unit)?;
+            first = false;
+        }
+    }
+    Ok(())
+}
+
+/// Year, month and day of the given number of days since 1970-01-01,
+/// in the proleptic Gregorian calendar
+fn civil_from_days(days: u32) -> (u32, u32, u32) {
+    // Shift the epoch to 0000-03-01, so that leap days end each cycle
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_units() {
+        let blocks = RelLockTime::from_consensus(144);
+        assert!(blocks.is_height_locked());
+        assert_eq!(blocks.value(), 144);
+        assert_eq!(blocks.to_string(), "144 blocks");
+        assert_eq!(RelLockTime::from_height(1).to_string(), "1 block");
+
+        // 4194305 = 1 << 22 | 1, a single 512-second interval
+        let time = RelLockTime::from_consensus(4194305);
+        assert_eq!(time, RelLockTime::from_512_second_intervals(1));
+        assert!(time.is_time_locked());
+        assert_eq!(time.value(), 1);
+        assert_eq!(time.to_string(), "8m 32s");
+        assert_eq!(
+            RelLockTime::from_512_second_intervals(675).to_string(),
+            "4d"
+        );
+        assert_eq!(
+            RelLockTime::from_512_second_intervals(1000).to_string(),
+            "5d 22h 13m 20s"
+        );
+
+        assert!(!time.is_implied_by(blocks));
+        assert!(!blocks.is_implied_by(time));
+        assert!(RelLockTime::from_height(100).is_implied_by(blocks));
+        assert!(!blocks.is_implied_by(RelLockTime::from_height(100)));
+
+        assert!(blocks.is_satisfied_by(144));
+        assert!(!blocks.is_satisfied_by(143));
+        assert!(!blocks.is_satisfied_by(SEQUENCE_LOCKTIME_TYPE_FLAG | 144));
+        assert!(!blocks.is_satisfied_by(SEQUENCE_LOCKTIME_DISABLE_FLAG | 144));
+        assert!(time.is_satisfied_by(SEQUENCE_LOCKTIME_TYPE_FLAG | 2));
+    }
+
+    #[test]
+    fn absolute_units() {
+        let height = AbsLockTime::from_consensus(499_999_999);
+        assert!(height.is_block_height());
+        assert_eq!(height.to_string(), "block 499999999");
+
+        let time = AbsLockTime::from_consensus(LOCKTIME_THRESHOLD);
+        assert!(time.is_block_time());
+        assert_eq!(time.to_string(), "1985-11-05 00:53:20 UTC");
+        assert_eq!(
+            AbsLockTime::from_consensus(1231488000).to_string(),
+            "2009-01-09 08:00:00 UTC"
+        );
+        assert_eq!(
+            AbsLockTime::from_consensus(1582934400).to_string(),
+            "2020-02-29 00:00:00 UTC"
+        );
+
+        assert!(!height.is_implied_by(time));
+        assert!(!time.is_implied_by(height));
+        assert!(time.is_satisfied_by(1231488000));
+        assert!(!height.is_satisfied_by(1231488000));
+        assert!(height.is_satisfied_by(499_999_999));
+        assert!(!height.is_satisfied_by(499_999_998));
+    }
+}